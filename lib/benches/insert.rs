@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+
+/// Generate deposits for a set of clients, grouped into runs of consecutive transactions.
+fn transactions(count: u32) -> Vec<(u16, u32, Amount)> {
+    (0..count)
+        .map(|tx| ((tx / 8 % 1000) as u16, tx, Amount(10000)))
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let txs = transactions(100_000);
+    let mut group = c.benchmark_group("insert");
+
+    group.bench_function("insert_transaction", |b| {
+        b.iter_batched(
+            Ledger::default,
+            |mut ledger| {
+                for &(client, tx, amount) in black_box(&txs) {
+                    ledger.insert_transaction(client, tx, amount);
+                }
+                ledger
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("insert_batch", |b| {
+        b.iter_batched(
+            Ledger::default,
+            |mut ledger| {
+                ledger.insert_batch(black_box(&txs));
+                ledger
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, insert);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use csv_ledger_lib::ledger::Ledger;
+use std::{fmt::Write, fs, path::PathBuf};
+use tempfile::{tempdir, TempDir};
+
+/// The number of transactions in each generated file.
+const ROWS: u32 = 200_000;
+
+/// Write `count` csv files of deposits, each with its own range of transaction ids.
+fn files(count: u32) -> (TempDir, Vec<PathBuf>) {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let paths = (0..count)
+        .map(|file| {
+            let mut input = String::from("type,client,tx,amount\n");
+            for tx in file * ROWS..(file + 1) * ROWS {
+                writeln!(input, "deposit,{},{tx},1.0", tx % 1000).unwrap();
+            }
+
+            let path = dir.path().join(format!("{file}.csv"));
+            fs::write(&path, input).expect("Unable to write file");
+            path
+        })
+        .collect();
+
+    (dir, paths)
+}
+
+fn parallel(c: &mut Criterion) {
+    let (_dir, paths) = files(4);
+    let mut group = c.benchmark_group("consume_4_files");
+    group.sample_size(10);
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let mut ledger = Ledger::default();
+            for path in &paths {
+                ledger
+                    .merge(Ledger::try_from(path.as_path()).unwrap())
+                    .unwrap();
+            }
+            ledger
+        })
+    });
+
+    group.bench_function("consume_csv_parallel", |b| {
+        b.iter(|| Ledger::consume_csv_parallel(&paths).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parallel);
+criterion_main!(benches);
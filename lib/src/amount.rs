@@ -0,0 +1,230 @@
+//! # Amount
+//!  A fixed-point monetary value, stored as an `i64` scaled by 10000.
+//!
+//! **Basic example:**
+//! ```rust
+//! use csv_ledger_lib::amount::Amount;
+//!
+//! fn main() {
+//!     // 1.5 is stored as 15000
+//!     let amount = Amount::from_major_minor(1, 5000);
+//!     assert_eq!(amount, Amount(15000));
+//!
+//!     // Arithmetic is checked to prevent overflows
+//!     let total = amount.checked_add(Amount(5000)).unwrap();
+//!     assert_eq!(total.as_decimal_string(), "2.0000");
+//! }
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    ops::Neg,
+};
+
+/// The number of minor units in a single major unit (four decimal places).
+pub const SCALE: i64 = 10000;
+
+/// The number of decimal places stored by an `Amount`.
+pub const PRECISION: u32 = 4;
+
+/// A fixed-point monetary value with four decimal places.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(pub i64);
+
+impl Amount {
+    /// Construct an amount from its major (whole) and minor (ten-thousandths) parts.
+    /// The sign of the major part is applied to the minor part.
+    pub fn from_major_minor(major: i64, minor: i64) -> Self {
+        if major < 0 {
+            Amount(major * SCALE - minor)
+        } else {
+            Amount(major * SCALE + minor)
+        }
+    }
+
+    /// Format the amount as a string with four decimal places.
+    pub fn as_decimal_string(&self) -> String {
+        dp_string(self.0)
+    }
+
+    /// Format the amount as a string with the given number of decimal places, up to four.
+    /// Any further decimal places are truncated.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::amount::Amount;
+    ///
+    /// assert_eq!(Amount(15000).as_decimal_string_with_precision(2), "1.50");
+    /// assert_eq!(Amount(-15000).as_decimal_string_with_precision(0), "-1");
+    /// ```
+    pub fn as_decimal_string_with_precision(&self, precision: u32) -> String {
+        let precision = precision.min(PRECISION);
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let value = self.0.unsigned_abs() / 10_u64.pow(PRECISION - precision);
+        let scale = 10_u64.pow(precision);
+
+        match precision {
+            0 => format!("{sign}{value}"),
+            _ => format!(
+                "{sign}{}.{:0width$}",
+                value / scale,
+                value % scale,
+                width = precision as usize
+            ),
+        }
+    }
+
+    /// Add two amounts, returning `None` on overflow.
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    /// Subtract two amounts, returning `None` on overflow.
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Self::Output {
+        Amount(-self.0)
+    }
+}
+
+impl Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_decimal_string())
+    }
+}
+
+/// Convert a i64 to a string with four decimal places (eg val / 100)
+pub fn dp_string(amount: i64) -> String {
+    dp_string_with_precision(amount, PRECISION)
+}
+
+/// Convert an i64 scaled by `10^precision` to a string with `precision` decimal places.
+/// The precision is capped at 18, the most that an i64 can represent.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::amount::dp_string_with_precision;
+///
+/// assert_eq!(dp_string_with_precision(150, 2), "1.50");
+/// assert_eq!(dp_string_with_precision(12345678, 8), "0.12345678");
+/// assert_eq!(dp_string_with_precision(7, 0), "7");
+/// ```
+pub fn dp_string_with_precision(amount: i64, precision: u32) -> String {
+    let precision = precision.min(18);
+    let divisor = 10_u64.pow(precision);
+
+    // Format the magnitude, so that the sign is not repeated in the fractional part
+    let sign = if amount < 0 { "-" } else { "" };
+    let value = amount.unsigned_abs();
+
+    match precision {
+        0 => amount.to_string(),
+        _ => format!(
+            "{sign}{}.{:0width$}",
+            value / divisor,
+            value % divisor,
+            width = precision as usize
+        ),
+    }
+}
+
+#[cfg(test)]
+mod dp_string {
+    use super::dp_string;
+    #[test]
+    fn test_dp_string() {
+        assert_eq!(dp_string(0), "0.0000");
+        assert_eq!(dp_string(1), "0.0001");
+        assert_eq!(dp_string(10), "0.0010");
+        assert_eq!(dp_string(100), "0.0100");
+        assert_eq!(dp_string(1000), "0.1000");
+        assert_eq!(dp_string(10000), "1.0000");
+    }
+
+    #[test]
+    fn negative() {
+        assert_eq!(dp_string(-1), "-0.0001");
+        assert_eq!(dp_string(-10000), "-1.0000");
+        assert_eq!(dp_string(-12345), "-1.2345");
+        assert_eq!(dp_string(i64::MIN), "-922337203685477.5808");
+    }
+
+    #[test]
+    fn dp_string_with_precision() {
+        use super::dp_string_with_precision;
+
+        assert_eq!(dp_string_with_precision(0, 2), "0.00");
+        assert_eq!(dp_string_with_precision(5, 2), "0.05");
+        assert_eq!(dp_string_with_precision(12345, 4), "1.2345");
+        assert_eq!(dp_string_with_precision(123456789, 8), "1.23456789");
+        assert_eq!(dp_string_with_precision(42, 0), "42");
+        assert_eq!(
+            dp_string_with_precision(i64::MAX, 18),
+            "9.223372036854775807"
+        );
+        assert_eq!(dp_string_with_precision(-5, 2), "-0.05");
+        assert_eq!(dp_string_with_precision(-5, 0), "-5");
+        assert_eq!(
+            dp_string_with_precision(1, 30),
+            dp_string_with_precision(1, 18)
+        );
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod amount {
+    use super::Amount;
+
+    #[test]
+    fn from_major_minor() {
+        assert_eq!(Amount::from_major_minor(0, 1), Amount(1));
+        assert_eq!(Amount::from_major_minor(1, 0), Amount(10000));
+        assert_eq!(Amount::from_major_minor(12, 3400), Amount(123400));
+        assert_eq!(Amount::from_major_minor(-1, 5000), Amount(-15000));
+    }
+
+    #[test]
+    fn as_decimal_string() {
+        assert_eq!(Amount(31000).as_decimal_string(), "3.1000");
+        assert_eq!(Amount(31000).to_string(), "3.1000");
+    }
+
+    #[test]
+    fn as_decimal_string_with_precision() {
+        assert_eq!(Amount(12345).as_decimal_string_with_precision(4), "1.2345");
+        assert_eq!(Amount(12345).as_decimal_string_with_precision(2), "1.23");
+        assert_eq!(Amount(500).as_decimal_string_with_precision(2), "0.05");
+        assert_eq!(Amount(-500).as_decimal_string_with_precision(2), "-0.05");
+        assert_eq!(Amount(12345).as_decimal_string_with_precision(0), "1");
+        assert_eq!(Amount(12345).as_decimal_string_with_precision(8), "1.2345");
+        assert_eq!(
+            Amount(i64::MIN).as_decimal_string_with_precision(4),
+            "-922337203685477.5808"
+        );
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(Amount(1).checked_add(Amount(2)), Some(Amount(3)));
+        assert_eq!(Amount(1).checked_sub(Amount(2)), Some(Amount(-1)));
+        assert_eq!(Amount(i64::MAX).checked_add(Amount(1)), None);
+        assert_eq!(Amount(i64::MIN).checked_sub(Amount(1)), None);
+    }
+
+    #[test]
+    fn neg() {
+        assert_eq!(-Amount(10), Amount(-10));
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!(format!("{:?}", Amount(10)), "Amount(10)");
+    }
+}
@@ -0,0 +1,143 @@
+//! # Audit
+//!  Per-client summaries of a ledger, produced by `Ledger::generate_audit_report`.
+//!
+//! **Basic example:**
+//! ```rust
+//! use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+//!
+//! fn main() {
+//!     let mut ledger = Ledger::default();
+//!     ledger.insert_transaction(1, 1, Amount(20000));
+//!     ledger.insert_transaction(1, 2, -Amount(5000));
+//!     ledger.hold(1, 2);
+//!
+//!     let report = ledger.generate_audit_report();
+//!     assert_eq!(report.clients[0].dispute_count, 1);
+//!     assert_eq!(
+//!         report.to_string(),
+//!         "client | available |   held |  total | locked | deposited | withdrawn | disputes | chargebacks\n\
+//!          -------+-----------+--------+--------+--------+-----------+-----------+----------+------------\n     \
+//!               1 |    1.5000 | 0.5000 | 2.0000 |  false |    2.0000 |    0.5000 |        1 |           0"
+//!     );
+//! }
+//! ```
+
+use crate::amount::Amount;
+use std::fmt::{self, Display};
+
+/// The column names of the audit report table.
+const HEADER: [&str; 9] = [
+    "client",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "deposited",
+    "withdrawn",
+    "disputes",
+    "chargebacks",
+];
+
+/// A summary of a single client account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientAudit {
+    pub client_id: u16,
+    pub final_available: Amount,
+    pub final_held: Amount,
+    pub final_total: Amount,
+    pub is_locked: bool,
+    pub total_deposited: Amount,
+    pub total_withdrawn: Amount,
+    pub chargeback_count: u32,
+    pub dispute_count: u32,
+}
+
+impl ClientAudit {
+    /// The fields of a row of the audit report table.
+    fn fields(&self) -> [String; 9] {
+        [
+            self.client_id.to_string(),
+            self.final_available.to_string(),
+            self.final_held.to_string(),
+            self.final_total.to_string(),
+            self.is_locked.to_string(),
+            self.total_deposited.to_string(),
+            self.total_withdrawn.to_string(),
+            self.dispute_count.to_string(),
+            self.chargeback_count.to_string(),
+        ]
+    }
+}
+
+/// A summary of every client account in a ledger, sorted by client id.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    pub clients: Vec<ClientAudit>,
+}
+
+/// Outputs the report as a table, with every column right-aligned to its widest value.
+impl Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<[String; 9]> = self.clients.iter().map(ClientAudit::fields).collect();
+        let widths: [usize; 9] = std::array::from_fn(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .fold(HEADER[i].len(), usize::max)
+        });
+
+        let line = |cells: &mut dyn Iterator<Item = &str>| {
+            cells
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:>width$}"))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+
+        write!(f, "{}", line(&mut HEADER.into_iter()))?;
+        write!(f, "\n{}", widths.map(|width| "-".repeat(width)).join("-+-"))?;
+        for row in &rows {
+            write!(f, "\n{}", line(&mut row.iter().map(String::as_str)))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod audit_report {
+    use super::{AuditReport, ClientAudit};
+    use crate::amount::Amount;
+
+    #[test]
+    fn display_empty() {
+        assert_eq!(
+            AuditReport::default().to_string(),
+            "client | available | held | total | locked | deposited | withdrawn | disputes | chargebacks\n\
+             -------+-----------+------+-------+--------+-----------+-----------+----------+------------"
+        );
+    }
+
+    #[test]
+    fn display_widens_columns() {
+        let report = AuditReport {
+            clients: vec![ClientAudit {
+                client_id: 65535,
+                final_available: Amount(123456780000),
+                final_held: Amount(0),
+                final_total: Amount(123456780000),
+                is_locked: true,
+                total_deposited: Amount(123456780000),
+                total_withdrawn: Amount(0),
+                chargeback_count: 2,
+                dispute_count: 3,
+            }],
+        };
+
+        assert_eq!(
+            report.to_string().lines().last(),
+            Some(
+                " 65535 | 12345678.0000 | 0.0000 | 12345678.0000 |   true | 12345678.0000 |    0.0000 |        3 |           2"
+            )
+        );
+    }
+}
@@ -27,420 +27,6730 @@
 //! ```
 
 use crate::{
-    parse::{parse_header, parse_transaction, Transaction},
-    LedgerErr,
+    amount::{dp_string, Amount, PRECISION},
+    audit::{AuditReport, ClientAudit},
+    parse::{
+        fields, parse_currency, parse_header_with_options, parse_timestamp,
+        parse_transaction_with_layout, ColumnLayout, HeaderSchema, ParseOptions, Transaction, Trim,
+        MAX_CURRENCY_LEN,
+    },
+    LedgerErr, ParseKind,
 };
 use std::{
-    collections::{BTreeMap, HashMap},
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{self, Display},
-    io::{BufRead, BufReader, Read},
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "persist")]
+use std::io::BufWriter;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+#[cfg(feature = "json-input")]
+use {
+    crate::parse::{fixed_point, timestamp},
+    nom::combinator::all_consuming,
+    serde::Deserialize,
+    serde_json::Value,
+};
+
+/// The header row of the account statements output.
+const HEADER: &str = "client, available, held, total, locked";
+
 // The state store used for the `csv_ledger` CLI.
-#[derive(Default, Debug)]
+#[derive(Default)]
 pub struct Ledger {
     /// The list of client accounts.
     pub clients: HashMap<u16, ClientData>,
     /// The list of transactions. Note: This is a nieve implementation of transaction storage,
-    /// requiring all transactions to be stored in memory. Due to there being no maximum limmit to
-    /// how old a transaction can be for a `hold` to be applied, all transactions must be addressable.
-    pub transactions: BTreeMap<u32, i64>,
+    /// requiring all transactions to be stored in memory. Unless `LedgerOptions::dispute_window` is set,
+    /// there is no maximum limmit to how old a transaction can be for a `hold` to be applied, so all
+    /// transactions must be addressable.
+    /// Each transaction is stored alongside the id of the client that owns it, and the line of
+    /// the csv file it was read from, if any.
+    pub transactions: BTreeMap<u32, (u16, Amount, Option<usize>)>,
+    /// Every applied transaction alongside its line, when `LedgerOptions::event_log` is set.
+    event_log: Vec<(usize, Transaction)>,
+    /// The time of each transaction read with a timestamp, in seconds since the Unix epoch.
+    timestamps: HashMap<u32, i64>,
+}
+
+/// Options used to configure how a `Ledger` consumes a csv file.
+#[derive(Debug, Clone)]
+pub struct LedgerOptions {
+    /// The maximum number of transaction rows to process. By default, there is no limit.
+    pub max_rows: Option<usize>,
+    /// Return `LedgerErr::RowLimitExceeded` when the row limit is reached, rather than
+    /// stopping early and leaving the ledger in a partial state.
+    pub fail_on_row_limit: bool,
+    /// The maximum number of transactions to apply. Once exceeded, consuming stops and
+    /// `LedgerErr::TransactionLimitExceeded` is returned. Operations that are ignored, such as
+    /// a dispute of an unknown transaction, do not count towards the limit. By default, there
+    /// is no limit.
+    pub max_transactions: Option<usize>,
+    /// Options passed through to the csv parser.
+    pub parse: ParseOptions,
+    /// The number of lines between each invocation of a progress callback.
+    pub progress_interval: u64,
+    /// Treat invalid operations, such as a chargeback without a dispute, as errors
+    /// rather than silently ignoring them.
+    pub strict: bool,
+    /// The maximum length of a single line in bytes, excluding its line ending.
+    /// Protects against unbounded memory use when reading untrusted files.
+    pub max_line_length: usize,
+    /// The maximum number of lines between a transaction and a dispute of it. Disputes of older
+    /// transactions are ignored, or rejected in strict mode. Transactions that were not read from
+    /// the same csv file cannot be measured, and are always disputable. By default, there is no limit.
+    pub dispute_window: Option<usize>,
+    /// The maximum number of seconds between a transaction and a dispute of it, when both were
+    /// read with a timestamp. Disputes of older transactions are ignored, or rejected in strict
+    /// mode. Transactions or disputes without a timestamp are always disputable under this limit.
+    /// By default, there is no limit.
+    pub dispute_window_seconds: Option<u64>,
+    /// Client ids that must never be transacted against, such as a sentinel for the system.
+    /// Any transaction for a reserved client returns `LedgerErr::InvalidOperation`.
+    /// By default, no ids are reserved.
+    pub reserved_clients: Vec<u16>,
+    /// Record every applied transaction and its line, in order, in `Ledger::event_log`.
+    pub event_log: bool,
+    /// Return `LedgerErr::WrongClient` when a dispute, resolve or chargeback names a different
+    /// client to the one that owns the transaction. By default, the operation has no effect and a
+    /// warning is logged with the `log` feature.
+    pub strict_client_matching: bool,
+    /// The number of transaction rows to skip, without parsing them, before applying any.
+    /// Allows consuming to resume part way through a file. Skipped rows do not count towards
+    /// the row or transaction limits. By default, no rows are skipped.
+    pub skip_rows: usize,
+}
+
+impl Default for LedgerOptions {
+    fn default() -> Self {
+        LedgerOptions {
+            max_rows: None,
+            fail_on_row_limit: false,
+            max_transactions: None,
+            parse: ParseOptions::default(),
+            progress_interval: 10_000,
+            strict: false,
+            max_line_length: 1024 * 1024,
+            dispute_window: None,
+            dispute_window_seconds: None,
+            reserved_clients: Vec::new(),
+            event_log: false,
+            strict_client_matching: false,
+            skip_rows: 0,
+        }
+    }
+}
+
+impl LedgerOptions {
+    /// Set the maximum number of transaction rows to process.
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Set whether reaching the row limit should return an error.
+    pub fn with_fail_on_row_limit(mut self, fail_on_row_limit: bool) -> Self {
+        self.fail_on_row_limit = fail_on_row_limit;
+        self
+    }
+
+    /// Set the maximum number of transactions to apply.
+    pub fn with_max_transactions(mut self, max_transactions: usize) -> Self {
+        self.max_transactions = Some(max_transactions);
+        self
+    }
+
+    /// Set whether unknown columns should be ignored.
+    pub fn with_allow_extra_columns(mut self, allow_extra_columns: bool) -> Self {
+        self.parse.allow_extra_columns = allow_extra_columns;
+        self
+    }
+
+    /// Set the maximum number of decimal places accepted in an amount.
+    pub fn with_max_decimal_places(mut self, max_decimal_places: usize) -> Self {
+        self.parse.max_decimal_places = max_decimal_places;
+        self
+    }
+
+    /// Set whether fields may be wrapped in double quotes.
+    /// Quoted fields may span multiple lines.
+    pub fn with_quoting(mut self, quoting: bool) -> Self {
+        self.parse.quoting = quoting;
+        self
+    }
+
+    /// Set the character separating the fields of each line, including the header.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.parse.delimiter = delimiter;
+        self
+    }
+
+    /// Set the character separating the whole and fractional parts of an amount.
+    pub fn with_decimal_separator(mut self, decimal_separator: char) -> Self {
+        self.parse.decimal_separator = decimal_separator;
+        self
+    }
+
+    /// Set how whitespace around each field is treated.
+    pub fn with_trim(mut self, trim: Trim) -> Self {
+        self.parse.trim = trim;
+        self
+    }
+
+    /// Set whether ids and amounts written with a leading zero, such as `007`, are rejected.
+    pub fn with_reject_leading_zeros(mut self, reject_leading_zeros: bool) -> Self {
+        self.parse.reject_leading_zeros = reject_leading_zeros;
+        self
+    }
+
+    /// Set whether to read a timestamp column, which is the fifth column of files without a header.
+    pub fn with_timestamp(mut self, timestamp: bool) -> Self {
+        self.parse.timestamp = timestamp;
+        self
+    }
+
+    /// Set the number of lines between each invocation of a progress callback.
+    pub fn with_progress_interval(mut self, progress_interval: u64) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
+    /// Set whether invalid operations should return an error.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the maximum length of a single line in bytes.
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Set the maximum number of lines between a transaction and a dispute of it.
+    pub fn with_dispute_window(mut self, dispute_window: usize) -> Self {
+        self.dispute_window = Some(dispute_window);
+        self
+    }
+
+    /// Set the maximum number of seconds between a transaction and a dispute of it.
+    pub fn with_dispute_window_seconds(mut self, dispute_window_seconds: u64) -> Self {
+        self.dispute_window_seconds = Some(dispute_window_seconds);
+        self
+    }
+
+    /// Set the client ids that must never be transacted against.
+    pub fn with_reserved_clients(mut self, reserved_clients: Vec<u16>) -> Self {
+        self.reserved_clients = reserved_clients;
+        self
+    }
+
+    /// Set whether every applied transaction should be recorded in the event log.
+    pub fn with_event_log(mut self, event_log: bool) -> Self {
+        self.event_log = event_log;
+        self
+    }
+
+    /// Set whether a dispute, resolve or chargeback for the wrong client should return an error.
+    pub fn with_strict_client_matching(mut self, strict_client_matching: bool) -> Self {
+        self.strict_client_matching = strict_client_matching;
+        self
+    }
+
+    /// Set the number of transaction rows to skip before applying any.
+    pub fn with_skip_rows(mut self, skip_rows: usize) -> Self {
+        self.skip_rows = skip_rows;
+        self
+    }
+}
+
+/// Options used to configure how a `Ledger` outputs its account statements.
+#[derive(Default, Debug, Clone)]
+pub struct OutputOptions {
+    /// Skip writing the header row.
+    pub omit_header: bool,
+    /// Only output these clients, in the order provided. By default, all clients are output.
+    pub filter_clients: Vec<u16>,
+    /// The format of the output.
+    pub format: OutputFormat,
+    /// The strings used to render a locked and unlocked account in csv output.
+    /// By default, `true` and `false` are used.
+    pub locked_labels: Option<(String, String)>,
+    /// The number of decimal places to output, up to four. By default, four are output.
+    pub precision: Option<u32>,
+    /// The order to output clients in, overriding the order of `filter_clients`.
+    /// By default, clients are sorted by client id.
+    pub sort_by: Option<SortBy>,
+    /// Prefix csv output with a comment line counting the clients and transactions in the ledger.
+    /// Ignored for JSON output.
+    pub meta: bool,
+}
+
+/// The orders that account statements can be sorted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Ascending client id.
+    Client,
+    /// Descending total funds, with ties in ascending client id.
+    Total,
+    /// Descending available funds, with ties in ascending client id.
+    Available,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(SortBy::Client),
+            "total" => Ok(SortBy::Total),
+            "available" => Ok(SortBy::Available),
+            _ => Err(format!(
+                "Unknown sort order \"{s}\", expected total, client or available"
+            )),
+        }
+    }
+}
+
+/// The formats that account statements can be output as.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "Unknown output format \"{s}\", expected csv or json"
+            )),
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Set whether the header row should be skipped.
+    pub fn with_omit_header(mut self, omit_header: bool) -> Self {
+        self.omit_header = omit_header;
+        self
+    }
+
+    /// Set the clients to output.
+    pub fn with_filter_clients(mut self, filter_clients: Vec<u16>) -> Self {
+        self.filter_clients = filter_clients;
+        self
+    }
+
+    /// Set the format of the output.
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the strings used to render a locked and unlocked account.
+    pub fn with_locked_labels(mut self, true_str: &str, false_str: &str) -> Self {
+        self.locked_labels = Some((true_str.to_string(), false_str.to_string()));
+        self
+    }
+
+    /// Set the number of decimal places to output.
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Set the order to output clients in.
+    pub fn with_sort_by(mut self, sort_by: SortBy) -> Self {
+        self.sort_by = Some(sort_by);
+        self
+    }
+
+    /// Set whether to prefix csv output with a comment counting the clients and transactions.
+    pub fn with_meta(mut self, meta: bool) -> Self {
+        self.meta = meta;
+        self
+    }
 }
 
 /// An individual client account.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ClientData {
-    held: BTreeMap<u32, i64>,
-    available: i64,
-    total: i64,
+    held: BTreeMap<u32, Amount>,
+    /// The held transactions that are withdrawals, recorded when they are disputed so that a
+    /// later duplicate transaction id cannot change how they are resolved.
+    held_withdrawals: BTreeSet<u32>,
+    available: Amount,
+    total: Amount,
     locked: bool,
+    first_line: Option<usize>,
+    total_deposited: Amount,
+    total_withdrawn: Amount,
+    currency: Option<String>,
+    dispute_count: u32,
+    chargeback_count: u32,
 }
 
 impl Ledger {
+    /// Construct a ledger by consuming a csv file held in a string.
+    /// Equivalent to `Ledger::try_from`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    ///
+    /// let ledger = Ledger::from_csv_string("type,client,tx,amount\ndeposit,1,1,5.0").unwrap();
+    /// assert_eq!(ledger.to_string(), "client, available, held, total, locked\n1, 5.0000, 0.0000, 5.0000, false");
+    ///
+    /// // The header is malformed
+    /// assert!(Ledger::from_csv_string("type,client,tx\ndeposit,1,1,5.0").is_err());
+    /// ```
+    pub fn from_csv_string(input: &str) -> Result<Self, LedgerErr> {
+        Ledger::from_csv_bytes(input.as_bytes())
+    }
+
+    /// Construct a ledger by consuming a csv file held in a byte slice.
+    /// Equivalent to `Ledger::try_from`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    ///
+    /// let ledger = Ledger::from_csv_bytes(b"type,client,tx,amount\ndeposit,1,1,5.0").unwrap();
+    /// assert_eq!(ledger.transactions.len(), 1);
+    ///
+    /// // The input is not valid UTF-8
+    /// assert!(Ledger::from_csv_bytes(b"type,client,tx,amount\ndeposit,1,1,\xff").is_err());
+    /// ```
+    pub fn from_csv_bytes(input: &[u8]) -> Result<Self, LedgerErr> {
+        let mut ledger = Ledger::default();
+        ledger.consume_csv(BufReader::new(Cursor::new(input)))?;
+        Ok(ledger)
+    }
+
     /// Consume a `BufReader` that contains a csv file of transactions.
-    pub fn consume_csv<T>(&mut self, mut reader: BufReader<T>) -> Result<(), LedgerErr>
+    ///
+    /// Calling `consume_csv` multiple times on the same `Ledger` accumulates state,
+    /// so transactions from later files are applied on top of earlier ones.
+    ///
+    /// The header is optional: when the first line is a valid transaction rather than a header,
+    /// it is applied as data with the default column order. A first line that is neither is
+    /// reported as a malformed header.
+    ///
+    /// A file containing only a header is valid and leaves the ledger unchanged, whereas a file
+    /// with no content at all, or a blank first line, returns `LedgerErr::EmptyFile`. A final record that is missing its
+    /// line ending and appears to have been cut short is reported as a truncated record.
+    pub fn consume_csv<T>(&mut self, reader: BufReader<T>) -> Result<(), LedgerErr>
     where
         T: Read,
     {
-        validate_header(&mut reader)?;
+        self.consume_csv_with_options(reader, &LedgerOptions::default())
+            .map(|_| ())
+    }
 
-        for (index, line) in reader.lines().enumerate() {
-            let res = line.map_err(LedgerErr::Reading)?; // map_err is used to provide better debug info
-            if !res.trim().is_empty() {
-                match parse_transaction(&res)
-                    .map_err(|err| LedgerErr::from_parse(err, index + 2))?
-                {
-                    Transaction::Withdrawal(id, tx, amount) => {
-                        self.insert_transaction(id, tx, -amount) // Negative amounts for withdrawals
-                    }
-                    Transaction::Deposit(id, tx, amount) => self.insert_transaction(id, tx, amount),
-                    Transaction::Dispute(id, tx) => self.hold(id, tx),
-                    Transaction::Resolve(id, tx) => self.resolve(id, tx),
-                    Transaction::Chargeback(id, tx) => self.chageback(id, tx),
-                }
-            }
-        }
+    /// Consume a `BufReader` that contains a csv file of transactions, using the provided options.
+    /// Returns the number of transaction rows that were processed.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0";
+    /// let mut ledger = Ledger::default();
+    ///
+    /// // Stop processing after the first row
+    /// let opts = LedgerOptions::default().with_max_rows(1);
+    /// let rows = ledger.consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts);
+    ///
+    /// assert_eq!(rows.unwrap(), 1);
+    /// ```
+    pub fn consume_csv_with_options<T>(
+        &mut self,
+        reader: BufReader<T>,
+        opts: &LedgerOptions,
+    ) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        self.consume_csv_with_progress(reader, opts, None)
+    }
 
-        Ok(())
+    /// Consume a `BufReader` that contains a csv file of transactions, optionally reporting progress.
+    /// The callback is invoked with the number of lines read every `progress_interval` lines,
+    /// and once more when the file has been fully consumed.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0";
+    /// let mut ledger = Ledger::default();
+    ///
+    /// let opts = LedgerOptions::default().with_progress_interval(1);
+    /// ledger
+    ///     .consume_csv_with_progress(
+    ///         BufReader::new(Cursor::new(csv)),
+    ///         &opts,
+    ///         Some(&mut |lines| eprintln!("Processed {lines} lines")),
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn consume_csv_with_progress<T>(
+        &mut self,
+        reader: BufReader<T>,
+        opts: &LedgerOptions,
+        progress: Option<&mut dyn FnMut(u64)>,
+    ) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        self.consume_csv_with_hooks(reader, opts, progress, None)
     }
 
-    /// Insert a new transaction
+    /// Consume a `BufReader` that contains a csv file of transactions, optionally writing a line
+    /// to `log` for each transaction that is applied. Failures to write to the log are ignored.
     ///
     /// Example:
     /// ```rust
-    /// use csv_ledger_lib::ledger::Ledger;
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    /// use std::io::{BufReader, Cursor};
     ///
-    /// // Create a new ledger
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0";
     /// let mut ledger = Ledger::default();
+    /// let mut log = Vec::new();
     ///
-    /// // Deposit
-    /// ledger.insert_transaction(1,1,10.0 as i64);
+    /// ledger
+    ///     .consume_csv_with_log(
+    ///         BufReader::new(Cursor::new(csv)),
+    ///         &LedgerOptions::default(),
+    ///         Some(&mut log),
+    ///     )
+    ///     .unwrap();
     ///
-    /// // Withdrawal
-    /// ledger.insert_transaction(1,2,-10.0 as i64);
+    /// assert_eq!(
+    ///     String::from_utf8(log).unwrap(),
+    ///     "line 2: applied Deposit client=1 tx=1 amount=1.0000\n"
+    /// );
     /// ```
-    pub fn insert_transaction(&mut self, client_id: u16, transaction_id: u32, amount: i64) {
-        if let Some(client) = self.clients.get_mut(&client_id) {
-            if !client.locked {
-                client.total += amount;
-                client.available += amount;
-                self.transactions.insert(transaction_id, amount);
+    pub fn consume_csv_with_log<T>(
+        &mut self,
+        reader: BufReader<T>,
+        opts: &LedgerOptions,
+        log: Option<&mut dyn Write>,
+    ) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        self.consume_csv_with_hooks(reader, opts, None, log)
+    }
+
+    /// Consume a csv file of transactions, reporting progress and logging applied transactions.
+    fn consume_csv_with_hooks<T>(
+        &mut self,
+        reader: BufReader<T>,
+        opts: &LedgerOptions,
+        progress: Option<&mut dyn FnMut(u64)>,
+        log: Option<&mut dyn Write>,
+    ) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        let mut records = Records::new(reader, opts);
+        let header = records
+            .next_line()
+            .map_err(LedgerErr::Reading)?
+            .ok_or(LedgerErr::EmptyFile)?;
+        let layout = match detect_header(&header, &opts.parse)? {
+            Some(layout) => layout,
+            None => {
+                records.unread(header);
+                ColumnLayout::from_options(&opts.parse)
             }
-        } else {
-            self.clients.insert(client_id, ClientData::new(amount));
-            self.transactions.insert(transaction_id, amount);
-        }
+        };
+        self.consume_rows(records, &layout, opts, progress, log)
     }
 
-    /// Opens a dispute on a transaction.
-    pub fn hold(&mut self, client_id: u16, transaction_id: u32) {
-        // Discard any incorrect inputs
-        if let Some(client) = self.clients.get_mut(&client_id) {
-            if let Some(amount) = self.transactions.remove(&transaction_id) {
-                {
-                    client.available -= amount;
-                    client.held.insert(transaction_id, amount);
+    /// Consume a `BufReader` that contains transactions without a header row.
+    /// This is useful for appending additional data after a previous call to `consume_csv`.
+    /// Returns the number of transaction rows that were processed.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let mut ledger = Ledger::default();
+    ///
+    /// ledger
+    ///     .consume_csv(BufReader::new(Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0")))
+    ///     .unwrap();
+    /// let rows = ledger
+    ///     .consume_csv_no_header(BufReader::new(Cursor::new("deposit,1,2,1.0")))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows, 1);
+    /// ```
+    pub fn consume_csv_no_header<T>(&mut self, reader: BufReader<T>) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        let opts = LedgerOptions::default();
+        let records = Records::new(reader, &opts);
+        self.consume_rows(records, &ColumnLayout::default(), &opts, None, None)
+    }
+
+    /// Consume any reader that contains a csv file of transactions, such as a `TcpStream`, without
+    /// allocating for each line. Every line is parsed in place from a single scratch buffer of
+    /// `LedgerOptions::max_line_length` bytes, which is refilled from the reader as it is consumed,
+    /// so the reader does not need to be wrapped in a `BufReader`.
+    /// Returns the number of transaction rows that were processed.
+    ///
+    /// As each line is parsed as soon as it is read, quoted fields may not span multiple lines.
+    /// Progress and logging are not reported, use `consume_csv_with_progress` or
+    /// `consume_csv_with_log` for those.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    ///
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0";
+    /// let mut ledger = Ledger::default();
+    ///
+    /// let rows = ledger.consume_csv_streaming(csv.as_bytes(), &LedgerOptions::default());
+    ///
+    /// assert_eq!(rows.unwrap(), 2);
+    /// ```
+    pub fn consume_csv_streaming<R>(
+        &mut self,
+        mut reader: R,
+        opts: &LedgerOptions,
+    ) -> Result<usize, LedgerErr>
+    where
+        R: Read,
+    {
+        self.forget_lines();
+
+        // Allow room for a CRLF line ending on top of the maximum length
+        let mut buf = vec![0; opts.max_line_length + 2];
+        let (mut start, mut end, mut eof) = (0, 0, false);
+        let mut columns: Option<ColumnLayout> = None;
+        let mut lines = 0;
+        let mut limits = RowLimits::new(opts);
+
+        loop {
+            let (range, terminated) = match buf[start..end].iter().position(|&b| b == b'\n') {
+                Some(position) => {
+                    let range = start..start + position;
+                    start += position + 1;
+                    (range, true)
+                }
+                None if eof && start == end => break,
+                None if eof => {
+                    let range = start..end;
+                    start = end;
+                    (range, false)
+                }
+                None => {
+                    // Move the partial line to the front of the buffer and read more after it
+                    buf.copy_within(start..end, 0);
+                    (start, end) = (0, end - start);
+                    if end == buf.len() {
+                        let err = line_too_long(lines + 1, opts.max_line_length);
+                        return Err(LedgerErr::Reading(err));
+                    }
+
+                    match reader.read(&mut buf[end..]) {
+                        Ok(0) => eof = true,
+                        Ok(read) => end += read,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                        Err(err) => return Err(LedgerErr::Reading(err)),
+                    }
+                    continue;
                 }
+            };
+            lines += 1;
+
+            let mut bytes = &buf[range];
+            if terminated {
+                bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
             }
-        }
-    }
+            if bytes.len() > opts.max_line_length {
+                return Err(LedgerErr::Reading(line_too_long(
+                    lines,
+                    opts.max_line_length,
+                )));
+            }
+            let line = std::str::from_utf8(bytes).map_err(|err| {
+                LedgerErr::Reading(io::Error::new(io::ErrorKind::InvalidData, err))
+            })?;
 
-    /// Resolves a disputed transaction - adds disputed transaction's value back to the available funds.
-    pub fn resolve(&mut self, client_id: u16, transaction_id: u32) {
-        // Discard any incorrect inputs
-        if let Some(client) = self.clients.get_mut(&client_id) {
-            if let Some(amount) = client.held.remove(&transaction_id) {
-                client.available += amount;
+            // The first line is either the header or the first transaction
+            let layout = match columns {
+                Some(ref layout) => layout,
+                None => match detect_header(line, &opts.parse)? {
+                    Some(layout) => {
+                        columns = Some(layout);
+                        continue;
+                    }
+                    None => columns.insert(ColumnLayout::from_options(&opts.parse)),
+                },
+            };
+
+            let index = lines as usize - 1;
+            if !self.consume_row(&mut limits, line, index, terminated, layout, None)? {
+                break;
             }
         }
+
+        if columns.is_none() {
+            return Err(LedgerErr::EmptyFile);
+        }
+
+        log_info!("Consumed {} transactions from csv", limits.rows);
+        Ok(limits.rows)
     }
 
-    /// Peform a chargeback on a disputed transaction -
-    pub fn chageback(&mut self, client_id: u16, transaction_id: u32) {
-        // Discard any incorrect inputs
-        if let Some(client) = self.clients.get_mut(&client_id) {
-            if let Some(amount) = client.held.remove(&transaction_id) {
-                client.total -= amount;
-                client.locked = true;
+    /// Consume several csv files of transactions in parallel, available with the `rayon` feature.
+    /// Each file is consumed into its own ledger on the rayon thread pool, and the ledgers are then
+    /// merged one at a time, in the order given, with `Ledger::merge`.
+    ///
+    /// As the files are consumed independently, a dispute, resolve or chargeback has no effect on
+    /// a transaction from another file, and the line of any error is relative to its own file.
+    /// Returns `LedgerErr::Duplicate` if a transaction id is used in more than one file.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    /// use std::io::Write;
+    ///
+    /// let mut first = tempfile::NamedTempFile::new().unwrap();
+    /// let mut second = tempfile::NamedTempFile::new().unwrap();
+    /// write!(first, "type,client,tx,amount\ndeposit,1,1,1.0").unwrap();
+    /// write!(second, "type,client,tx,amount\ndeposit,1,2,2.0").unwrap();
+    ///
+    /// let paths = [first.path().to_path_buf(), second.path().to_path_buf()];
+    /// let ledger = Ledger::consume_csv_parallel(&paths).unwrap();
+    /// assert_eq!(ledger.gross_total(), 30000);
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn consume_csv_parallel(paths: &[PathBuf]) -> Result<Ledger, LedgerErr> {
+        let ledgers = paths
+            .par_iter()
+            .map(|path| Ledger::try_from(path.as_path()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Merging must be sequential, as clients may appear in several files
+        ledgers
+            .into_iter()
+            .try_fold(Ledger::default(), |mut ledger, other| {
+                ledger.merge(other)?;
+                Ok(ledger)
+            })
+    }
+
+    /// Check that every row of a csv file of transactions can be parsed, without applying any of
+    /// them. Unlike `consume_csv`, parsing continues past a malformed row so that every parse error
+    /// is returned, alongside the line that it occurred on. An empty result means that the file
+    /// will import cleanly, although operations such as a dispute of an unknown transaction may
+    /// still be ignored.
+    ///
+    /// A missing or malformed header, or a failure to read the file, stops linting immediately.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    /// use std::io::Cursor;
+    ///
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,foo,2,1.0";
+    /// let errors = Ledger::lint_csv(Cursor::new(csv));
+    ///
+    /// assert_eq!(errors.len(), 1);
+    /// assert!(errors[0].code().starts_with("parse"));
+    /// ```
+    pub fn lint_csv<R>(reader: R) -> Vec<LedgerErr>
+    where
+        R: BufRead,
+    {
+        let opts = LedgerOptions::default();
+        let mut records = Records::new(BufReader::new(reader), &opts);
+        let layout = match records.next_line() {
+            Ok(Some(header)) => match detect_header(&header, &opts.parse) {
+                Ok(Some(layout)) => layout,
+                Ok(None) => {
+                    records.unread(header);
+                    ColumnLayout::default()
+                }
+                Err(err) => return vec![err],
+            },
+            Ok(None) => return vec![LedgerErr::EmptyFile],
+            Err(err) => return vec![LedgerErr::Reading(err)],
+        };
+
+        let mut errors = Vec::new();
+        loop {
+            match records.next_record() {
+                Ok(Some((index, res))) if !res.trim().is_empty() => {
+                    if let Err(err) =
+                        parse_record(&res, index, records.terminated, &layout, &opts.parse)
+                    {
+                        errors.push(err);
+                    }
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(err) => {
+                    errors.push(LedgerErr::Reading(err));
+                    break;
+                }
             }
         }
+
+        errors
     }
-}
 
-impl Display for Ledger {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "client, available, held, total, locked{}",
-            self.clients
-                .iter()
-                .fold(String::new(), |acc, (key, value)| format!(
-                    "{acc}\n{key}, {value}"
-                ))
-        )
+    /// Consume an asynchronous reader that contains a csv file of transactions, available with the
+    /// `tokio` feature. Returns the number of transaction rows that were processed.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0";
+    /// let mut ledger = Ledger::default();
+    ///
+    /// let rows = ledger.consume_csv_async(csv.as_bytes()).await;
+    ///
+    /// assert_eq!(rows.unwrap(), 2);
+    /// # });
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn consume_csv_async<R>(&mut self, reader: R) -> Result<usize, LedgerErr>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        self.consume_csv_async_with_options(reader, &LedgerOptions::default())
+            .await
     }
-}
 
-/// Validate the header of the csv file.
-fn validate_header<T>(reader: &mut BufReader<T>) -> Result<(), LedgerErr>
-where
-    T: Read,
-{
-    let mut buf = String::new();
-    reader.read_line(&mut buf).map_err(LedgerErr::Reading)?; // map_err is used to provide better debug info
-    parse_header(&buf).map_err(|err| LedgerErr::Parse(err.to_string(), 1))?;
-    Ok(())
-}
+    /// Consume an asynchronous reader that contains a csv file of transactions, using the provided
+    /// options, available with the `tokio` feature. Returns the number of transaction rows that
+    /// were processed.
+    ///
+    /// Reading stops as soon as a line exceeds `LedgerOptions::max_line_length`, so memory use is
+    /// bounded for untrusted readers. As with `consume_csv_streaming`, quoted fields may not span
+    /// multiple lines.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0";
+    /// let mut ledger = Ledger::default();
+    ///
+    /// // Stop processing after the first row
+    /// let opts = LedgerOptions::default().with_max_rows(1);
+    /// let rows = ledger.consume_csv_async_with_options(csv.as_bytes(), &opts).await;
+    ///
+    /// assert_eq!(rows.unwrap(), 1);
+    /// # });
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn consume_csv_async_with_options<R>(
+        &mut self,
+        mut reader: R,
+        opts: &LedgerOptions,
+    ) -> Result<usize, LedgerErr>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        self.forget_lines();
+
+        let mut lines = 0;
+        let header = read_line_async(&mut reader, opts.max_line_length, &mut lines)
+            .await
+            .map_err(LedgerErr::Reading)?
+            .ok_or(LedgerErr::EmptyFile)?;
+        let (layout, mut first) = match detect_header(&header.0, &opts.parse)? {
+            Some(layout) => (layout, None),
+            None => (ColumnLayout::from_options(&opts.parse), Some(header)),
+        };
+
+        let mut limits = RowLimits::new(opts);
+        while let Some((line, terminated)) = match first.take() {
+            Some(line) => Some(line),
+            None => read_line_async(&mut reader, opts.max_line_length, &mut lines)
+                .await
+                .map_err(LedgerErr::Reading)?,
+        } {
+            let index = lines as usize - 1;
+            if !self.consume_row(&mut limits, &line, index, terminated, &layout, None)? {
+                break;
+            }
+        }
+
+        log_info!("Consumed {} transactions from csv", limits.rows);
+        Ok(limits.rows)
+    }
+
+    /// Consume a JSON array of transactions, available with the `json-input` feature.
+    /// Each transaction is an object with `type`, `client`, `tx` and an optional `amount`,
+    /// which may be a number or a string. Returns the number of transactions processed.
+    ///
+    /// The line of any `LedgerErr::Parse` is the position of the transaction within the array,
+    /// starting at 1.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::Ledger;
+    ///
+    /// let json = r#"[
+    ///     {"type": "deposit", "client": 1, "tx": 1, "amount": 1.5},
+    ///     {"type": "dispute", "client": 1, "tx": 1}
+    /// ]"#;
+    /// let mut ledger = Ledger::default();
+    ///
+    /// assert_eq!(ledger.consume_json(json.as_bytes()).unwrap(), 2);
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 0.0000, 1.5000, 1.5000, false"
+    /// );
+    /// ```
+    #[cfg(feature = "json-input")]
+    pub fn consume_json<R>(&mut self, reader: R) -> Result<usize, LedgerErr>
+    where
+        R: Read,
+    {
+        self.consume_json_with_options(reader, &LedgerOptions::default())
+    }
+
+    /// Consume a JSON array of transactions using the provided options, available with the
+    /// `json-input` feature. Returns the number of transactions processed.
+    ///
+    /// The options apply as they do to a csv file, with each transaction counting as a row. An
+    /// ISO-8601 `timestamp` field is read when `ParseOptions::timestamp` is set, and any other
+    /// fields are rejected unless `ParseOptions::allow_extra_columns` is set.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    ///
+    /// let json = r#"[
+    ///     {"type": "deposit", "client": 1, "tx": 1, "amount": 1.5},
+    ///     {"type": "deposit", "client": 1, "tx": 2, "amount": 2.5}
+    /// ]"#;
+    /// let mut ledger = Ledger::default();
+    ///
+    /// // Skip the first transaction, which was applied by a previous run
+    /// let opts = LedgerOptions::default().with_skip_rows(1);
+    /// assert_eq!(ledger.consume_json_with_options(json.as_bytes(), &opts).unwrap(), 1);
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 2.5000, 0.0000, 2.5000, false"
+    /// );
+    /// ```
+    #[cfg(feature = "json-input")]
+    pub fn consume_json_with_options<R>(
+        &mut self,
+        reader: R,
+        opts: &LedgerOptions,
+    ) -> Result<usize, LedgerErr>
+    where
+        R: Read,
+    {
+        self.consume_json_with_log(reader, opts, None)
+    }
+
+    /// Consume a JSON array of transactions using the provided options, optionally writing a line
+    /// to `log` for each transaction that is applied, available with the `json-input` feature.
+    /// Failures to write to the log are ignored.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    ///
+    /// let json = r#"[{"type": "deposit", "client": 1, "tx": 1, "amount": 1}]"#;
+    /// let mut ledger = Ledger::default();
+    /// let mut log = Vec::new();
+    ///
+    /// ledger
+    ///     .consume_json_with_log(json.as_bytes(), &LedgerOptions::default(), Some(&mut log))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     String::from_utf8(log).unwrap(),
+    ///     "line 1: applied Deposit client=1 tx=1 amount=1.0000\n"
+    /// );
+    /// ```
+    #[cfg(feature = "json-input")]
+    pub fn consume_json_with_log<R>(
+        &mut self,
+        reader: R,
+        opts: &LedgerOptions,
+        mut log: Option<&mut dyn Write>,
+    ) -> Result<usize, LedgerErr>
+    where
+        R: Read,
+    {
+        let invalid = |message: String, line: usize| LedgerErr::Parse {
+            kind: ParseKind::Format,
+            message,
+            line,
+            raw: None,
+        };
+
+        let transactions: Vec<JsonTransaction> =
+            serde_json::from_reader(reader).map_err(|err| invalid(err.to_string(), err.line()))?;
+
+        self.forget_lines();
+        let mut limits = RowLimits::new(opts);
+        for (index, json) in transactions.iter().enumerate() {
+            let log = log.as_mut().map(|log| &mut **log as &mut dyn Write);
+            let applied = self.apply_row(&mut limits, index + 1, log, |opts| {
+                let (transaction, time) =
+                    json.to_transaction(&opts.parse).map_err(|err| match err {
+                        LedgerErr::InvalidTransaction(message) => invalid(message, index + 1),
+                        err => err,
+                    })?;
+                Ok((transaction, None, time))
+            })?;
+            if !applied {
+                break;
+            }
+        }
+
+        log_info!("Consumed {} transactions from json", limits.rows);
+        Ok(limits.rows)
+    }
+
+    /// Consume the remaining transaction rows of a csv file.
+    /// Progress is reported relative to the first unread line.
+    fn consume_rows<T>(
+        &mut self,
+        mut records: Records<T>,
+        layout: &ColumnLayout,
+        opts: &LedgerOptions,
+        mut progress: Option<&mut dyn FnMut(u64)>,
+        mut log: Option<&mut dyn Write>,
+    ) -> Result<usize, LedgerErr>
+    where
+        T: Read,
+    {
+        self.forget_lines();
+
+        let mut limits = RowLimits::new(opts);
+        let mut reported = 0;
+        let interval = opts.progress_interval.max(1);
+        let skipped = records.lines - u64::from(records.pending.is_some());
+
+        while let Some((index, res)) = records.next_record().map_err(LedgerErr::Reading)? {
+            // Report progress periodically
+            let lines = records.lines - skipped;
+            if let Some(callback) = progress.as_mut() {
+                if lines / interval > reported / interval {
+                    reported = lines;
+                    callback(reported);
+                }
+            }
+
+            let terminated = records.terminated;
+            let log = log.as_mut().map(|log| &mut **log as &mut dyn Write);
+            if !self.consume_row(&mut limits, &res, index, terminated, layout, log)? {
+                return Ok(limits.rows);
+            }
+        }
+
+        // Report the final line count if it was not already reported
+        if let Some(callback) = progress {
+            if records.lines - skipped != reported {
+                callback(records.lines - skipped);
+            }
+        }
+
+        log_info!("Consumed {} transactions from csv", limits.rows);
+        Ok(limits.rows)
+    }
+
+    /// Parse and apply a single row of a csv file, shared by each way of consuming one. `index` is
+    /// the zero-based line of the row, and blank rows are ignored.
+    /// Returns `Ok(false)` once the row limit has been reached and consuming should stop.
+    fn consume_row(
+        &mut self,
+        limits: &mut RowLimits,
+        record: &str,
+        index: usize,
+        terminated: bool,
+        layout: &ColumnLayout,
+        log: Option<&mut dyn Write>,
+    ) -> Result<bool, LedgerErr> {
+        if record.trim().is_empty() {
+            return Ok(true);
+        }
+
+        self.apply_row(limits, index + 1, log, |opts| {
+            let transaction = parse_record(record, index, terminated, layout, &opts.parse)?;
+            let currency = parse_currency(record, layout, &opts.parse);
+            let time = parse_timestamp(record, layout, &opts.parse);
+            Ok((transaction, currency, time))
+        })
+    }
+
+    /// Skip, limit and apply a single row of input on `line`, shared by every input format.
+    /// `parse` reads the transaction, currency and time of the row, and is not called for rows
+    /// that are skipped. Each applied transaction is written to `log`, if provided.
+    /// Returns `Ok(false)` once the row limit has been reached and consuming should stop.
+    fn apply_row<'r, F>(
+        &mut self,
+        limits: &mut RowLimits,
+        line: usize,
+        log: Option<&mut dyn Write>,
+        parse: F,
+    ) -> Result<bool, LedgerErr>
+    where
+        F: FnOnce(&LedgerOptions) -> Result<(Transaction, Option<&'r str>, Option<i64>), LedgerErr>,
+    {
+        let opts = limits.opts;
+
+        // Skip rows that were applied by a previous run
+        if limits.skipped < opts.skip_rows {
+            limits.skipped += 1;
+            return Ok(true);
+        }
+
+        // Stop once the row limit has been reached
+        if row_limit_reached(limits.rows, opts)? {
+            return Ok(false);
+        }
+
+        let (transaction, currency, time) = parse(opts)?;
+
+        // Only count transactions that change the ledger, as ignored operations apply nothing
+        let takes_effect =
+            opts.max_transactions.is_some() && self.takes_effect(&transaction, opts, line, time);
+        if let (true, Some(max)) = (takes_effect, opts.max_transactions) {
+            if limits.applied >= max {
+                return Err(LedgerErr::TransactionLimitExceeded(max));
+            }
+        }
+
+        let entry = log.is_some().then(|| log_entry(&transaction));
+        self.apply(transaction, currency, time, opts, line)?;
+        limits.rows += 1;
+        limits.applied += usize::from(takes_effect);
+
+        if let (Some(log), Some(entry)) = (log, entry) {
+            let _ = writeln!(log, "line {line}: applied {entry}");
+        }
+        Ok(true)
+    }
+
+    /// Parse a single line of csv in the default column order and apply it to the ledger, exactly
+    /// as `consume_csv` does for each of its rows. `line_no` is the line reported by any error.
+    /// Blank lines are ignored.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.apply_line("deposit, 1, 1, 1.0", 1).unwrap();
+    /// ledger.apply_line("dispute, 1, 1,", 2).unwrap();
+    ///
+    /// assert_eq!(ledger.total_held(), 10000);
+    /// assert!(ledger.apply_line("deposit, foo, 2, 1.0", 3).is_err());
+    /// ```
+    pub fn apply_line(&mut self, line: &str, line_no: usize) -> Result<(), LedgerErr> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let opts = LedgerOptions::default();
+        let layout = ColumnLayout::from_options(&opts.parse);
+        let transaction =
+            parse_record(line, line_no.saturating_sub(1), true, &layout, &opts.parse)?;
+        self.apply_record(transaction, line, &layout, &opts, line_no)
+    }
+
+    /// Apply a transaction parsed from a csv record, reading its currency and timestamp from the
+    /// record. Shared by every method that consumes csv, so that each row is dispatched alike.
+    fn apply_record(
+        &mut self,
+        transaction: Transaction,
+        record: &str,
+        layout: &ColumnLayout,
+        opts: &LedgerOptions,
+        line: usize,
+    ) -> Result<(), LedgerErr> {
+        let currency = parse_currency(record, layout, &opts.parse);
+        let time = parse_timestamp(record, layout, &opts.parse);
+        self.apply(transaction, currency, time, opts, line)
+    }
+
+    /// Apply a parsed transaction to the ledger, in the given currency and at the given time if
+    /// they were provided.
+    fn apply(
+        &mut self,
+        transaction: Transaction,
+        currency: Option<&str>,
+        time: Option<i64>,
+        opts: &LedgerOptions,
+        line: usize,
+    ) -> Result<(), LedgerErr> {
+        if opts.reserved_clients.contains(&transaction.client()) {
+            return Err(LedgerErr::InvalidOperation(
+                format!("Transaction for reserved client {}", transaction.client()),
+                line,
+            ));
+        }
+
+        // Operations on another client's transaction have no effect, so warn or reject them
+        if let Err(err) = self.check_client(&transaction) {
+            if opts.strict_client_matching {
+                return Err(err);
+            }
+            log_warn!("Line {line}: {}", err.describe().1);
+        }
+
+        if let Transaction::Deposit(id, tx, _) | Transaction::Withdrawal(id, tx, _) = transaction {
+            // Reusing a transaction id indicates upstream data corruption
+            if let (true, Some(&(_, _, first_line))) = (opts.strict, self.transactions.get(&tx)) {
+                return Err(LedgerErr::Duplicate {
+                    tx_id: tx,
+                    first_line,
+                    second_line: Some(line),
+                });
+            }
+
+            // As does moving funds in or out of a frozen account
+            if opts.strict && self.clients.get(&id).is_some_and(|client| client.locked) {
+                return Err(LedgerErr::InvalidOperation(
+                    "Deposit or Withdrawal on a locked account".to_string(),
+                    line,
+                ));
+            }
+        }
+
+        match transaction {
+            Transaction::Withdrawal(id, tx, amount) => {
+                self.insert_transaction_in(id, tx, -amount, currency, Some(line))?;
+                // Negative amounts for withdrawals
+                self.record_time(tx, time, line);
+            }
+            Transaction::Deposit(id, tx, amount) => {
+                self.insert_transaction_in(id, tx, amount, currency, Some(line))?;
+                self.record_time(tx, time, line);
+            }
+            Transaction::Dispute(id, tx) => {
+                if !self.is_outside_dispute_window(tx, opts, line, time) {
+                    self.hold(id, tx)
+                } else if opts.strict {
+                    return Err(LedgerErr::InvalidOperation(
+                        "Dispute of a transaction outside of the dispute window".to_string(),
+                        line,
+                    ));
+                }
+            }
+            Transaction::Resolve(id, tx) => self.resolve(id, tx),
+            Transaction::PartialResolve(id, tx, amount) => {
+                // Resolving more than is held indicates upstream data corruption
+                if opts.strict && !self.can_resolve(id, tx, amount) {
+                    return Err(LedgerErr::InvalidOperation(
+                        "Resolve of more than the disputed amount".to_string(),
+                        line,
+                    ));
+                }
+                self.resolve_partial(id, tx, amount)
+            }
+            Transaction::Chargeback(id, tx) => {
+                // A chargeback without a dispute indicates upstream data corruption
+                if opts.strict && !self.is_held(id, tx) {
+                    return Err(LedgerErr::InvalidOperation(
+                        "Chargeback on a transaction that is not disputed".to_string(),
+                        line,
+                    ));
+                }
+                self.chageback(id, tx)
+            }
+        }
+
+        if opts.event_log {
+            self.event_log.push((line, transaction));
+        }
+
+        Ok(())
+    }
+
+    /// Test if applying a transaction on `line` would change the ledger, rather than being
+    /// ignored, such as a dispute of an unknown transaction or a deposit to a locked account.
+    fn takes_effect(
+        &self,
+        transaction: &Transaction,
+        opts: &LedgerOptions,
+        line: usize,
+        time: Option<i64>,
+    ) -> bool {
+        match *transaction {
+            Transaction::Deposit(id, _, amount) => self
+                .clients
+                .get(&id)
+                .is_none_or(|client| client.credited(amount).is_some()),
+            Transaction::Withdrawal(id, _, amount) => self
+                .clients
+                .get(&id)
+                .is_none_or(|client| client.credited(-amount).is_some()),
+            Transaction::Dispute(id, tx) => {
+                !self.is_outside_dispute_window(tx, opts, line, time)
+                    && self.held_balances(id, tx).is_some()
+            }
+            Transaction::Resolve(id, tx) => self.clients.get(&id).is_some_and(|client| {
+                client.held.get(&tx).is_some_and(|&amount| {
+                    let withdrawal = client.held_withdrawals.contains(&tx);
+                    client.released(amount, withdrawal).is_some()
+                })
+            }),
+            Transaction::PartialResolve(id, tx, amount) => {
+                self.can_resolve(id, tx, amount)
+                    && self.clients.get(&id).is_some_and(|client| {
+                        let withdrawal = client.held_withdrawals.contains(&tx);
+                        client.released(amount, withdrawal).is_some()
+                    })
+            }
+            Transaction::Chargeback(id, tx) => self.charged_back_balances(id, tx).is_some(),
+        }
+    }
+
+    /// Remove all clients and transactions, allowing the ledger to be reused for another file.
+    /// The allocated capacity of `clients` is retained to reduce allocations when reused.
+    /// Note: `transactions` is a `BTreeMap`, which does not retain capacity once cleared.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10));
+    ///
+    /// ledger.clear();
+    /// assert!(ledger.clients.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.clients.clear();
+        self.transactions.clear();
+        self.event_log.clear();
+        self.timestamps.clear();
+    }
+
+    /// Forget every transaction with an id below `before_tx_id`, returning the number removed.
+    /// This bounds memory use once older transactions are known to be past the dispute window.
+    ///
+    /// Transactions that are currently disputed are kept, so that they can still be resolved or
+    /// charged back. Any later dispute of a removed transaction is silently ignored, and its id is
+    /// no longer checked for duplicates.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10));
+    /// ledger.insert_transaction(1, 2, Amount(10));
+    /// ledger.insert_transaction(1, 3, Amount(10));
+    /// ledger.hold(1, 1);
+    ///
+    /// assert_eq!(ledger.truncate_old_transactions(3), 1);
+    /// assert_eq!(ledger.transactions.keys().collect::<Vec<_>>(), vec![&1, &3]);
+    /// ```
+    pub fn truncate_old_transactions(&mut self, before_tx_id: u32) -> usize {
+        let held: HashSet<u32> = self
+            .clients
+            .values()
+            .flat_map(|client| client.held.keys().copied())
+            .collect();
+
+        let count = self.transactions.len();
+        self.transactions
+            .retain(|id, _| *id >= before_tx_id || held.contains(id));
+        self.timestamps
+            .retain(|id, _| *id >= before_tx_id || held.contains(id));
+
+        count - self.transactions.len()
+    }
+
+    /// Merge all clients and transactions from another ledger into this one.
+    /// Clients present in both ledgers have their balances and open disputes combined,
+    /// and are locked if either ledger has them locked.
+    ///
+    /// Returns `LedgerErr::Duplicate` without making any changes if a transaction id exists in both
+    /// ledgers, `LedgerErr::Overflow` if the combined balance of a client would overflow, or
+    /// `LedgerErr::CurrencyMismatch` if a client holds a different currency in each ledger.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10));
+    ///
+    /// let mut other = Ledger::default();
+    /// other.insert_transaction(1, 2, Amount(5));
+    ///
+    /// ledger.merge(other).unwrap();
+    /// assert_eq!(ledger.transactions.len(), 2);
+    /// ```
+    pub fn merge(&mut self, other: Ledger) -> Result<(), LedgerErr> {
+        if let Some((&tx_id, first, second)) =
+            other.transactions.iter().find_map(|(id, &(_, _, line))| {
+                self.transactions
+                    .get(id)
+                    .map(|&(_, _, first)| (id, first, line))
+            })
+        {
+            return Err(LedgerErr::Duplicate {
+                tx_id,
+                first_line: first,
+                second_line: second,
+            });
+        }
+
+        // Check every client before making any changes, so that a failed merge changes nothing
+        for (id, client) in &other.clients {
+            if let Some(existing) = self.clients.get(id) {
+                if existing.available.checked_add(client.available).is_none()
+                    || existing.total.checked_add(client.total).is_none()
+                {
+                    return Err(LedgerErr::Overflow(*id));
+                }
+                if let (Some(expected), Some(found)) = (&existing.currency, &client.currency) {
+                    if expected != found {
+                        return Err(LedgerErr::CurrencyMismatch {
+                            client: *id,
+                            expected: expected.clone(),
+                            found: found.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for (id, client) in other.clients {
+            if let Some(existing) = self.clients.get_mut(&id) {
+                // The balances were checked for overflow above
+                existing.available = Amount(existing.available.0 + client.available.0);
+                existing.total = Amount(existing.total.0 + client.total.0);
+                existing.held.extend(client.held);
+                existing.held_withdrawals.extend(client.held_withdrawals);
+                existing.locked |= client.locked;
+                existing.currency = existing.currency.take().or(client.currency);
+                existing.record(client.total_deposited);
+                existing.record(-client.total_withdrawn);
+                existing.dispute_count =
+                    existing.dispute_count.saturating_add(client.dispute_count);
+                existing.chargeback_count = existing
+                    .chargeback_count
+                    .saturating_add(client.chargeback_count);
+            } else {
+                self.clients.insert(id, client);
+            }
+        }
+
+        self.timestamps.extend(other.timestamps);
+        self.transactions.extend(other.transactions);
+
+        Ok(())
+    }
+
+    /// Check that a dispute, resolve or chargeback names the client that owns the transaction.
+    /// Deposits, withdrawals and unknown transactions always pass.
+    fn check_client(&self, transaction: &Transaction) -> Result<(), LedgerErr> {
+        if let Transaction::Deposit(..) | Transaction::Withdrawal(..) = transaction {
+            return Ok(());
+        }
+
+        match self.transactions.get(&transaction.tx()) {
+            Some(&(owner, ..)) if owner != transaction.client() => Err(LedgerErr::WrongClient {
+                tx_id: transaction.tx(),
+                expected_client: owner,
+                actual_client: transaction.client(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the time of a transaction, if it was inserted from `line`.
+    fn record_time(&mut self, transaction_id: u32, time: Option<i64>, line: usize) {
+        if let Some(&(_, _, Some(first))) = self.transactions.get(&transaction_id) {
+            match time {
+                Some(time) if first == line => self.timestamps.insert(transaction_id, time),
+                _ if first == line => self.timestamps.remove(&transaction_id),
+                _ => None,
+            };
+        }
+    }
+
+    /// Forget the lines that the stored transactions were read from, before consuming another
+    /// file, so that the dispute window only measures transactions read from the same file.
+    fn forget_lines(&mut self) {
+        for (_, _, line) in self.transactions.values_mut() {
+            *line = None;
+        }
+    }
+
+    /// Test if a transaction was read too many lines, or too long, before a dispute of it on `line`
+    /// at `time`.
+    fn is_outside_dispute_window(
+        &self,
+        transaction_id: u32,
+        opts: &LedgerOptions,
+        line: usize,
+        time: Option<i64>,
+    ) -> bool {
+        let outside_lines = match (opts.dispute_window, self.transactions.get(&transaction_id)) {
+            (Some(window), Some(&(_, _, Some(first)))) => line.saturating_sub(first) > window,
+            _ => false,
+        };
+        let outside_time = match (
+            opts.dispute_window_seconds,
+            time,
+            self.timestamps.get(&transaction_id),
+        ) {
+            (Some(window), Some(time), Some(&first)) => {
+                u64::try_from(time.saturating_sub(first)).is_ok_and(|elapsed| elapsed > window)
+            }
+            _ => false,
+        };
+
+        outside_lines || outside_time
+    }
+
+    /// The time of a transaction in seconds since the Unix epoch, if it was read from a csv file
+    /// with a timestamp column.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let csv = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,2023-01-01T00:00:00Z";
+    /// let opts = LedgerOptions::default().with_timestamp(true);
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger
+    ///     .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(ledger.transaction_timestamp(1), Some(1672531200));
+    /// ```
+    pub fn transaction_timestamp(&self, transaction_id: u32) -> Option<i64> {
+        self.transactions
+            .contains_key(&transaction_id)
+            .then(|| self.timestamps.get(&transaction_id).copied())
+            .flatten()
+    }
+
+    /// Test if a transaction is currently held by a client.
+    fn is_held(&self, client_id: u16, transaction_id: u32) -> bool {
+        self.clients
+            .get(&client_id)
+            .is_some_and(|client| client.held.contains_key(&transaction_id))
+    }
+
+    /// Find the client that owns a transaction, using the global transaction map.
+    /// Transactions that have been charged back are no longer addressable.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(7, 1, Amount(10000));
+    ///
+    /// assert_eq!(ledger.find_client_by_transaction(1), Some(7));
+    /// assert_eq!(ledger.find_client_by_transaction(2), None);
+    /// ```
+    pub fn find_client_by_transaction(&self, transaction_id: u32) -> Option<u16> {
+        self.transactions
+            .get(&transaction_id)
+            .map(|&(client_id, ..)| client_id)
+    }
+
+    /// Find the client currently holding a disputed transaction, by searching each client's held
+    /// transactions.
+    pub fn find_client_with_held_transaction(&self, transaction_id: u32) -> Option<u16> {
+        self.clients
+            .iter()
+            .find(|(_, client)| client.held.contains_key(&transaction_id))
+            .map(|(&client_id, _)| client_id)
+    }
+
+    /// Insert a new transaction
+    ///
+    /// Transactions for a locked client are discarded entirely: the balances are left untouched
+    /// and the transaction is not recorded in `transactions`, so it can never be disputed.
+    /// A client is only created unlocked, so a transaction for a new client is always recorded.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// // Create a new ledger
+    /// let mut ledger = Ledger::default();
+    ///
+    /// // Deposit
+    /// ledger.insert_transaction(1, 1, Amount::from_major_minor(10, 0));
+    ///
+    /// // Withdrawal
+    /// ledger.insert_transaction(1, 2, -Amount::from_major_minor(10, 0));
+    /// ```
+    pub fn insert_transaction(&mut self, client_id: u16, transaction_id: u32, amount: Amount) {
+        self.insert_transaction_at(client_id, transaction_id, amount, None)
+    }
+
+    /// Insert a new transaction, recording the line of the csv that it was read from
+    /// if it creates a new client.
+    fn insert_transaction_at(
+        &mut self,
+        client_id: u16,
+        transaction_id: u32,
+        amount: Amount,
+        line: Option<usize>,
+    ) {
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            if !client.credit(amount) {
+                log_warn!(
+                    "Skipped transaction {transaction_id} for client {client_id}: the account is locked or would overflow"
+                );
+                return;
+            }
+        } else {
+            let mut client = ClientData::new(amount);
+            client.first_line = line;
+            self.clients.insert(client_id, client);
+        }
+
+        self.transactions
+            .insert(transaction_id, (client_id, amount, line));
+        log_debug!("Inserted transaction {transaction_id} of {amount} for client {client_id}");
+    }
+
+    /// Insert a new transaction in a currency. The first transaction in a currency sets the
+    /// currency of the client, after which transactions in any other currency are rejected with
+    /// `LedgerErr::CurrencyMismatch`. Transactions inserted without a currency are always accepted.
+    /// A currency longer than `MAX_CURRENCY_LEN` bytes is rejected with
+    /// `LedgerErr::InvalidTransaction`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction_with_currency(1, 1, Amount(10), "EUR").unwrap();
+    /// ledger.insert_transaction_with_currency(1, 2, Amount(10), "EUR").unwrap();
+    ///
+    /// assert!(ledger.insert_transaction_with_currency(1, 3, Amount(10), "USD").is_err());
+    /// assert_eq!(ledger.clients.get(&1).unwrap().currency(), Some("EUR"));
+    /// ```
+    pub fn insert_transaction_with_currency(
+        &mut self,
+        client_id: u16,
+        transaction_id: u32,
+        amount: Amount,
+        currency: &str,
+    ) -> Result<(), LedgerErr> {
+        self.insert_transaction_in(client_id, transaction_id, amount, Some(currency), None)
+    }
+
+    fn insert_transaction_in(
+        &mut self,
+        client_id: u16,
+        transaction_id: u32,
+        amount: Amount,
+        currency: Option<&str>,
+        line: Option<usize>,
+    ) -> Result<(), LedgerErr> {
+        let Some(currency) = currency else {
+            self.insert_transaction_at(client_id, transaction_id, amount, line);
+            return Ok(());
+        };
+        if currency.len() > MAX_CURRENCY_LEN {
+            return Err(LedgerErr::InvalidTransaction(format!(
+                "Currency is longer than {MAX_CURRENCY_LEN} bytes"
+            )));
+        }
+
+        if let Some(expected) = self
+            .clients
+            .get(&client_id)
+            .and_then(|client| client.currency.as_deref())
+        {
+            if expected != currency {
+                return Err(LedgerErr::CurrencyMismatch {
+                    client: client_id,
+                    expected: expected.to_string(),
+                    found: currency.to_string(),
+                });
+            }
+        }
+
+        self.insert_transaction_at(client_id, transaction_id, amount, line);
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            client.currency.get_or_insert_with(|| currency.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Insert a batch of transactions, producing the same state as calling `insert_transaction`
+    /// for each transaction in order.
+    ///
+    /// Consecutive transactions for the same client share a single client lookup,
+    /// so grouping transactions by client gives the best performance.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_batch(&[(1, 1, Amount(10)), (1, 2, -Amount(5)), (2, 3, Amount(1))]);
+    ///
+    /// assert_eq!(ledger.transactions.len(), 3);
+    /// ```
+    pub fn insert_batch(&mut self, transactions: &[(u16, u32, Amount)]) {
+        // There can be no more new clients than transactions or possible client ids
+        self.clients
+            .reserve(transactions.len().min(usize::from(u16::MAX) + 1));
+
+        for group in transactions.chunk_by(|a, b| a.0 == b.0) {
+            let client_id = group[0].0;
+            let mut group = group.iter();
+
+            let client = match self.clients.entry(client_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let &(_, transaction_id, amount) = group.next().unwrap(); // Groups are never empty
+                    self.transactions
+                        .insert(transaction_id, (client_id, amount, None));
+                    entry.insert(ClientData::new(amount))
+                }
+            };
+
+            for &(_, transaction_id, amount) in group {
+                if client.credit(amount) {
+                    self.transactions
+                        .insert(transaction_id, (client_id, amount, None));
+                }
+            }
+        }
+    }
+
+    /// Opens a dispute on a transaction.
+    /// Disputes that reference a transaction belonging to another client, or a transaction
+    /// that is already disputed, are ignored.
+    ///
+    /// The transaction remains in `transactions` whilst it is held, so that it can be
+    /// disputed again after being resolved.
+    ///
+    /// Disputing a deposit moves its value from `available` to `held`. Disputing a withdrawal
+    /// instead holds the withdrawn value as a pending reversal: `held` and `total` increase by
+    /// the withdrawn value and `available` is left unchanged, as the funds have already left
+    /// the account. In both cases `total = available + held` continues to hold.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(50000));
+    /// ledger.insert_transaction(1, 2, -Amount(20000));
+    /// ledger.hold(1, 2);
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 3.0000, 2.0000, 5.0000, false"
+    /// );
+    /// ```
+    pub fn hold(&mut self, client_id: u16, transaction_id: u32) {
+        // Discard any incorrect inputs
+        if let Some((available, total, held)) = self.held_balances(client_id, transaction_id) {
+            if let Some(client) = self.clients.get_mut(&client_id) {
+                client.available = available;
+                client.total = total;
+                client.held.insert(transaction_id, Amount(held.0.abs()));
+                // Withdrawals are stored with a negative amount
+                if held.0 < 0 {
+                    client.held_withdrawals.insert(transaction_id);
+                } else {
+                    client.held_withdrawals.remove(&transaction_id);
+                }
+                client.dispute_count = client.dispute_count.saturating_add(1);
+                log_debug!("Held transaction {transaction_id} for client {client_id}");
+                return;
+            }
+        }
+        log_warn!("Skipped dispute of transaction {transaction_id} for client {client_id}");
+    }
+
+    /// The available and total funds of a client after disputing one of their transactions,
+    /// along with the signed amount of the transaction. Returns `None` if the dispute would be
+    /// ignored.
+    fn held_balances(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Option<(Amount, Amount, Amount)> {
+        let client = self.clients.get(&client_id)?;
+        let &(owner, amount, _) = self.transactions.get(&transaction_id)?;
+        if owner != client_id || client.held.contains_key(&transaction_id) {
+            return None;
+        }
+        amount.0.checked_abs()?;
+
+        // Withdrawals are stored with a negative amount
+        if amount.0 < 0 {
+            Some((client.available, client.total.checked_sub(amount)?, amount))
+        } else {
+            Some((client.available.checked_sub(amount)?, client.total, amount))
+        }
+    }
+
+    /// Resolves a disputed transaction - the dispute is dropped and the transaction stands.
+    /// Only transactions held by the given client can be resolved.
+    ///
+    /// A resolved deposit has its value added back to the available funds. A resolved
+    /// withdrawal has its pending reversal removed from `held` and `total`, leaving the
+    /// balances as they were before the dispute.
+    pub fn resolve(&mut self, client_id: u16, transaction_id: u32) {
+        // Discard any incorrect inputs
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            if let Some(&amount) = client.held.get(&transaction_id) {
+                let withdrawal = client.held_withdrawals.contains(&transaction_id);
+                if client.release(amount, withdrawal) {
+                    client.held.remove(&transaction_id);
+                    client.held_withdrawals.remove(&transaction_id);
+                    log_debug!("Resolved transaction {transaction_id} for client {client_id}");
+                    return;
+                }
+            }
+        }
+        log_warn!("Skipped resolve of transaction {transaction_id} for client {client_id}");
+    }
+
+    /// Resolves part of a disputed transaction - moves the given amount from held back to the
+    /// available funds, leaving the remainder disputed. Resolving the full held amount is
+    /// equivalent to `resolve`. For a disputed withdrawal, the amount is removed from `held`
+    /// and `total` instead.
+    ///
+    /// Resolves of more than the held amount are ignored.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(50000));
+    /// ledger.hold(1, 1);
+    /// ledger.resolve_partial(1, 1, Amount(30000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 3.0000, 2.0000, 5.0000, false"
+    /// );
+    /// ```
+    pub fn resolve_partial(&mut self, client_id: u16, transaction_id: u32, amount: Amount) {
+        if self.can_resolve(client_id, transaction_id, amount) {
+            if let Some(client) = self.clients.get_mut(&client_id) {
+                let withdrawal = client.held_withdrawals.contains(&transaction_id);
+                if client.release(amount, withdrawal) {
+                    if let Some(held) = client.held.get_mut(&transaction_id) {
+                        held.0 -= amount.0;
+                        if held.0 == 0 {
+                            client.held.remove(&transaction_id);
+                            client.held_withdrawals.remove(&transaction_id);
+                        }
+                    }
+                    log_debug!(
+                        "Partially resolved {amount} of transaction {transaction_id} for client {client_id}"
+                    );
+                    return;
+                }
+            }
+        }
+        log_warn!("Skipped resolve of transaction {transaction_id} for client {client_id}");
+    }
+
+    /// Test if a positive amount, no greater than the held amount, can be resolved.
+    fn can_resolve(&self, client_id: u16, transaction_id: u32, amount: Amount) -> bool {
+        self.clients
+            .get(&client_id)
+            .and_then(|client| client.held.get(&transaction_id))
+            .is_some_and(|&held| amount.0 > 0 && amount <= held)
+    }
+
+    /// Output the account statements of the given clients only, in the order they are provided.
+    /// Returns `LedgerErr::ClientNotFound` if any of the clients are not in the ledger.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.insert_transaction(2, 2, Amount(20000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string_filtered(&[2]).unwrap(),
+    ///     "client, available, held, total, locked\n2, 2.0000, 0.0000, 2.0000, false"
+    /// );
+    /// assert!(ledger.to_string_filtered(&[3]).is_err());
+    /// ```
+    pub fn to_string_filtered(&self, client_ids: &[u16]) -> Result<String, LedgerErr> {
+        let mut buf = Vec::new();
+        self.to_writer(
+            &mut buf,
+            &OutputOptions::default().with_filter_clients(client_ids.to_vec()),
+        )?;
+
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Output the account statements, prefixed with a comment line counting every client and
+    /// stored transaction in the ledger. The `Display` output is otherwise unchanged.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string_with_meta(),
+    ///     "# 1 client, 1 transaction\nclient, available, held, total, locked\n1, 1.0000, 0.0000, 1.0000, false"
+    /// );
+    /// ```
+    pub fn to_string_with_meta(&self) -> String {
+        format!("{}\n{self}", self.meta_comment())
+    }
+
+    /// The comment line counting the clients and transactions in the ledger.
+    fn meta_comment(&self) -> String {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        let (clients, transactions) = (self.clients.len(), self.transactions.len());
+
+        format!(
+            "# {clients} client{}, {transactions} transaction{}",
+            plural(clients),
+            plural(transactions)
+        )
+    }
+
+    /// Output the account statements, rendering the locked status with custom strings.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string_with_bool_format("Y", "N"),
+    ///     "client, available, held, total, locked\n1, 1.0000, 0.0000, 1.0000, N"
+    /// );
+    /// ```
+    pub fn to_string_with_bool_format(&self, true_str: &str, false_str: &str) -> String {
+        let mut buf = Vec::new();
+        let opts = OutputOptions::default().with_locked_labels(true_str, false_str);
+
+        // Writing to a `Vec` without a filter cannot fail
+        self.to_writer(&mut buf, &opts)
+            .expect("Failed to write to buffer");
+
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+
+    /// Write the account statements to a writer, using the provided output options.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::{Ledger, OutputOptions}};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// let mut buf = Vec::new();
+    /// ledger
+    ///     .to_writer(&mut buf, &OutputOptions::default().with_omit_header(true))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(buf).unwrap(), "1, 1.0000, 0.0000, 1.0000, false");
+    /// ```
+    pub fn to_writer<W>(&self, writer: &mut W, opts: &OutputOptions) -> Result<(), LedgerErr>
+    where
+        W: Write + ?Sized,
+    {
+        let precision = opts.precision.unwrap_or(PRECISION);
+        let mut clients: Vec<(&u16, &ClientData)> = if opts.filter_clients.is_empty() {
+            self.clients.iter().collect()
+        } else {
+            opts.filter_clients
+                .iter()
+                .map(|id| {
+                    self.clients
+                        .get_key_value(id)
+                        .ok_or(LedgerErr::ClientNotFound(*id))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        match opts.sort_by {
+            Some(SortBy::Client) => clients.sort_unstable_by_key(|(id, _)| **id),
+            // Keep the order of `filter_clients` if provided, otherwise sort by client id
+            None if opts.filter_clients.is_empty() => clients.sort_unstable_by_key(|(id, _)| **id),
+            Some(SortBy::Total) => {
+                clients.sort_unstable_by_key(|(id, client)| (Reverse(client.total), **id))
+            }
+            Some(SortBy::Available) => {
+                clients.sort_unstable_by_key(|(id, client)| (Reverse(client.available), **id))
+            }
+            None => (),
+        }
+
+        let output = match opts.format {
+            OutputFormat::Csv => {
+                let mut lines = Vec::new();
+                if opts.meta {
+                    lines.push(self.meta_comment());
+                }
+                if !opts.omit_header {
+                    lines.push(HEADER.to_string());
+                }
+                lines.extend(clients.into_iter().map(|(id, client)| {
+                    let mut fields = client.fields(precision);
+                    if let Some((true_str, false_str)) = &opts.locked_labels {
+                        fields[3] = if client.locked { true_str } else { false_str }.clone();
+                    }
+                    format!("{id}, {}", fields.join(", "))
+                }));
+                lines.join("\n")
+            }
+            OutputFormat::Json => json_array(clients, precision),
+        };
+
+        writer
+            .write_all(output.as_bytes())
+            .map_err(LedgerErr::Saving)
+    }
+
+    /// Output the account statements as a JSON array, sorted by client id.
+    /// Monetary values are output as strings to avoid floating-point precision issues.
+    ///
+    /// This method currently cannot fail, but returns a `Result` so that IO errors
+    /// may be returned in a future version.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_json_string().unwrap(),
+    ///     r#"[{"client":1,"available":"1.0000","held":"0.0000","total":"1.0000","locked":false}]"#
+    /// );
+    /// ```
+    pub fn to_json_string(&self) -> Result<String, LedgerErr> {
+        let mut clients: Vec<(&u16, &ClientData)> = self.clients.iter().collect();
+        clients.sort_unstable_by_key(|(id, _)| **id);
+
+        Ok(json_array(clients, PRECISION))
+    }
+
+    /// The sum of all disputed funds held across every client, in ten-thousandths.
+    /// The sum saturates rather than overflows.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.hold(1, 1);
+    ///
+    /// assert_eq!(ledger.total_held(), 10000);
+    /// assert_eq!(ledger.total_held_string(), "1.0000");
+    /// ```
+    pub fn total_held(&self) -> i64 {
+        self.clients
+            .values()
+            .flat_map(|client| client.held.values())
+            .fold(0, |sum, amount| sum.saturating_add(amount.0))
+    }
+
+    /// The sum of all disputed funds held across every client, formatted with four decimal places.
+    pub fn total_held_string(&self) -> String {
+        dp_string(self.total_held())
+    }
+
+    /// The sum of all available funds across every client, in ten-thousandths.
+    /// The sum saturates rather than overflows.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.insert_transaction(2, 2, Amount(5000));
+    /// ledger.hold(2, 2);
+    ///
+    /// assert_eq!(ledger.net_position(), 10000);
+    /// assert_eq!(ledger.gross_total(), 15000);
+    /// ```
+    pub fn net_position(&self) -> i64 {
+        self.clients
+            .values()
+            .fold(0, |sum, client| sum.saturating_add(client.available.0))
+    }
+
+    /// The sum of all account totals across every client, in ten-thousandths.
+    /// The sum saturates rather than overflows.
+    pub fn gross_total(&self) -> i64 {
+        self.clients
+            .values()
+            .fold(0, |sum, client| sum.saturating_add(client.total.0))
+    }
+
+    /// Test that the available and held funds of every account add up to its total, and
+    /// therefore that `net_position() + total_held() == gross_total()`.
+    pub fn validate_invariants(&self) -> bool {
+        self.clients.values().all(|client| {
+            client
+                .held_total_checked()
+                .and_then(|held| client.available.0.checked_add(held))
+                == Some(client.total.0)
+        }) && self.net_position().checked_add(self.total_held()) == Some(self.gross_total())
+    }
+
+    /// A digest of the final account states, for reconciling independent runs. Every client's id,
+    /// available, held, total and locked status are hashed in client id order, so ledgers with the
+    /// same balances hash equal regardless of the order their transactions were applied in.
+    ///
+    /// The hash is 64-bit FNV-1a, which is stable across platforms and Rust versions, but is not
+    /// suitable for detecting deliberate tampering.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut first = Ledger::default();
+    /// first.insert_transaction(1, 1, Amount(10000));
+    /// first.insert_transaction(2, 2, Amount(5000));
+    ///
+    /// let mut second = Ledger::default();
+    /// second.insert_transaction(2, 7, Amount(5000));
+    /// second.insert_transaction(1, 8, Amount(10000));
+    ///
+    /// assert_eq!(first.state_hash(), second.state_hash());
+    /// assert_ne!(first.state_hash(), Ledger::default().state_hash());
+    /// ```
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        self.for_each_client(|id, client| {
+            // The held amounts wrap rather than overflow, as they only need to be deterministic
+            let held = client
+                .held
+                .values()
+                .fold(0_i64, |sum, amount| sum.wrapping_add(amount.0));
+
+            for bytes in [
+                &id.to_le_bytes()[..],
+                &client.available.0.to_le_bytes(),
+                &held.to_le_bytes(),
+                &client.total.0.to_le_bytes(),
+                &[u8::from(client.locked)],
+            ] {
+                hash = fnv1a(hash, bytes);
+            }
+        });
+
+        hash
+    }
+
+    /// The client accounts, by client id. Prefer this over the `clients` field when only reading,
+    /// as it will remain stable if the internal storage changes.
+    pub fn clients(&self) -> &HashMap<u16, ClientData> {
+        &self.clients
+    }
+
+    /// The transactions that can be disputed, by transaction id. Each is stored alongside the id
+    /// of the client that owns it, and the line of the csv file it was read from, if any.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// assert_eq!(ledger.transactions().get(&1), Some(&(1, Amount(10000), None)));
+    /// assert_eq!(ledger.clients().len(), 1);
+    /// ```
+    pub fn transactions(&self) -> &BTreeMap<u32, (u16, Amount, Option<usize>)> {
+        &self.transactions
+    }
+
+    /// Every transaction applied whilst `LedgerOptions::event_log` was set, in the order they were
+    /// applied, alongside the line each was read from. Transactions that were ignored, such as a
+    /// dispute of an unknown transaction, are still recorded. The log is empty by default.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::{Ledger, LedgerOptions}, parse::Transaction};
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,";
+    /// let opts = LedgerOptions::default().with_event_log(true);
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts).unwrap();
+    ///
+    /// assert_eq!(
+    ///     ledger.event_log(),
+    ///     [
+    ///         (2, Transaction::Deposit(1, 1, Amount(10000))),
+    ///         (3, Transaction::Dispute(1, 1))
+    ///     ]
+    /// );
+    /// ```
+    pub fn event_log(&self) -> &[(usize, Transaction)] {
+        &self.event_log
+    }
+
+    /// The ids of all clients, in ascending order.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(2, 1, Amount(10000));
+    /// ledger.insert_transaction(1, 2, Amount(10000));
+    ///
+    /// assert_eq!(ledger.client_ids().collect::<Vec<_>>(), vec![1, 2]);
+    /// ```
+    pub fn client_ids(&self) -> impl Iterator<Item = u16> + '_ {
+        let mut ids: Vec<u16> = self.clients.keys().copied().collect();
+        ids.sort_unstable();
+
+        ids.into_iter()
+    }
+
+    /// Call `f` with every client account, in ascending order of client id.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(2, 1, Amount(10000));
+    /// ledger.insert_transaction(1, 2, Amount(20000));
+    ///
+    /// let mut deposited = Vec::new();
+    /// ledger.for_each_client(|id, client| deposited.push((id, client.total_deposited())));
+    ///
+    /// assert_eq!(deposited, vec![(1, Amount(20000)), (2, Amount(10000))]);
+    /// ```
+    pub fn for_each_client<F>(&self, mut f: F)
+    where
+        F: FnMut(u16, &ClientData),
+    {
+        let mut clients: Vec<(&u16, &ClientData)> = self.clients.iter().collect();
+        clients.sort_unstable_by_key(|(id, _)| **id);
+
+        for (&id, client) in clients {
+            f(id, client);
+        }
+    }
+
+    /// The ids of all locked clients, sorted in ascending order.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.hold(1, 1);
+    /// ledger.chageback(1, 1);
+    ///
+    /// assert_eq!(ledger.locked_clients(), vec![1]);
+    /// ```
+    pub fn locked_clients(&self) -> Vec<u16> {
+        self.clients_where(|client| client.locked)
+    }
+
+    /// The ids of all unlocked clients with a positive total, sorted in ascending order.
+    pub fn active_clients(&self) -> Vec<u16> {
+        self.clients_where(|client| !client.locked && client.total.0 > 0)
+    }
+
+    /// The ids of all clients with a zero total and no disputed funds, sorted in ascending order.
+    pub fn zero_balance_clients(&self) -> Vec<u16> {
+        self.clients_where(|client| client.total.0 == 0 && client.held.is_empty())
+    }
+
+    /// The ids of all clients matching a predicate, sorted in ascending order.
+    fn clients_where<F>(&self, predicate: F) -> Vec<u16>
+    where
+        F: Fn(&ClientData) -> bool,
+    {
+        let mut ids: Vec<u16> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| predicate(client))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+
+        ids
+    }
+
+    /// Output all currently open disputes as csv, sorted by client id and then transaction id.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.hold(1, 1);
+    ///
+    /// assert_eq!(ledger.disputes_csv(), "client, tx, amount\n1, 1, 1.0000");
+    /// ```
+    pub fn disputes_csv(&self) -> String {
+        let mut clients: Vec<(&u16, &ClientData)> = self.clients.iter().collect();
+        clients.sort_unstable_by_key(|(id, _)| **id);
+
+        clients
+            .into_iter()
+            .flat_map(|(id, client)| {
+                client
+                    .held
+                    .iter()
+                    .map(move |(tx, amount)| format!("{id}, {tx}, {}", dp_string(amount.0)))
+            })
+            .fold(String::from("client, tx, amount"), |acc, row| {
+                format!("{acc}\n{row}")
+            })
+    }
+
+    /// Summarise every client account, sorted by client id, including the lifetime totals
+    /// and the number of disputes and chargebacks.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(2, 1, Amount(10000));
+    /// ledger.insert_transaction(1, 2, Amount(10000));
+    /// ledger.hold(2, 1);
+    /// ledger.chageback(2, 1);
+    ///
+    /// let report = ledger.generate_audit_report();
+    /// assert_eq!(report.clients[0].client_id, 1);
+    /// assert_eq!(report.clients[1].chargeback_count, 1);
+    /// assert!(report.clients[1].is_locked);
+    /// ```
+    pub fn generate_audit_report(&self) -> AuditReport {
+        let mut clients: Vec<ClientAudit> = self
+            .clients
+            .iter()
+            .map(|(&client_id, client)| ClientAudit {
+                client_id,
+                final_available: client.available,
+                final_held: Amount(
+                    client
+                        .held
+                        .values()
+                        .fold(0, |sum, amount| sum.saturating_add(amount.0)),
+                ),
+                final_total: client.total,
+                is_locked: client.locked,
+                total_deposited: client.total_deposited,
+                total_withdrawn: client.total_withdrawn,
+                chargeback_count: client.chargeback_count,
+                dispute_count: client.dispute_count,
+            })
+            .collect();
+        clients.sort_unstable_by_key(|client| client.client_id);
+
+        AuditReport { clients }
+    }
+
+    /// Output the account statements as canonical RFC 4180 csv, sorted by client id.
+    ///
+    /// Unlike `Display`, fields are not padded with spaces, every record is terminated by CRLF
+    /// and any field containing a comma, quote or line break is quoted.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// assert_eq!(
+    ///     ledger.to_csv_string(),
+    ///     "client,available,held,total,locked\r\n1,1.0000,0.0000,1.0000,false\r\n"
+    /// );
+    /// ```
+    pub fn to_csv_string(&self) -> String {
+        let mut ids: Vec<&u16> = self.clients.keys().collect();
+        ids.sort_unstable();
+
+        let mut output = csv_record(HEADER.split(", ").map(String::from));
+        for id in ids {
+            let client = &self.clients[id];
+            output.push_str(&csv_record(
+                std::iter::once(id.to_string()).chain(client.fields(PRECISION)),
+            ));
+        }
+
+        output
+    }
+
+    /// Peform a chargeback on a disputed transaction -
+    /// reverses the transaction and locks the account.
+    /// Only transactions held by the given client can be charged back.
+    ///
+    /// A charged back deposit has its held funds removed from the total. `available` is not
+    /// reduced, as the disputed funds were already moved from `available` to `held` by `hold`.
+    ///
+    /// A charged back withdrawal has its pending reversal moved from `held` back to `available`,
+    /// returning the withdrawn funds to the client. `total` is not increased, as `hold` already
+    /// added the withdrawn value to it. In both cases `total = available + held` continues to hold.
+    pub fn chageback(&mut self, client_id: u16, transaction_id: u32) {
+        // Discard any incorrect inputs
+        if let Some((available, total)) = self.charged_back_balances(client_id, transaction_id) {
+            if let Some(client) = self.clients.get_mut(&client_id) {
+                client.held.remove(&transaction_id);
+                client.held_withdrawals.remove(&transaction_id);
+                client.available = available;
+                client.total = total;
+                client.locked = true;
+                client.chargeback_count = client.chargeback_count.saturating_add(1);
+
+                // A charged back transaction has been reversed and cannot be disputed again
+                self.transactions.remove(&transaction_id);
+                self.timestamps.remove(&transaction_id);
+                log_debug!("Charged back transaction {transaction_id} for client {client_id}");
+                return;
+            }
+        }
+        log_warn!("Skipped chargeback of transaction {transaction_id} for client {client_id}");
+    }
+
+    /// The available and total funds of a client after charging back one of their disputed
+    /// transactions. Returns `None` if the chargeback would be ignored.
+    fn charged_back_balances(
+        &self,
+        client_id: u16,
+        transaction_id: u32,
+    ) -> Option<(Amount, Amount)> {
+        let client = self.clients.get(&client_id)?;
+        let &amount = client.held.get(&transaction_id)?;
+        if client.held_withdrawals.contains(&transaction_id) {
+            Some((client.available.checked_add(amount)?, client.total))
+        } else {
+            Some((client.available, client.total.checked_sub(amount)?))
+        }
+    }
+
+    /// Reverse a previously applied transaction, removing its amount from the client's
+    /// available and total funds and forgetting the transaction. The lifetime deposit and
+    /// withdrawal totals are left unchanged.
+    ///
+    /// Returns `LedgerErr::NotFound` if the transaction is not in the ledger,
+    /// `LedgerErr::ClientNotFound` if its client is missing, `LedgerErr::AlreadyDisputed` if it is
+    /// currently disputed, and `LedgerErr::Overflow` if reversing it would overflow the balance.
+    /// The ledger is left unchanged on error.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(50000));
+    /// ledger.insert_transaction(1, 2, Amount(100000));
+    ///
+    /// ledger.rollback(2).unwrap();
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 5.0000, 0.0000, 5.0000, false"
+    /// );
+    /// assert!(ledger.rollback(2).is_err());
+    /// ```
+    pub fn rollback(&mut self, transaction_id: u32) -> Result<(), LedgerErr> {
+        let &(client_id, amount, _) = self
+            .transactions
+            .get(&transaction_id)
+            .ok_or(LedgerErr::NotFound(transaction_id))?;
+
+        let client = self
+            .clients
+            .get_mut(&client_id)
+            .ok_or(LedgerErr::ClientNotFound(client_id))?;
+        if client.held.contains_key(&transaction_id) {
+            return Err(LedgerErr::AlreadyDisputed(transaction_id));
+        }
+
+        let (Some(available), Some(total)) = (
+            client.available.checked_sub(amount),
+            client.total.checked_sub(amount),
+        ) else {
+            return Err(LedgerErr::Overflow(client_id));
+        };
+        client.available = available;
+        client.total = total;
+        self.transactions.remove(&transaction_id);
+        self.timestamps.remove(&transaction_id);
+
+        Ok(())
+    }
+}
+
+/// Shows the number of clients and transactions, as a ledger may hold millions of each.
+/// The alternate format (`{:#?}`) shows every client, transaction and event instead.
+impl fmt::Debug for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let mut debug = f.debug_struct("Ledger");
+        if alternate {
+            debug
+                .field("clients", &self.clients)
+                .field("transactions", &self.transactions)
+                .field("event_log", &self.event_log)
+        } else {
+            debug
+                .field("clients", &self.clients.len())
+                .field("transactions", &self.transactions.len())
+        }
+        .finish()
+    }
+}
+
+/// Outputs the account statements in a human readable csv format, with a space after each comma.
+/// Use `Ledger::to_csv_string` for strict RFC 4180 output.
+impl Display for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{HEADER}{}",
+            self.clients
+                .iter()
+                .fold(String::new(), |acc, (key, value)| format!(
+                    "{acc}\n{key}, {value}"
+                ))
+        )
+    }
+}
+
+/// Construct a ledger by consuming a csv file held in a string.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::ledger::Ledger;
+///
+/// let ledger = Ledger::try_from("type,client,tx,amount\ndeposit,1,1,5.0").unwrap();
+/// assert_eq!(
+///     ledger.to_string(),
+///     "client, available, held, total, locked\n1, 5.0000, 0.0000, 5.0000, false"
+/// );
+///
+/// // The header is malformed
+/// assert!(Ledger::try_from("type,client,tx\ndeposit,1,1,5.0").is_err());
+/// ```
+impl TryFrom<&str> for Ledger {
+    type Error = LedgerErr;
+
+    fn try_from(csv: &str) -> Result<Self, Self::Error> {
+        Ledger::from_csv_string(csv)
+    }
+}
+
+/// Construct a ledger by consuming a csv file held in a byte slice.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::ledger::Ledger;
+///
+/// let ledger = Ledger::try_from(&b"type,client,tx,amount\ndeposit,1,1,5.0"[..]).unwrap();
+/// assert_eq!(ledger.transactions.len(), 1);
+///
+/// // The header is malformed
+/// assert!(Ledger::try_from(&b"type,client,tx,amnt\ndeposit,1,1,5.0"[..]).is_err());
+/// ```
+impl TryFrom<&[u8]> for Ledger {
+    type Error = LedgerErr;
+
+    fn try_from(csv: &[u8]) -> Result<Self, Self::Error> {
+        Ledger::from_csv_bytes(csv)
+    }
+}
+
+/// Construct a ledger by consuming the csv file at the given path.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::{ledger::Ledger, LedgerErr};
+/// use std::{io::Write, path::Path};
+///
+/// let mut file = tempfile::NamedTempFile::new().unwrap();
+/// write!(file, "type,client,tx,amount\ndeposit,1,1,5.0").unwrap();
+///
+/// let ledger = Ledger::try_from(file.path()).unwrap();
+/// assert_eq!(ledger.transactions.len(), 1);
+///
+/// // The file does not exist
+/// assert!(matches!(
+///     Ledger::try_from(Path::new("missing.csv")),
+///     Err(LedgerErr::Opening(_))
+/// ));
+/// ```
+impl TryFrom<&Path> for Ledger {
+    type Error = LedgerErr;
+
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        let file = File::open(path).map_err(LedgerErr::Opening)?;
+        let mut ledger = Ledger::default();
+        ledger.consume_csv(BufReader::new(file))?;
+        Ok(ledger)
+    }
+}
+
+/// Construct a ledger by consuming the csv file at the given path.
+impl TryFrom<PathBuf> for Ledger {
+    type Error = LedgerErr;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Ledger::try_from(path.as_path())
+    }
+}
+
+/// The magic bytes at the start of every saved ledger.
+#[cfg(feature = "persist")]
+const MAGIC: &[u8; 4] = b"CSVL";
+
+/// The version of the saved ledger format, incremented whenever the format changes.
+#[cfg(feature = "persist")]
+const VERSION: u8 = 6;
+
+/// Binary persistence, enabled by the `persist` feature.
+///
+/// The format is a magic header and version byte, followed by each client, each transaction and
+/// then each transaction timestamp. All integers are stored little-endian.
+#[cfg(feature = "persist")]
+impl Ledger {
+    /// Save the state of the ledger to a binary file, so that it can be restored with `load`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    /// use std::path::Path;
+    /// # use std::fs;
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10));
+    ///
+    /// ledger.save(Path::new("./ledger.bin")).unwrap();
+    /// let loaded = Ledger::load(Path::new("./ledger.bin")).unwrap();
+    /// assert_eq!(loaded.transactions, ledger.transactions);
+    /// # fs::remove_file("./ledger.bin").unwrap();
+    /// ```
+    pub fn save(&self, path: &Path) -> Result<(), LedgerErr> {
+        let file = File::create(path).map_err(LedgerErr::Saving)?;
+        self.save_snapshot(BufWriter::new(file))
+    }
+
+    /// Load a ledger previously saved with `save`.
+    /// Returns `LedgerErr::Reading` if the file is not a saved ledger or has an unsupported version.
+    pub fn load(path: &Path) -> Result<Ledger, LedgerErr> {
+        let file = File::open(path).map_err(LedgerErr::Opening)?;
+        Ledger::load_snapshot(BufReader::new(file))
+    }
+
+    /// Write a snapshot of the ledger to a writer, so that processing can be resumed later with
+    /// `load_snapshot`. The snapshot includes every client, held transaction, transaction and
+    /// transaction timestamp.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    ///
+    /// let mut snapshot = Vec::new();
+    /// ledger.save_snapshot(&mut snapshot).unwrap();
+    ///
+    /// // Dispute a transaction recorded before the snapshot was taken
+    /// let mut ledger = Ledger::load_snapshot(snapshot.as_slice()).unwrap();
+    /// ledger
+    ///     .consume_csv(BufReader::new(Cursor::new("type,client,tx,amount\ndispute,1,1,")))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     ledger.to_string(),
+    ///     "client, available, held, total, locked\n1, 0.0000, 1.0000, 1.0000, false"
+    /// );
+    /// ```
+    pub fn save_snapshot<W>(&self, mut writer: W) -> Result<(), LedgerErr>
+    where
+        W: Write,
+    {
+        self.write_binary(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(LedgerErr::Saving)
+    }
+
+    /// Read a ledger from a snapshot previously written with `save_snapshot`.
+    /// Returns `LedgerErr::Reading` if the input is not a snapshot or has an unsupported version.
+    pub fn load_snapshot<R>(mut reader: R) -> Result<Ledger, LedgerErr>
+    where
+        R: Read,
+    {
+        Ledger::read_binary(&mut reader).map_err(LedgerErr::Reading)
+    }
+
+    fn write_binary<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        writer.write_all(&(self.clients.len() as u32).to_le_bytes())?;
+        for (id, client) in &self.clients {
+            writer.write_all(&id.to_le_bytes())?;
+            for amount in [
+                client.available,
+                client.total,
+                client.total_deposited,
+                client.total_withdrawn,
+            ] {
+                writer.write_all(&amount.0.to_le_bytes())?;
+            }
+            writer.write_all(&[u8::from(client.locked)])?;
+            writer.write_all(&[u8::from(client.first_line.is_some())])?;
+            writer.write_all(&(client.first_line.unwrap_or_default() as u64).to_le_bytes())?;
+            writer.write_all(&client.dispute_count.to_le_bytes())?;
+            writer.write_all(&client.chargeback_count.to_le_bytes())?;
+
+            let currency = client.currency.as_deref().unwrap_or_default();
+            if currency.len() > MAX_CURRENCY_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Currency of client {id} is longer than {MAX_CURRENCY_LEN} bytes"),
+                ));
+            }
+            writer.write_all(&[u8::from(client.currency.is_some())])?;
+            writer.write_all(&(currency.len() as u32).to_le_bytes())?;
+            writer.write_all(currency.as_bytes())?;
+
+            writer.write_all(&(client.held.len() as u32).to_le_bytes())?;
+            for (tx, amount) in &client.held {
+                writer.write_all(&tx.to_le_bytes())?;
+                writer.write_all(&amount.0.to_le_bytes())?;
+                writer.write_all(&[u8::from(client.held_withdrawals.contains(tx))])?;
+            }
+        }
+
+        writer.write_all(&(self.transactions.len() as u32).to_le_bytes())?;
+        for (tx, (id, amount, line)) in &self.transactions {
+            writer.write_all(&tx.to_le_bytes())?;
+            writer.write_all(&id.to_le_bytes())?;
+            writer.write_all(&amount.0.to_le_bytes())?;
+            writer.write_all(&[u8::from(line.is_some())])?;
+            writer.write_all(&(line.unwrap_or_default() as u64).to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.timestamps.len() as u32).to_le_bytes())?;
+        for (tx, time) in &self.timestamps {
+            writer.write_all(&tx.to_le_bytes())?;
+            writer.write_all(&time.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn read_binary<R>(reader: &mut R) -> io::Result<Ledger>
+    where
+        R: Read,
+    {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        if &read_bytes::<_, 4>(reader)? != MAGIC {
+            return Err(invalid("File is not a saved ledger".to_string()));
+        }
+        let [version] = read_bytes(reader)?;
+        if version != VERSION {
+            return Err(invalid(format!(
+                "Unsupported saved ledger version {version}"
+            )));
+        }
+
+        let mut ledger = Ledger::default();
+        for _ in 0..u32::from_le_bytes(read_bytes(reader)?) {
+            let id = u16::from_le_bytes(read_bytes(reader)?);
+            let mut client = ClientData::new(Amount(0));
+            client.available = Amount(i64::from_le_bytes(read_bytes(reader)?));
+            client.total = Amount(i64::from_le_bytes(read_bytes(reader)?));
+            client.total_deposited = Amount(i64::from_le_bytes(read_bytes(reader)?));
+            client.total_withdrawn = Amount(i64::from_le_bytes(read_bytes(reader)?));
+            let [locked, has_first_line] = read_bytes(reader)?;
+            let first_line = u64::from_le_bytes(read_bytes(reader)?) as usize;
+            client.locked = locked != 0;
+            client.first_line = (has_first_line != 0).then_some(first_line);
+            client.dispute_count = u32::from_le_bytes(read_bytes(reader)?);
+            client.chargeback_count = u32::from_le_bytes(read_bytes(reader)?);
+
+            let [has_currency] = read_bytes(reader)?;
+            let len = u32::from_le_bytes(read_bytes(reader)?) as usize;
+            if len > MAX_CURRENCY_LEN {
+                return Err(invalid(format!(
+                    "Saved currency is longer than {MAX_CURRENCY_LEN} bytes"
+                )));
+            }
+            let mut currency = vec![0; len];
+            reader.read_exact(&mut currency)?;
+            let currency = String::from_utf8(currency)
+                .map_err(|_| invalid("Saved currency is not valid UTF-8".to_string()))?;
+            client.currency = (has_currency != 0).then_some(currency);
+
+            for _ in 0..u32::from_le_bytes(read_bytes(reader)?) {
+                let tx = u32::from_le_bytes(read_bytes(reader)?);
+                let amount = Amount(i64::from_le_bytes(read_bytes(reader)?));
+                let [withdrawal] = read_bytes(reader)?;
+                client.held.insert(tx, amount);
+                if withdrawal != 0 {
+                    client.held_withdrawals.insert(tx);
+                }
+            }
+
+            ledger.clients.insert(id, client);
+        }
+
+        for _ in 0..u32::from_le_bytes(read_bytes(reader)?) {
+            let tx = u32::from_le_bytes(read_bytes(reader)?);
+            let id = u16::from_le_bytes(read_bytes(reader)?);
+            let amount = Amount(i64::from_le_bytes(read_bytes(reader)?));
+            let [has_line] = read_bytes(reader)?;
+            let line = u64::from_le_bytes(read_bytes(reader)?) as usize;
+            ledger
+                .transactions
+                .insert(tx, (id, amount, (has_line != 0).then_some(line)));
+        }
+
+        for _ in 0..u32::from_le_bytes(read_bytes(reader)?) {
+            let tx = u32::from_le_bytes(read_bytes(reader)?);
+            let time = i64::from_le_bytes(read_bytes(reader)?);
+            ledger.timestamps.insert(tx, time);
+        }
+
+        Ok(ledger)
+    }
+}
+
+/// The initial state of a 64-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Extend a 64-bit FNV-1a hash with the given bytes.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Read a fixed number of bytes from a reader.
+#[cfg(feature = "persist")]
+fn read_bytes<R, const N: usize>(reader: &mut R) -> io::Result<[u8; N]>
+where
+    R: Read,
+{
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Validate the header of the csv file, returning the layout of its columns.
+/// Only the first line of the reader is consumed.
+///
+/// As the header is optional, a first line that is a valid transaction returns the default layout.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::{ledger::validate_header, parse::ParseOptions};
+/// use std::io::{BufReader, Cursor};
+///
+/// let mut reader = BufReader::new(Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0"));
+/// assert!(validate_header(&mut reader, &ParseOptions::default()).is_ok());
+/// ```
+pub fn validate_header<T>(
+    reader: &mut BufReader<T>,
+    opts: &ParseOptions,
+) -> Result<HeaderSchema, LedgerErr>
+where
+    T: Read,
+{
+    let mut buf = String::new();
+    // map_err is used to provide better debug info
+    if reader.read_line(&mut buf).map_err(LedgerErr::Reading)? == 0 {
+        return Err(LedgerErr::EmptyFile);
+    }
+    Ok(detect_header(&buf, opts)?.unwrap_or_else(|| ColumnLayout::from_options(opts)))
+}
+
+/// Detect whether the first line of a csv file is a header, returning the layout of its columns.
+/// Returns `None` if the line is instead a transaction in the default column order, and a
+/// line that is neither is reported as a malformed header.
+///
+/// A line can never be both, as the column names do not parse as a transaction type or id.
+fn detect_header(line: &str, opts: &ParseOptions) -> Result<Option<ColumnLayout>, LedgerErr> {
+    match header_layout(line, opts) {
+        Ok(layout) => Ok(Some(layout)),
+        Err(LedgerErr::Parse { .. })
+            if parse_transaction_with_layout(line, &ColumnLayout::from_options(opts), opts)
+                .is_ok() =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Parse the header line of the csv file, returning the layout of its columns.
+/// A blank header line is treated the same as an empty file, rather than a malformed header.
+fn header_layout(line: &str, opts: &ParseOptions) -> Result<ColumnLayout, LedgerErr> {
+    if line.trim().is_empty() {
+        return Err(LedgerErr::EmptyFile);
+    }
+    parse_header_with_options(line, opts).map_err(|err| LedgerErr::Parse {
+        kind: ParseKind::from(&err),
+        message: err.to_string(),
+        line: 1,
+        raw: Some(line.trim_end().to_string()),
+    })
+}
+
+impl ClientData {
+    fn new(amount: Amount) -> Self {
+        let mut client = ClientData {
+            held: BTreeMap::new(),
+            held_withdrawals: BTreeSet::new(),
+            available: amount,
+            total: amount,
+            locked: false,
+            first_line: None,
+            total_deposited: Amount(0),
+            total_withdrawn: Amount(0),
+            currency: None,
+            dispute_count: 0,
+            chargeback_count: 0,
+        };
+        client.record(amount);
+
+        client
+    }
+
+    /// The line of the csv that first introduced the client.
+    /// Returns `None` if the client was not created from a csv file.
+    pub fn first_line(&self) -> Option<usize> {
+        self.first_line
+    }
+
+    /// The sum of every deposit ever applied to the account.
+    pub fn total_deposited(&self) -> Amount {
+        self.total_deposited
+    }
+
+    /// The sum of every withdrawal ever applied to the account, as a positive amount.
+    pub fn total_withdrawn(&self) -> Amount {
+        self.total_withdrawn
+    }
+
+    /// The currency of the account, set by its first transaction with a currency.
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    /// The number of disputes ever opened on the account.
+    pub fn dispute_count(&self) -> u32 {
+        self.dispute_count
+    }
+
+    /// The number of chargebacks ever performed on the account.
+    pub fn chargeback_count(&self) -> u32 {
+        self.chargeback_count
+    }
+
+    /// The currently disputed transactions of the account, mapped to the amount held for each.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.insert_transaction(1, 2, Amount(5000));
+    /// ledger.hold(1, 2);
+    ///
+    /// let held = ledger.clients()[&1].held_transactions();
+    /// assert_eq!(held.iter().collect::<Vec<_>>(), vec![(&2, &Amount(5000))]);
+    /// ```
+    pub fn held_transactions(&self) -> &BTreeMap<u32, Amount> {
+        &self.held
+    }
+
+    /// The sum of the disputed funds held for this account, or `None` if it would overflow.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::{amount::Amount, ledger::Ledger};
+    ///
+    /// let mut ledger = Ledger::default();
+    /// ledger.insert_transaction(1, 1, Amount(10000));
+    /// ledger.hold(1, 1);
+    ///
+    /// assert_eq!(ledger.clients()[&1].held_total_checked(), Some(10000));
+    /// ```
+    pub fn held_total_checked(&self) -> Option<i64> {
+        self.held
+            .values()
+            .try_fold(0_i64, |sum, amount| sum.checked_add(amount.0))
+    }
+}
+
+/// Outputs the account fields. The alternate form (`{:#}`) additionally outputs the lifetime
+/// deposit and withdrawal totals.
+impl Display for ClientData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fields(PRECISION).join(", "))?;
+        if f.alternate() {
+            write!(f, ", {}, {}", self.total_deposited, self.total_withdrawn)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ClientData {
+    /// Add an amount to the balance of an unlocked account, returning whether it was applied.
+    /// Discards any amount that would overflow the balance.
+    fn credit(&mut self, amount: Amount) -> bool {
+        match self.credited(amount) {
+            Some((available, total)) => {
+                self.total = total;
+                self.available = available;
+                self.record(amount);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The available and total funds after crediting an amount, or `None` if it would be
+    /// discarded.
+    fn credited(&self, amount: Amount) -> Option<(Amount, Amount)> {
+        if self.locked {
+            return None;
+        }
+
+        Some((
+            self.available.checked_add(amount)?,
+            self.total.checked_add(amount)?,
+        ))
+    }
+
+    /// Release a resolved amount from dispute, returning whether it could be applied.
+    /// A resolved deposit is returned to `available`, whilst a resolved withdrawal is removed
+    /// from `total`, as the withdrawn funds have already left the account.
+    fn release(&mut self, amount: Amount, withdrawal: bool) -> bool {
+        match self.released(amount, withdrawal) {
+            Some((available, total)) => {
+                self.available = available;
+                self.total = total;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The available and total funds after releasing a resolved amount, or `None` if it could
+    /// not be applied.
+    fn released(&self, amount: Amount, withdrawal: bool) -> Option<(Amount, Amount)> {
+        if withdrawal {
+            Some((self.available, self.total.checked_sub(amount)?))
+        } else {
+            Some((self.available.checked_add(amount)?, self.total))
+        }
+    }
+
+    /// Add an amount to the lifetime deposit or withdrawal totals.
+    /// The totals saturate rather than overflow, as they do not affect the balance.
+    fn record(&mut self, amount: Amount) {
+        if amount.0 > 0 {
+            self.total_deposited = Amount(self.total_deposited.0.saturating_add(amount.0));
+        } else {
+            self.total_withdrawn = Amount(self.total_withdrawn.0.saturating_sub(amount.0));
+        }
+    }
+
+    /// The output fields of the account: available, held, total and locked.
+    /// A held sum that would overflow is output as `OVERFLOW`.
+    fn fields(&self, precision: u32) -> [String; 4] {
+        [
+            self.available.as_decimal_string_with_precision(precision),
+            self.held_total_checked().map_or_else(
+                || "OVERFLOW".to_string(),
+                |held| Amount(held).as_decimal_string_with_precision(precision),
+            ),
+            self.total.as_decimal_string_with_precision(precision),
+            self.locked.to_string(),
+        ]
+    }
+}
+
+/// Reads the logical records of a csv file.
+/// When quoting is enabled, physical lines are joined until the quotes of a record are balanced,
+/// allowing quoted fields to contain newlines.
+struct Records<T> {
+    reader: BufReader<T>,
+    quoting: bool,
+    max_line_length: usize,
+    /// The number of physical lines read so far.
+    lines: u64,
+    /// Whether the last line read was terminated by a line ending.
+    terminated: bool,
+    /// A line that was read and then returned, to be read again.
+    pending: Option<String>,
+}
+
+impl<T> Records<T>
+where
+    T: Read,
+{
+    fn new(reader: BufReader<T>, opts: &LedgerOptions) -> Self {
+        Records {
+            reader,
+            quoting: opts.parse.quoting,
+            max_line_length: opts.max_line_length,
+            lines: 0,
+            terminated: true,
+            pending: None,
+        }
+    }
+
+    /// Return a line that has already been read, so that the next read yields it again.
+    fn unread(&mut self, line: String) {
+        self.pending = Some(line);
+    }
+
+    /// Read the next record, returning the zero-based index of its first line alongside it.
+    /// A record that spans multiple lines is limited to the maximum line length in total.
+    fn next_record(&mut self) -> io::Result<Option<(usize, String)>> {
+        let index = self.lines as usize - usize::from(self.pending.is_some());
+        let mut record = match self.next_line()? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+
+        // An odd number of quotes means a quoted field continues onto the next line
+        let mut open = record.matches('"').count() % 2 == 1;
+        while self.quoting && open {
+            match self.next_line()? {
+                Some(line) => {
+                    if record.len() + 1 + line.len() > self.max_line_length {
+                        return Err(record_too_long(index + 1, self.max_line_length));
+                    }
+                    open ^= line.matches('"').count() % 2 == 1;
+                    record.push('\n');
+                    record.push_str(&line);
+                }
+                None => break,
+            }
+        }
+
+        Ok(Some((index, record)))
+    }
+
+    /// Read the next physical line, without its line ending.
+    /// Reading stops as soon as the line exceeds the maximum length, so memory use is bounded.
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+
+        let mut buf = Vec::new();
+
+        // Allow room for a CRLF line ending on top of the maximum length
+        let limit = self.max_line_length as u64 + 2;
+        if self
+            .reader
+            .by_ref()
+            .take(limit)
+            .read_until(b'\n', &mut buf)?
+            == 0
+        {
+            return Ok(None);
+        }
+        self.lines += 1;
+        self.terminated = buf.ends_with(b"\n");
+
+        if self.terminated {
+            buf.pop();
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
+        }
+
+        if buf.len() > self.max_line_length {
+            return Err(line_too_long(self.lines, self.max_line_length));
+        }
+
+        String::from_utf8(buf)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Parse a record that was read starting at the zero-based line `index`.
+/// Only a record that was not `terminated` by a line ending can be reported as truncated.
+fn parse_record(
+    record: &str,
+    index: usize,
+    terminated: bool,
+    layout: &ColumnLayout,
+    opts: &ParseOptions,
+) -> Result<Transaction, LedgerErr> {
+    check_field_count(record, index + 1, layout, opts)?;
+    parse_transaction_with_layout(record, layout, opts).map_err(|err| {
+        // Only the final record can be missing its line ending
+        if terminated || !is_truncated(record, layout, opts) {
+            LedgerErr::from_line(err, record, index + 1)
+        } else {
+            LedgerErr::Parse {
+                kind: ParseKind::Incomplete,
+                message: format!("Truncated final record at line {}", index + 1),
+                line: index + 1,
+                raw: Some(record.to_string()),
+            }
+        }
+    })
+}
+
+/// Test that a record does not have more fields than the layout describes, unless extra columns
+/// are allowed. Too few fields are left to the parser, as a dispute may omit its amount.
+fn check_field_count(
+    record: &str,
+    line: usize,
+    layout: &ColumnLayout,
+    opts: &ParseOptions,
+) -> Result<(), LedgerErr> {
+    let expected = layout.column_count();
+    match fields(record, opts.delimiter, opts.quoting).count() {
+        found if found > expected && !opts.allow_extra_columns => Err(LedgerErr::WrongFieldCount {
+            expected,
+            found,
+            line,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The error for a line that is longer than the maximum length.
+fn line_too_long(line: u64, max_line_length: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Line {line} exceeds the maximum length of {max_line_length} bytes"),
+    )
+}
+
+/// The error for a quoted record, spanning multiple lines, that is longer than the maximum length.
+fn record_too_long(line: usize, max_line_length: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Record starting at line {line} exceeds the maximum length of {max_line_length} bytes"
+        ),
+    )
+}
+
+/// Read the next physical line from an asynchronous reader, without its line ending, alongside
+/// whether it was terminated by one. `lines` counts the lines read so far.
+/// Reading stops as soon as the line exceeds the maximum length, so memory use is bounded.
+#[cfg(feature = "tokio")]
+async fn read_line_async<R>(
+    reader: &mut R,
+    max_line_length: usize,
+    lines: &mut u64,
+) -> io::Result<Option<(String, bool)>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut buf = Vec::new();
+
+    // Allow room for a CRLF line ending on top of the maximum length
+    let limit = max_line_length as u64 + 2;
+    if (&mut *reader)
+        .take(limit)
+        .read_until(b'\n', &mut buf)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    *lines += 1;
+    let terminated = buf.ends_with(b"\n");
+
+    if terminated {
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+    }
+
+    if buf.len() > max_line_length {
+        return Err(line_too_long(*lines, max_line_length));
+    }
+
+    String::from_utf8(buf)
+        .map(|line| Some((line, terminated)))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// The rows skipped and applied whilst consuming a csv file, checked against the limits in the
+/// options.
+struct RowLimits<'a> {
+    opts: &'a LedgerOptions,
+    /// The number of rows skipped by `LedgerOptions::skip_rows` so far.
+    skipped: usize,
+    /// The number of rows applied so far.
+    rows: usize,
+    /// The number of rows that changed the ledger so far, counted against
+    /// `LedgerOptions::max_transactions`.
+    applied: usize,
+}
+
+impl<'a> RowLimits<'a> {
+    fn new(opts: &'a LedgerOptions) -> Self {
+        RowLimits {
+            opts,
+            skipped: 0,
+            rows: 0,
+            applied: 0,
+        }
+    }
+}
+
+/// Test if the row limit has been reached, once `rows` have been processed.
+/// Returns `LedgerErr::RowLimitExceeded` if the limit should fail rather than stop consuming.
+fn row_limit_reached(rows: usize, opts: &LedgerOptions) -> Result<bool, LedgerErr> {
+    match opts.max_rows {
+        Some(max) if rows >= max && opts.fail_on_row_limit => Err(LedgerErr::RowLimitExceeded(max)),
+        Some(max) => Ok(rows >= max),
+        None => Ok(false),
+    }
+}
+
+/// Format client accounts as a JSON array.
+fn json_array(clients: Vec<(&u16, &ClientData)>, precision: u32) -> String {
+    let entries: Vec<String> = clients
+        .into_iter()
+        .map(|(id, client)| {
+            let [available, held, total, locked] = client.fields(precision);
+            format!(
+                "{{\"client\":{id},\"available\":\"{available}\",\"held\":\"{held}\",\"total\":\"{total}\",\"locked\":{locked}}}"
+            )
+        })
+        .collect();
+
+    format!("[{}]", entries.join(","))
+}
+
+/// Test if a record that failed to parse appears to have been cut short, either by missing
+/// trailing columns, ending on a decimal separator or leaving a quoted field open.
+fn is_truncated(record: &str, layout: &ColumnLayout, opts: &ParseOptions) -> bool {
+    fields(record, opts.delimiter, opts.quoting).count() < layout.column_count()
+        || record.trim_end().ends_with(opts.decimal_separator)
+        || (opts.quoting && record.matches('"').count() % 2 == 1)
+}
+
+/// A transaction read from a JSON array.
+#[cfg(feature = "json-input")]
+#[derive(Deserialize)]
+struct JsonTransaction {
+    #[serde(rename = "type")]
+    kind: String,
+    client: u16,
+    tx: u32,
+    #[serde(default)]
+    amount: Value,
+    #[serde(default)]
+    timestamp: Option<String>,
+    /// Any other fields, which are rejected unless extra columns are allowed.
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+#[cfg(feature = "json-input")]
+impl JsonTransaction {
+    /// Validate the transaction and read its time, parsing the amount and timestamp in the same
+    /// way as the csv parser.
+    fn to_transaction(&self, opts: &ParseOptions) -> Result<(Transaction, Option<i64>), LedgerErr> {
+        let invalid = |msg: &str| LedgerErr::InvalidTransaction(msg.to_string());
+
+        // Without the timestamp option, a timestamp is an unknown field like any other
+        let unknown = !self.extra.is_empty() || (self.timestamp.is_some() && !opts.timestamp);
+        if unknown && !opts.allow_extra_columns {
+            return Err(invalid("Unexpected extra fields in transaction"));
+        }
+        let time = match &self.timestamp {
+            Some(time) if opts.timestamp => Some(
+                all_consuming(timestamp)(time.trim())
+                    .map_err(|_| invalid("Timestamp is not a valid ISO-8601 time"))?
+                    .1,
+            ),
+            _ => None,
+        };
+
+        // A blank amount is treated as no amount, as an empty csv field is
+        let amount = match &self.amount {
+            Value::Null => None,
+            Value::Number(number) => Some(number.to_string()),
+            Value::String(string) if string.trim().is_empty() => None,
+            Value::String(string) => Some(string.trim().to_string()),
+            _ => return Err(invalid("Amount must be a number or a string")),
+        };
+        let amount = amount
+            .map(|amount| {
+                all_consuming(|i| fixed_point(i, '.', opts.max_decimal_places, 4))(amount.as_str())
+                    .map(|(_, value)| Amount(value))
+                    .map_err(|_| invalid("Amount is not a valid number"))
+            })
+            .transpose()?;
+
+        let (client, tx) = (self.client, self.tx);
+        let transaction = match (self.kind.as_str(), amount) {
+            ("deposit", Some(amount)) => Transaction::Deposit(client, tx, amount),
+            ("withdrawal", Some(amount)) => Transaction::Withdrawal(client, tx, amount),
+            ("deposit" | "withdrawal", None) => {
+                return Err(invalid("Deposit or Withdrawal is missing an amount"))
+            }
+            ("dispute", None) => Transaction::Dispute(client, tx),
+            ("resolve", None) => Transaction::Resolve(client, tx),
+            ("resolve", Some(amount)) => Transaction::PartialResolve(client, tx, amount),
+            ("chargeback", None) => Transaction::Chargeback(client, tx),
+            ("dispute" | "chargeback", Some(_)) => {
+                return Err(invalid("Dispute or Chargeback with an amount"))
+            }
+            _ => return Err(invalid("Unknown transaction type")),
+        };
+
+        Ok((transaction, time))
+    }
+}
+
+/// Describe a transaction for the applied transaction log.
+fn log_entry(transaction: &Transaction) -> String {
+    match transaction {
+        Transaction::Deposit(client, tx, amount) => {
+            format!(
+                "Deposit client={client} tx={tx} amount={}",
+                dp_string(amount.0)
+            )
+        }
+        Transaction::Withdrawal(client, tx, amount) => {
+            format!(
+                "Withdrawal client={client} tx={tx} amount={}",
+                dp_string(amount.0)
+            )
+        }
+        Transaction::Dispute(client, tx) => format!("Dispute client={client} tx={tx}"),
+        Transaction::Resolve(client, tx) => format!("Resolve client={client} tx={tx}"),
+        Transaction::PartialResolve(client, tx, amount) => format!(
+            "PartialResolve client={client} tx={tx} amount={}",
+            dp_string(amount.0)
+        ),
+        Transaction::Chargeback(client, tx) => format!("Chargeback client={client} tx={tx}"),
+    }
+}
+
+/// Format fields as a single RFC 4180 csv record, terminated by CRLF.
+fn csv_record<I>(fields: I) -> String
+where
+    I: Iterator<Item = String>,
+{
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+
+    format!("{}\r\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod csv_record {
+    use super::csv_record;
+
+    #[test]
+    fn ok() {
+        assert_eq!(
+            csv_record(["a", "b,c", "d\"e"].into_iter().map(String::from)),
+            "a,\"b,c\",\"d\"\"e\"\r\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod validate_header {
+    use super::validate_header;
+    use crate::parse::{ColumnLayout, ParseOptions};
+    use std::io::{BufReader, Cursor, Error, ErrorKind, Read};
+
+    struct TestReader {}
+
+    impl Read for TestReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
+        }
+    }
+
+    #[test]
+    fn ok() {
+        validate_header(
+            &mut BufReader::new(Cursor::new("type, client, tx, amount")),
+            &ParseOptions::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn ok_extra_columns() {
+        validate_header(
+            &mut BufReader::new(Cursor::new("type, client, tx, amount, notes")),
+            &ParseOptions::default().with_allow_extra_columns(true),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn ok_no_header() {
+        let layout = validate_header(
+            &mut BufReader::new(Cursor::new("amount, tx, client, type\ndeposit, 1, 1, 1.0")),
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(layout.amount_col, 0);
+
+        // A headerless file is read with the default layout
+        let layout = validate_header(
+            &mut BufReader::new(Cursor::new("deposit, 1, 1, 1.0\n")),
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(layout, ColumnLayout::default());
+    }
+
+    #[test]
+    fn err_code() {
+        let opts = ParseOptions::default();
+        let err = validate_header(&mut BufReader::new(Cursor::new("type,")), &opts).unwrap_err();
+        assert_eq!(err.code(), "parse.failure");
+
+        let err = validate_header(&mut BufReader::new(TestReader {}), &opts).unwrap_err();
+        assert_eq!(err.code(), "read");
+    }
+
+    #[test]
+    fn err_empty_or_malformed() {
+        let opts = ParseOptions::default();
+
+        for input in ["", "\n", "  \r\ndeposit,1,1,1.0"] {
+            let err = validate_header(&mut BufReader::new(Cursor::new(input)), &opts).unwrap_err();
+            assert_eq!(
+                err.to_plain(),
+                "error: reading in the csv: The file is empty or missing its header"
+            );
+        }
+
+        let err =
+            validate_header(&mut BufReader::new(Cursor::new("type, client\n")), &opts).unwrap_err();
+        assert!(matches!(err, crate::LedgerErr::Parse { line: 1, .. }));
+        assert!(err.to_plain().starts_with("error: parsing csv: "));
+    }
+
+    #[test]
+    fn err_runthrough() {
+        let opts = ParseOptions::default();
+        validate_header(&mut BufReader::new(TestReader {}), &opts).unwrap_err();
+        assert!(matches!(
+            validate_header(&mut BufReader::new(Cursor::new("")), &opts),
+            Err(crate::LedgerErr::EmptyFile)
+        ));
+        assert!(matches!(
+            validate_header(&mut BufReader::new(Cursor::new("\n")), &opts),
+            Err(crate::LedgerErr::EmptyFile)
+        ));
+        validate_header(&mut BufReader::new(Cursor::new("type,")), &opts).unwrap_err();
+        validate_header(
+            &mut BufReader::new(Cursor::new("type, client, tx, amount, notes")),
+            &opts,
+        )
+        .unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod client_data {
+    use super::ClientData;
+    use crate::amount::Amount;
+
+    #[test]
+    fn debug() {
+        let data = ClientData::new(Amount(10));
+
+        assert_eq!(
+            format!("{:?}", data),
+            "ClientData { held: {}, held_withdrawals: {}, available: Amount(10), total: Amount(10), locked: false, first_line: None, total_deposited: Amount(10), total_withdrawn: Amount(0), currency: None, dispute_count: 0, chargeback_count: 0 }"
+        );
+    }
+
+    #[test]
+    fn display() {
+        let mut data = ClientData::new(Amount(10));
+        data.credit(-Amount(4));
+
+        assert_eq!(format!("{}", data), "0.0006, 0.0000, 0.0006, false");
+        assert_eq!(
+            format!("{:#}", data),
+            "0.0006, 0.0000, 0.0006, false, 0.0010, 0.0004"
+        );
+    }
+
+    #[test]
+    fn record_saturates() {
+        let mut data = ClientData::new(Amount(i64::MAX));
+        data.credit(-Amount(i64::MAX));
+        data.credit(Amount(1));
+
+        assert_eq!(data.total, Amount(1));
+        assert_eq!(data.total_deposited(), Amount(i64::MAX));
+        assert_eq!(data.total_withdrawn(), Amount(i64::MAX));
+    }
+
+    #[test]
+    fn held_total_overflow() {
+        let mut data = ClientData::new(Amount(0));
+        data.held.insert(1, Amount(i64::MAX));
+        assert_eq!(data.held_total_checked(), Some(i64::MAX));
+
+        data.held.insert(2, Amount(1));
+        assert_eq!(data.held_total_checked(), None);
+        assert_eq!(format!("{}", data), "0.0000, OVERFLOW, 0.0000, false");
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod ledger {
+    use super::{ClientData, Ledger, LedgerOptions, OutputFormat, OutputOptions, SortBy};
+    use crate::{
+        amount::Amount,
+        audit::ClientAudit,
+        parse::{parse_header, ColumnLayout, ParseOptions, Transaction, Trim, MAX_CURRENCY_LEN},
+        LedgerErr,
+    };
+    use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Write};
+
+    struct TestReader {}
+
+    impl Read for TestReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
+        }
+    }
+
+    struct TestReaderTwo<'a> {
+        inner: Cursor<&'a str>,
+        state: bool,
+    }
+
+    // Fail after second read
+    impl Read for TestReaderTwo<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.state {
+                Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
+            } else {
+                self.state = true;
+                Ok(self.inner.read(buf).unwrap())
+            }
+        }
+    }
+
+    #[test]
+    fn ok_consume() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount
+                
+                deposit, 1, 1, 20.0
+                withdrawal,1,2,10.0
+                dispute,1,2,
+                resolve,1,2,
+            
+                deposit,2,3,113.1112
+                dispute,2,3,
+                chargeback,2,3,
+                
+                ",
+            )))
+            .unwrap();
+
+        let result = ledger.to_string();
+        let mut lines = result.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "client, available, held, total, locked"
+        );
+
+        let accounts = [
+            "1, 10.0000, 0.0000, 10.0000, false",
+            "2, 0.0000, 0.0000, 0.0000, true",
+        ];
+
+        assert!(accounts.contains(&lines.next().unwrap()));
+        assert!(accounts.contains(&lines.next().unwrap()));
+        assert!(lines.next().is_none())
+    }
+
+    #[test]
+    fn consume_csv_with_log() {
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 1.0\n\
+            \n\
+            withdrawal, 1, 2, 0.25\n\
+            dispute, 1, 1,\n\
+            resolve, 1, 1,\n\
+            chargeback, 1, 1,";
+
+        let mut ledger = Ledger::default();
+        let mut log = Vec::new();
+        let rows = ledger
+            .consume_csv_with_log(
+                BufReader::new(Cursor::new(csv)),
+                &LedgerOptions::default(),
+                Some(&mut log),
+            )
+            .unwrap();
+
+        assert_eq!(rows, 5);
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "line 2: applied Deposit client=1 tx=1 amount=1.0000\n\
+            line 4: applied Withdrawal client=1 tx=2 amount=0.2500\n\
+            line 5: applied Dispute client=1 tx=1\n\
+            line 6: applied Resolve client=1 tx=1\n\
+            line 7: applied Chargeback client=1 tx=1\n"
+        );
+    }
+
+    #[test]
+    fn consume_currency() {
+        let csv = "type, client, tx, amount, currency\n\
+            deposit, 1, 1, 5.0, EUR\n\
+            withdrawal, 1, 2, 1.0, EUR\n\
+            dispute, 1, 1, ,\n\
+            deposit, 2, 3, 2.0, USD\n\
+            deposit, 3, 4, 1.0,";
+
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(csv)))
+            .unwrap();
+
+        assert_eq!(ledger.clients.get(&1).unwrap().currency(), Some("EUR"));
+        assert_eq!(ledger.clients.get(&2).unwrap().currency(), Some("USD"));
+        assert_eq!(ledger.clients.get(&3).unwrap().currency(), None);
+        assert_eq!(
+            ledger.to_string_filtered(&[1]).unwrap(),
+            "client, available, held, total, locked\n1, -1.0000, 5.0000, 4.0000, false"
+        );
+    }
+
+    #[test]
+    fn err_consume_currency() {
+        let csv = "type, client, tx, amount, currency\n\
+            deposit, 1, 1, 5.0, EUR\n\
+            deposit, 1, 2, 1.0, USD";
+
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(csv)))
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            LedgerErr::CurrencyMismatch { client: 1, ref expected, ref found }
+                if expected == "EUR" && found == "USD"
+        ));
+        assert!(!ledger.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn err_currency_too_long() {
+        let currency = "X".repeat(MAX_CURRENCY_LEN + 1);
+
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(format!(
+                "type, client, tx, amount, currency\ndeposit, 1, 1, 5.0, {currency}"
+            ))))
+            .unwrap_err();
+        assert!(err.code().starts_with("parse"), "{err:?}");
+
+        let err = ledger
+            .insert_transaction_with_currency(1, 1, Amount(1), &currency)
+            .unwrap_err();
+        assert!(matches!(err, LedgerErr::InvalidTransaction(_)));
+        assert!(ledger.clients.is_empty());
+    }
+
+    #[test]
+    fn merge_currency() {
+        let mut ledger = Ledger::default();
+        ledger
+            .insert_transaction_with_currency(1, 1, Amount(10), "EUR")
+            .unwrap();
+        ledger.insert_transaction(2, 2, Amount(10));
+
+        // A client without a currency takes on the currency of the other ledger
+        let mut other = Ledger::default();
+        other
+            .insert_transaction_with_currency(2, 3, Amount(5), "USD")
+            .unwrap();
+        ledger.merge(other).unwrap();
+
+        let c = ledger.clients.get(&2).unwrap();
+        assert_eq!((c.total, c.currency()), (Amount(15), Some("USD")));
+
+        // Conflicting currencies are rejected without changing the ledger
+        let mut other = Ledger::default();
+        other
+            .insert_transaction_with_currency(1, 4, Amount(5), "USD")
+            .unwrap();
+        other.insert_transaction(3, 5, Amount(5));
+        let err = ledger.merge(other).unwrap_err();
+
+        assert!(matches!(
+            err,
+            LedgerErr::CurrencyMismatch { client: 1, ref expected, ref found }
+                if expected == "EUR" && found == "USD"
+        ));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.total, c.currency()), (Amount(10), Some("EUR")));
+        assert!(!ledger.clients.contains_key(&3));
+        assert_eq!(ledger.transactions.len(), 3);
+    }
+
+    #[test]
+    fn err_truncated_final_record() {
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type,client,tx,amount\ndeposit,1,1,1.",
+            )))
+            .unwrap_err();
+
+        assert_eq!(err.code(), "parse.incomplete");
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst parsing csv: \"Truncated final record at line 2\", At line: 2, Content: \"deposit,1,1,1.\""
+        );
+
+        // A complete final record without a line ending is valid
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type,client,tx,amount\ndeposit,1,1,1.5",
+            )))
+            .unwrap();
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(15000));
+
+        // A final record cut short before its last columns
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1",
+            )))
+            .unwrap_err();
+        assert!(err.to_string().contains("Truncated final record at line 3"));
+
+        // Malformed lines that are terminated are reported as usual
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type,client,tx,amount\ndeposit,1,1,1.\n",
+            )))
+            .unwrap_err();
+        assert!(!err.to_string().contains("Truncated"));
+    }
+
+    #[test]
+    #[cfg(feature = "json-input")]
+    fn consume_json() {
+        let json = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": 5.0},
+            {"type": "deposit", "client": 2, "tx": 2, "amount": "2.5"},
+            {"type": "withdrawal", "client": 1, "tx": 3, "amount": 1},
+            {"type": "dispute", "client": 2, "tx": 2, "amount": null},
+            {"type": "dispute", "client": 1, "tx": 1},
+            {"type": "chargeback", "client": 1, "tx": 1}
+        ]"#;
+
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.consume_json(json.as_bytes()).unwrap(), 6);
+
+        assert_eq!(
+            ledger.to_string_filtered(&[1, 2]).unwrap(),
+            "client, available, held, total, locked\n\
+            1, -1.0000, 0.0000, -1.0000, true\n\
+            2, 0.0000, 2.5000, 2.5000, false"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json-input")]
+    fn err_consume_json() {
+        let mut ledger = Ledger::default();
+
+        for (json, line) in [
+            (r#"{"type": "deposit"}"#, 1),
+            (r#"[{"type": "deposit", "client": 1, "tx": 1}]"#, 1),
+            (
+                r#"[{"type": "deposit", "client": 1, "tx": 1, "amount": 1},
+                {"type": "foo", "client": 1, "tx": 2}]"#,
+                2,
+            ),
+            (
+                r#"[{"type": "dispute", "client": 1, "tx": 1, "amount": 1}]"#,
+                1,
+            ),
+            (
+                r#"[{"type": "deposit", "client": 1, "tx": 1, "amount": -1}]"#,
+                1,
+            ),
+            (
+                r#"[{"type": "deposit", "client": 1, "tx": 1, "amount": [1]}]"#,
+                1,
+            ),
+        ] {
+            let err = ledger.consume_json(json.as_bytes()).unwrap_err();
+            assert!(
+                matches!(err, LedgerErr::Parse { line: l, .. } if l == line),
+                "{json}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json-input")]
+    fn consume_json_with_options() {
+        let json = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": 5.0},
+            {"type": "deposit", "client": 1, "tx": 2, "amount": 0, "timestamp": "2023-01-01T00:00:00Z"},
+            {"type": "deposit", "client": 1, "tx": 3, "amount": "1.25", "note": "foo"}
+        ]"#;
+        let opts = LedgerOptions::default()
+            .with_skip_rows(1)
+            .with_timestamp(true)
+            .with_allow_extra_columns(true)
+            .with_max_decimal_places(2);
+
+        // Zero amounts are accepted, as they are in a csv file
+        let mut ledger = Ledger::default();
+        assert_eq!(
+            ledger
+                .consume_json_with_options(json.as_bytes(), &opts)
+                .unwrap(),
+            2
+        );
+        assert_eq!(ledger.clients[&1].total, Amount(12500));
+        assert_eq!(ledger.transaction_timestamp(2), Some(1672531200));
+
+        // Each option is enforced as it is for a csv file
+        for (opts, line) in [
+            (LedgerOptions::default(), 2),
+            (opts.clone().with_allow_extra_columns(false), 3),
+            (
+                opts.clone()
+                    .with_timestamp(false)
+                    .with_allow_extra_columns(false),
+                2,
+            ),
+            (opts.with_max_decimal_places(1), 3),
+        ] {
+            let err = Ledger::default()
+                .consume_json_with_options(json.as_bytes(), &opts)
+                .unwrap_err();
+            assert!(
+                matches!(err, LedgerErr::Parse { line: l, .. } if l == line),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn consume_header_only() {
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new("type, client, tx, amount\n")),
+                &LedgerOptions::default(),
+            )
+            .unwrap();
+
+        assert_eq!(rows, 0);
+        assert!(ledger.clients.is_empty());
+    }
+
+    #[test]
+    fn err_consume_runthrough() {
+        let mut ledger = Ledger::default();
+
+        assert!(matches!(
+            ledger.consume_csv(BufReader::new(Cursor::new(""))),
+            Err(LedgerErr::EmptyFile)
+        ));
+        assert!(matches!(
+            ledger.consume_csv(BufReader::new(Cursor::new("\n"))),
+            Err(LedgerErr::EmptyFile)
+        ));
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(&[0x0])))
+            .unwrap_err();
+
+        ledger
+            .consume_csv(BufReader::new(TestReader {}))
+            .unwrap_err();
+
+        ledger
+            .consume_csv(BufReader::new(TestReaderTwo {
+                inner: Cursor::new("type, client, tx, amount\n"),
+                state: false,
+            }))
+            .unwrap_err();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new("type, client, tx, amount\n123")))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn ok_consume_max_rows() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_rows(2);
+
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\n\ndeposit,2,2,1.0\ndeposit,3,3,1.0",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(rows, 2);
+        assert_eq!(ledger.clients.len(), 2);
+        assert!(!ledger.clients.contains_key(&3));
+    }
+
+    #[test]
+    fn ok_consume_skip_rows() {
+        let csv = "type, client, tx, amount\ndeposit,1,1,1.0\n\ndeposit,2,2,1.0\ndeposit,3,3,1.0";
+        let opts = LedgerOptions::default().with_skip_rows(2).with_max_rows(1);
+
+        // Blank lines are not counted as skipped rows
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts)
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(ledger.clients.keys().collect::<Vec<_>>(), vec![&3]);
+
+        let mut ledger = Ledger::default();
+        let rows = ledger.consume_csv_streaming(csv.as_bytes(), &opts).unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(ledger.clients.keys().collect::<Vec<_>>(), vec![&3]);
+
+        // Skipped rows are not parsed
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\nfoo\ndeposit,1,1,1.0",
+                )),
+                &LedgerOptions::default().with_skip_rows(1),
+            )
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn ok_consume_under_max_rows() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_max_rows(2)
+            .with_fail_on_row_limit(true);
+
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0\n",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(rows, 2);
+    }
+
+    #[test]
+    fn err_consume_row_limit_exceeded() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_max_rows(1)
+            .with_fail_on_row_limit(true);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, LedgerErr::RowLimitExceeded(1)));
+        assert_eq!(ledger.clients.len(), 1);
+    }
+
+    #[test]
+    fn err_consume_transaction_limit_exceeded() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_transactions(2);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\n\ndeposit,1,1,1.0\n\ndeposit,2,2,1.0\ndeposit,3,3,1.0",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, LedgerErr::TransactionLimitExceeded(2)));
+        assert_eq!(ledger.clients.len(), 2);
+        assert!(!ledger.clients.contains_key(&3));
+    }
+
+    #[test]
+    fn ok_consume_within_transaction_limit() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_transactions(2);
+
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\n\ndeposit,2,2,1.0\n",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(rows, 2);
+    }
+
+    #[test]
+    fn ok_consume_transaction_limit_ignores_skipped_operations() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_transactions(4);
+
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\ndispute,1,9,\nresolve,1,1,\nchargeback,1,1,\ndispute,1,1,\nchargeback,1,1,\ndeposit,1,2,1.0\ndispute,2,1,\ndeposit,2,3,1.0\n",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        let client = ledger.clients.get(&1).unwrap();
+        assert!(client.locked);
+        assert_eq!(client.total, Amount(0));
+        assert!(!ledger.transactions.contains_key(&2));
+        assert_eq!(ledger.clients.get(&2).unwrap().total, Amount(10000));
+    }
+
+    #[test]
+    fn ok_consume_progress() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_progress_interval(2);
+        let mut calls = Vec::new();
+
+        ledger
+            .consume_csv_with_progress(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\n\ndeposit,1,3,1.0\ndeposit,1,4,1.0",
+                )),
+                &opts,
+                Some(&mut |lines| calls.push(lines)),
+            )
+            .unwrap();
+
+        assert_eq!(calls, vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn ok_consume_progress_exact_interval() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_progress_interval(1);
+        let mut calls = 0;
+
+        ledger
+            .consume_csv_with_progress(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0",
+                )),
+                &opts,
+                Some(&mut |_| calls += 1),
+            )
+            .unwrap();
+
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn ok_consume_quoted_newline() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_allow_extra_columns(true)
+            .with_quoting(true);
+
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount, notes\ndeposit, 1, 1, 1.0, \"first\nsecond\"\ndeposit, 1, 2, 1.0, foo",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(rows, 2);
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(20000));
+    }
+
+    #[test]
+    fn err_consume_quoted_newline_line_number() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_allow_extra_columns(true)
+            .with_quoting(true);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount, notes\ndeposit, 1, 1, 1.0, \"a\nb\"\nfoo, 1, 2, 1.0,",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        // The record following the multi-line record starts on line 4
+        assert!(matches!(
+            err,
+            LedgerErr::Parse { line: 4, .. } | LedgerErr::ParseVerbose(_, 4)
+        ));
+    }
+
+    #[test]
+    fn err_consume_max_line_length() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_line_length(32);
+        let input = format!(
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0{}",
+            " ".repeat(64)
+        );
+
+        let err = ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(input)), &opts)
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: Line 2 exceeds the maximum length of 32 bytes"
+        );
+    }
+
+    #[test]
+    fn err_consume_unterminated_quote() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_max_line_length(64)
+            .with_quoting(true);
+
+        // An unterminated quote cannot join the rest of the file into a single record
+        let input = format!(
+            "type, client, tx, amount\ndeposit, 1, 1, \"1.0\n{}",
+            "deposit, 1, 2, 1.0\n".repeat(100_000)
+        );
+        let err = ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(input)), &opts)
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: Record starting at line 2 exceeds the maximum length of 64 bytes"
+        );
+    }
+
+    #[test]
+    fn ok_consume_max_line_length() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_max_line_length(24);
+
+        // Line endings do not count towards the maximum length
+        let rows = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\r\ndeposit, 1, 1, 1.0\r\n",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn ok_consume_extra_columns() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_allow_extra_columns(true);
+
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount, notes\ndeposit, 1, 1, 1.0, foo\ndispute, 1, 1, , bar",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.available, Amount(0));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(10000)));
+    }
+
+    #[test]
+    fn apply_line() {
+        let mut ledger = Ledger::default();
+
+        ledger.apply_line("deposit, 1, 1, 2.0", 1).unwrap();
+        ledger.apply_line("", 2).unwrap();
+        ledger.apply_line("dispute, 1, 1,", 3).unwrap();
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(0), Amount(20000)));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(20000)));
+
+        ledger.apply_line("resolve, 1, 1,", 4).unwrap();
+        assert_eq!(
+            ledger.to_string_filtered(&[1]).unwrap(),
+            "client, available, held, total, locked\n1, 2.0000, 0.0000, 2.0000, false"
+        );
+
+        // The same lines consumed as a file give the same ledger
+        let mut consumed = Ledger::default();
+        consumed
+            .consume_csv(BufReader::new(Cursor::new(
+                "deposit, 1, 1, 2.0\n\ndispute, 1, 1,\nresolve, 1, 1,",
+            )))
+            .unwrap();
+        assert_eq!(consumed.to_string(), ledger.to_string());
+    }
+
+    #[test]
+    fn err_apply_line() {
+        let mut ledger = Ledger::default();
+
+        let err = ledger.apply_line("deposit, foo, 1, 1.0", 7).unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::Parse { line: 7, .. } | LedgerErr::ParseVerbose(_, 7)
+        ));
+
+        let err = ledger.apply_line("deposit, 1, 1, 1.0, foo", 8).unwrap_err();
+        assert!(matches!(err, LedgerErr::WrongFieldCount { line: 8, .. }));
+
+        // Lines that fail to parse are never applied
+        assert!(ledger.clients.is_empty());
+        assert!(ledger.transactions.is_empty());
+    }
+
+    #[test]
+    fn err_consume_extra_columns_strict() {
+        let mut ledger = Ledger::default();
+
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0, foo",
+            )))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::WrongFieldCount {
+                expected: 4,
+                found: 5,
+                line: 2
+            }
+        ));
+
+        // The count follows the header, including any optional columns
+        let err = Ledger::default()
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount, currency\ndeposit, 1, 1, 1.0, EUR, foo, bar\n",
+            )))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::WrongFieldCount {
+                expected: 5,
+                found: 7,
+                line: 2
+            }
+        ));
+
+        // Too few fields are reported by the parser
+        let err = Ledger::default()
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1\n",
+            )))
+            .unwrap_err();
+        assert!(err.code().starts_with("parse"));
+    }
+
+    #[test]
+    fn ok_consume_multiple_files() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 2, 2, 1.0",
+            )))
+            .unwrap();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\nwithdrawal, 1, 3, 0.5\ndispute, 2, 2,",
+            )))
+            .unwrap();
+
+        let c1 = ledger.clients.get(&1).unwrap();
+        let c2 = ledger.clients.get(&2).unwrap();
+        assert_eq!((c1.available, c1.total), (Amount(15000), Amount(15000)));
+        assert_eq!((c2.available, c2.total), (Amount(0), Amount(10000)));
+    }
+
+    #[test]
+    fn err_consume_missing_amount() {
+        let consume = |csv: &str| {
+            Ledger::default()
+                .consume_csv(BufReader::new(Cursor::new(csv)))
+                .unwrap_err()
+        };
+
+        // An empty amount and an absent amount column are the same error. An unterminated final
+        // record without an amount column is reported as truncated instead.
+        let empty = consume("type, client, tx, amount\ndeposit, 1, 1,\n");
+        for csv in [
+            "type, client, tx, amount\ndeposit, 1, 1\n",
+            "type, client, tx, amount\nwithdrawal, 1, 1, \n",
+        ] {
+            let err = consume(csv);
+            assert!(matches!(
+                err,
+                LedgerErr::Parse { line: 2, .. } | LedgerErr::ParseVerbose(_, 2)
+            ));
+            assert_eq!(std::mem::discriminant(&err), std::mem::discriminant(&empty));
+            assert_eq!(err.code(), empty.code());
+
+            #[cfg(feature = "verbose-errors")]
+            assert!(err
+                .to_string()
+                .contains("Amount field required for deposit/withdrawal"));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "verbose-errors")]
+    fn err_consume_verbose() {
+        let mut ledger = Ledger::default();
+
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, x, 1.0",
+            )))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst parsing csv: At line: 2\n0: at line 1, in Digit:\ndeposit, 1, x, 1.0\n            ^\n\n"
+        );
+
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1,",
+            )))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst parsing csv: At line: 2\nAmount field required for deposit/withdrawal Content: \"deposit, 1, 1,\""
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_consume_raw_line() {
+        let mut ledger = Ledger::default();
+
+        let err = ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, x, 1.0",
+            )))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst parsing csv: \"Input was in the wrong format\", At line: 3, Content: \"deposit, 1, x, 1.0\""
+        );
+    }
+
+    #[test]
+    fn ok_consume_optional_header() {
+        let mut with_header = Ledger::default();
+        let rows = with_header
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndispute, 1, 1,",
+                )),
+                &LedgerOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(rows, 2);
+
+        // The first line is applied as a transaction
+        let mut without_header = Ledger::default();
+        let rows = without_header
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new("deposit,1,1,2.0\ndispute, 1, 1,")),
+                &LedgerOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(without_header.to_string(), with_header.to_string());
+        assert_eq!(
+            without_header.transactions.get(&1),
+            Some(&(1, Amount(20000), Some(1)))
+        );
+
+        // Lines are numbered from the first transaction
+        let err = Ledger::default()
+            .consume_csv(BufReader::new(Cursor::new(
+                "deposit, 1, 1, 2.0\ndeposit, 1, 2, foo",
+            )))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::Parse { line: 2, .. } | LedgerErr::ParseVerbose(_, 2)
+        ));
+
+        // A line that is neither a header nor a transaction is a malformed header
+        let err = Ledger::default()
+            .consume_csv(BufReader::new(Cursor::new(
+                "deposit, 1, 1\ndeposit, 1, 2, 1.0",
+            )))
+            .unwrap_err();
+        assert!(matches!(err, LedgerErr::Parse { line: 1, .. }));
+        assert!(err.to_string().contains("Header"));
+    }
+
+    #[test]
+    fn ok_consume_no_header_quoted() {
+        let opts = LedgerOptions::default().with_quoting(true);
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new("deposit, 1, 1, \"2.0\"\ndispute, 1, 1,")),
+                &opts,
+            )
+            .unwrap();
+        assert_eq!(
+            ledger.clients.get(&1).unwrap().held_transactions().get(&1),
+            Some(&Amount(20000))
+        );
+    }
+
+    #[test]
+    fn detect_header() {
+        let opts = ParseOptions::default();
+
+        // Data lines never validate as a header
+        for line in [
+            "deposit,1,1,1.0",
+            "withdrawal, 2, 3, 4",
+            "dispute, 1, 1,",
+            "resolve,1,1,1.0",
+            "chargeback,1,1,",
+        ] {
+            assert!(parse_header(line).is_err());
+            assert_eq!(super::detect_header(line, &opts).unwrap(), None);
+        }
+
+        assert_eq!(
+            super::detect_header("type,client,tx,amount", &opts).unwrap(),
+            Some(ColumnLayout::default())
+        );
+        assert!(super::detect_header("type,client,tx", &opts).is_err());
+        assert!(super::detect_header(" ", &opts).is_err());
+    }
+
+    #[test]
+    fn ok_consume_no_header() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 2.0",
+            )))
+            .unwrap();
+        let rows = ledger
+            .consume_csv_no_header(BufReader::new(Cursor::new(
+                "deposit, 1, 2, 1.0\n\ndispute, 1, 1,",
+            )))
+            .unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!((c.available, c.total), (Amount(10000), Amount(30000)));
+    }
+
+    #[test]
+    fn err_consume_no_header() {
+        let mut ledger = Ledger::default();
+
+        let err = ledger
+            .consume_csv_no_header(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 2.0",
+            )))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::Parse { line: 1, .. } | LedgerErr::ParseVerbose(_, 1)
+        ));
+    }
+
+    #[test]
+    fn ok_consume_trim() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit, 1,2,1.0";
+        let opts = LedgerOptions::default().with_trim(Trim::None);
+
+        let err = Ledger::default()
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts)
+            .unwrap_err();
+        assert!(err.to_string().contains("At line: 3"));
+    }
+
+    #[test]
+    fn ok_consume_delimiter() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_delimiter(';')
+            .with_decimal_separator(',');
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type;client;tx;amount\ndeposit;1;1;1,5\nwithdrawal;1;2;0,25\ndispute;1;1;",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert_eq!(
+            ledger.to_string(),
+            "client, available, held, total, locked\n1, -0.2500, 1.5000, 1.2500, false"
+        );
+
+        // A truncated final record is detected using the delimiter
+        let err = Ledger::default()
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new("type;client;tx;amount\ndeposit;1;1")),
+                &opts,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Truncated final record at line 2"));
+    }
+
+    /// A reader that returns at most three bytes at a time.
+    struct ChunkedReader<'a>(&'a [u8]);
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn consume_csv_streaming() {
+        let csv = "type, client, tx, amount, currency\r\n\
+            deposit, 1, 1, 1.0, EUR\r\n\
+            deposit, 2, 2, 2.0,\n\
+            \n\
+            withdrawal, 1, 3, 0.5, EUR\n\
+            dispute, 2, 2,,\n\
+            deposit, 1, 4, 3.25,";
+
+        let mut expected = Ledger::default();
+        let expected_rows = expected
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &LedgerOptions::default())
+            .unwrap();
+
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_streaming(ChunkedReader(csv.as_bytes()), &LedgerOptions::default())
+            .unwrap();
+
+        assert_eq!(rows, expected_rows);
+        assert_eq!(ledger.clients, expected.clients);
+        assert_eq!(ledger.transactions, expected.transactions);
+
+        // Without a header
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_streaming(
+                ChunkedReader(b"deposit, 1, 1, 1.0\ndispute, 1, 1,\n"),
+                &LedgerOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(
+            ledger.transactions.get(&1),
+            Some(&(1, Amount(10000), Some(1)))
+        );
+    }
+
+    #[test]
+    fn err_consume_csv_streaming() {
+        let opts = LedgerOptions::default().with_max_line_length(32);
+        let consume = |csv: &str, opts: &LedgerOptions| {
+            Ledger::default()
+                .consume_csv_streaming(ChunkedReader(csv.as_bytes()), opts)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            consume(&format!("type, client, tx, amount\ndeposit, 1, 1, 1.0{}\n", " ".repeat(64)), &opts),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: Line 2 exceeds the maximum length of 32 bytes"
+        );
+        assert!(
+            consume("type, client, tx, amount\ndeposit, 1, 1, 1.", &opts)
+                .contains("Truncated final record at line 2")
+        );
+        assert!(
+            consume("type, client, tx, amount\n\ndeposit, 1, 1, foo\n", &opts)
+                .contains("At line: 3")
+        );
+        assert_eq!(consume("", &opts), LedgerErr::EmptyFile.to_string());
+        assert_eq!(
+            consume(
+                "deposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0",
+                &opts.clone().with_max_rows(1).with_fail_on_row_limit(true)
+            ),
+            LedgerErr::RowLimitExceeded(1).to_string()
+        );
+
+        // Invalid UTF-8 is a read error
+        let err = Ledger::default()
+            .consume_csv_streaming(&b"deposit, 1, 1, \xff"[..], &opts)
+            .unwrap_err();
+        assert_eq!(err.code(), "read");
+    }
+
+    #[test]
+    fn lint_csv() {
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 1.0\n\
+            deposit, foo, 2, 1.0\n\
+            \n\
+            withdrawal, 1, 3, 1.0.0\n\
+            dispute, 1, 1,\n\
+            transfer, 1, 4, 1.0\n\
+            deposit, 1, 5, 1.0";
+
+        let lines: Vec<usize> = Ledger::lint_csv(Cursor::new(csv))
+            .into_iter()
+            .map(|err| match err {
+                LedgerErr::Parse { line, .. } | LedgerErr::ParseVerbose(_, line) => line,
+                err => panic!("Unexpected error {err:?}"),
+            })
+            .collect();
+        assert_eq!(lines, vec![3, 5, 7]);
+
+        assert!(
+            Ledger::lint_csv(Cursor::new("type, client, tx, amount\ndeposit, 1, 1, 1.0"))
+                .is_empty()
+        );
+        assert!(matches!(
+            Ledger::lint_csv(Cursor::new(""))[..],
+            [LedgerErr::EmptyFile]
+        ));
+        assert!(matches!(
+            Ledger::lint_csv(Cursor::new("type, client\ndeposit, foo"))[..],
+            [LedgerErr::Parse { line: 1, .. }]
+        ));
+    }
+
+    #[test]
+    fn ok_consume_chargeback_lenient() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0\nchargeback, 1, 1,",
+            )))
+            .unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.total, Amount(10000));
+        assert!(!c.locked);
+    }
+
+    #[test]
+    fn ok_consume_chargeback_strict() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_strict(true);
+
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,\nchargeback, 1, 1,",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        assert!(ledger.clients.get(&1).unwrap().locked);
+    }
+
+    #[test]
+    fn err_consume_chargeback_strict() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_strict(true);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\nchargeback, 1, 1,",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, LedgerErr::InvalidOperation(_, 3)));
+        assert!(!ledger.clients.get(&1).unwrap().locked);
+    }
+
+    #[test]
+    fn err_consume_locked_strict() {
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0\ndispute, 1, 1,\nchargeback, 1, 1,\ndeposit, 1, 3, 5.0";
+
+        // By default, the deposit into the locked account is skipped
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(input)))
+            .unwrap();
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(10000));
+
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(input)),
+                &LedgerOptions::default().with_strict(true),
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_plain(),
+            "error: applying transaction: \"Deposit or Withdrawal on a locked account\", At line: 6"
+        );
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(10000));
+        assert!(!ledger.transactions.contains_key(&3));
+    }
+
+    #[test]
+    fn err_consume_reserved_client() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_reserved_clients(vec![0]);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit,1,2,1.0\ndeposit,0,1,1.0",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_plain(),
+            "error: applying transaction: \"Transaction for reserved client 0\", At line: 3"
+        );
+        assert!(!ledger.clients.contains_key(&0));
+        assert!(!ledger.transactions.contains_key(&1));
+    }
+
+    #[test]
+    fn ok_consume_dispute_window() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_dispute_window(2);
+
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0\ndeposit, 1, 3, 1.0\ndispute, 1, 2,\ndispute, 1, 1,",
+                )),
+                &opts,
+            )
+            .unwrap();
+
+        // Transaction 1 is four lines before its dispute, so only transaction 2 is held
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.available, Amount(20000));
+        assert!(!c.held_transactions().contains_key(&1));
+        assert_eq!(c.held_transactions().get(&2), Some(&Amount(10000)));
+    }
+
+    #[test]
+    fn consume_dispute_window_across_files() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_dispute_window(2)
+            .with_strict(true);
+
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new("type, client, tx, amount\ndeposit, 1, 1, 1.0")),
+                &opts,
+            )
+            .unwrap();
+
+        // A transaction from the first file is always disputable from the second
+        let second = format!(
+            "type, client, tx, amount\n{}dispute, 1, 1,",
+            (2..53)
+                .map(|tx| format!("deposit, 2, {tx}, 1.0\n"))
+                .collect::<String>()
+        );
+        ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(second)), &opts)
+            .unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(10000)));
+    }
+
+    #[test]
+    fn err_consume_dispute_window_strict() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default()
+            .with_dispute_window(1)
+            .with_strict(true);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0\ndispute, 1, 1,",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, LedgerErr::InvalidOperation(_, 4)));
+        assert!(ledger
+            .clients
+            .get(&1)
+            .unwrap()
+            .held_transactions()
+            .is_empty());
+    }
+
+    #[test]
+    fn err_consume_duplicate_strict() {
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_strict(true);
+
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 1.0\n\nwithdrawal, 1, 1, 1.0",
+                )),
+                &opts,
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst recording transaction: Duplicate transaction ID 1 first seen at line 2, repeated at line 5"
+        );
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(20000));
+        assert_eq!(
+            ledger.transactions.get(&1),
+            Some(&(1, Amount(10000), Some(2)))
+        );
+    }
+
+    #[test]
+    fn ok_consume_reordered_columns() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "client, type, amount, tx\n1, deposit, 2.0, 1\n1, withdrawal, 0.5, 2\n1, dispute, , 1",
+            )))
+            .unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.available, Amount(-5000));
+        assert_eq!(c.total, Amount(15000));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(20000)));
+    }
+
+    #[test]
+    fn ok_consume_first_line() {
+        let mut ledger = Ledger::default();
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0\n\ndeposit, 1, 2, 1.0\ndeposit, 2, 3, 1.0",
+            )))
+            .unwrap();
+        ledger.insert_transaction(3, 4, Amount(1));
+
+        assert_eq!(ledger.clients.get(&1).unwrap().first_line(), Some(2));
+        assert_eq!(ledger.clients.get(&2).unwrap().first_line(), Some(5));
+        assert_eq!(ledger.clients.get(&3).unwrap().first_line(), None);
+    }
+
+    #[test]
+    fn insert_transaction() {
+        let mut client_2 = ClientData::new(Amount(0));
+        client_2.locked = true;
+
+        let mut ledger = Ledger {
+            clients: [(2_u16, client_2)].into_iter().collect(),
+            ..Default::default()
+        };
+
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.insert_transaction(1, 2, Amount(1));
+
+        // Locked
+        ledger.insert_transaction(2, 3, Amount(1));
+
+        ledger.insert_transaction(1, 4, -Amount(3));
+        ledger.insert_transaction(1, 5, -Amount(1));
+
+        let client_1 = ledger.clients.get(&1).unwrap();
+        let client_2 = ledger.clients.get(&2).unwrap();
+        assert_eq!(client_1.available, Amount(-2));
+        assert_eq!(client_2.available, Amount(0));
+        assert_eq!(client_1.total, Amount(-2));
+        assert_eq!(client_2.total, Amount(0));
+        assert_eq!(client_1.total_deposited(), Amount(2));
+        assert_eq!(client_1.total_withdrawn(), Amount(4));
+        assert_eq!(client_2.total_deposited(), Amount(0));
+        assert_eq!(client_2.total_withdrawn(), Amount(0));
+    }
+
+    #[test]
+    fn insert_transaction_locked_dispute() {
+        let mut client = ClientData::new(Amount(5));
+        client.locked = true;
+
+        let mut ledger = Ledger {
+            clients: [(1_u16, client)].into_iter().collect(),
+            ..Default::default()
+        };
+
+        // The deposit is never recorded, so the dispute is silently ignored
+        ledger.insert_transaction(1, 1, Amount(10));
+        assert!(!ledger.transactions.contains_key(&1));
+
+        ledger.hold(1, 1);
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(5), Amount(5)));
+        assert!(c.held_transactions().is_empty());
+    }
+
+    #[test]
+    fn insert_batch() {
+        let mut batch = Vec::new();
+        for tx in 0..1000_u32 {
+            let client = (tx % 7 / 2) as u16;
+            let amount = if tx % 3 == 0 { -Amount(5) } else { Amount(10) };
+            batch.push((client, tx, amount));
+        }
+        batch.push((9, 1000, Amount(i64::MAX)));
+        batch.push((9, 1001, Amount(1))); // Overflows
+        batch.push((2, 1002, Amount(3))); // Locked
+
+        let mut client = ClientData::new(Amount(0));
+        client.locked = true;
+
+        let mut batched = Ledger::default();
+        batched.clients.insert(2, client.clone());
+        batched.insert_batch(&batch);
+
+        let mut looped = Ledger::default();
+        looped.clients.insert(2, client);
+        for &(client, tx, amount) in &batch {
+            looped.insert_transaction(client, tx, amount);
+        }
+
+        assert_eq!(batched.transactions, looped.transactions);
+        assert_eq!(batched.clients, looped.clients);
+    }
+
+    #[test]
+    fn insert_transaction_overflow() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(i64::MAX));
+        ledger.insert_transaction(1, 2, Amount(1));
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.total, Amount(i64::MAX));
+        assert!(!ledger.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn dispute() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.hold(1, 1);
+        ledger.hold(2, 1);
+        ledger.hold(1, 2);
+
+        let c = ledger.clients.get(&1).unwrap();
+
+        assert_eq!(ledger.clients.len(), 1);
+        assert_eq!(c.held_transactions().get(&1).unwrap(), &Amount(1));
+        assert_eq!(c.available, Amount(0));
+    }
+
+    #[test]
+    fn dispute_wrong_client() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(2, 2, Amount(5));
+
+        // Client 2 attempts to dispute client 1's deposit
+        ledger.hold(2, 1);
+        ledger.resolve(2, 1);
+        ledger.chageback(2, 1);
+
+        let c1 = ledger.clients.get(&1).unwrap();
+        let c2 = ledger.clients.get(&2).unwrap();
+
+        assert_eq!(ledger.transactions.get(&1), Some(&(1, Amount(10), None)));
+        assert!(c1.held_transactions().is_empty());
+        assert!(c2.held_transactions().is_empty());
+        assert_eq!(
+            (c1.available, c1.total, c1.locked),
+            (Amount(10), Amount(10), false)
+        );
+        assert_eq!(
+            (c2.available, c2.total, c2.locked),
+            (Amount(5), Amount(5), false)
+        );
+    }
+
+    #[test]
+    fn dispute_twice() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.hold(1, 1);
+        ledger.hold(1, 1);
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.available, Amount(0));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(10)));
+    }
+
+    #[test]
+    fn dispute_cycles() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+
+        for _ in 0..2 {
+            ledger.hold(1, 1);
+
+            let c = ledger.clients.get(&1).unwrap();
+            assert_eq!((c.available, c.total), (Amount(0), Amount(10)));
+            assert_eq!(c.held_transactions().get(&1), Some(&Amount(10)));
+            assert_eq!(ledger.transactions.get(&1), Some(&(1, Amount(10), None)));
+
+            ledger.resolve(1, 1);
+
+            let c = ledger.clients.get(&1).unwrap();
+            assert_eq!((c.available, c.total), (Amount(10), Amount(10)));
+            assert!(c.held_transactions().is_empty());
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.hold(1, 1);
+        ledger.resolve(1, 1);
+        ledger.resolve(2, 1);
+        ledger.resolve(1, 2);
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.held_transactions().len(), 0);
+        assert_eq!(c.available, Amount(1));
+    }
+
+    #[test]
+    fn transaction_lifecycle() {
+        fn assert_balances(ledger: &Ledger, available: i64, held: i64, total: i64) {
+            let c = ledger.clients.get(&1).unwrap();
+            let held_sum: i64 = c.held_transactions().values().map(|amount| amount.0).sum();
+
+            assert_eq!(
+                (c.available.0, held_sum, c.total.0),
+                (available, held, total)
+            );
+            assert_eq!(c.total.0, c.available.0 + held_sum);
+        }
+
+        // Deposited -> Disputed -> Resolved
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        assert_balances(&ledger, 10, 0, 10);
+        ledger.hold(1, 1);
+        assert_balances(&ledger, 0, 10, 10);
+        ledger.resolve(1, 1);
+        assert_balances(&ledger, 10, 0, 10);
+
+        // Deposited -> Disputed -> Charged back
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, Amount(5));
+        assert_balances(&ledger, 15, 0, 15);
+        ledger.hold(1, 1);
+        assert_balances(&ledger, 5, 10, 15);
+        ledger.chageback(1, 1);
+        assert_balances(&ledger, 5, 0, 5);
+        assert!(ledger.clients.get(&1).unwrap().locked);
+
+        // A charged back transaction cannot be disputed again
+        ledger.hold(1, 1);
+        assert_balances(&ledger, 5, 0, 5);
+
+        // Withdrawn -> Disputed -> Resolved
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, -Amount(4));
+        assert_balances(&ledger, 6, 0, 6);
+        ledger.hold(1, 2);
+        assert_balances(&ledger, 6, 4, 10);
+        ledger.resolve(1, 2);
+        assert_balances(&ledger, 6, 0, 6);
+        assert!(!ledger.clients.get(&1).unwrap().locked);
+
+        // Withdrawn -> Disputed -> Charged back
+        ledger.hold(1, 2);
+        assert_balances(&ledger, 6, 4, 10);
+        ledger.chageback(1, 2);
+        assert_balances(&ledger, 10, 0, 10);
+        assert!(ledger.clients.get(&1).unwrap().locked);
+        assert!(!ledger.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn resolve_partial_withdrawal() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, -Amount(8));
+        ledger.hold(1, 2);
+
+        // The available funds are never inflated by a disputed withdrawal
+        ledger.resolve_partial(1, 2, Amount(3));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(2), Amount(7)));
+        assert_eq!(c.held_transactions().get(&2), Some(&Amount(5)));
+
+        ledger.resolve_partial(1, 2, Amount(5));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(2), Amount(2)));
+        assert!(c.held_transactions().is_empty());
+    }
+
+    #[test]
+    fn consume_csv_withdrawal_dispute() {
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 5.0\n\
+            withdrawal, 1, 2, 2.0\n\
+            dispute, 1, 2,\n\
+            deposit, 2, 3, 5.0\n\
+            withdrawal, 2, 4, 2.0\n\
+            dispute, 2, 4,\n\
+            chargeback, 2, 4,";
+
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(csv)))
+            .unwrap();
+        assert_eq!(
+            ledger.to_string_filtered(&[1, 2]).unwrap(),
+            "client, available, held, total, locked\n\
+            1, 3.0000, 2.0000, 5.0000, false\n\
+            2, 5.0000, 0.0000, 5.0000, true"
+        );
+    }
+
+    #[test]
+    fn consume_csv_duplicate_of_disputed() {
+        // A duplicate id overwrites the stored transaction, but the dispute still reverses a deposit
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 10.0\n\
+            dispute, 1, 1,\n\
+            withdrawal, 1, 1, 3.0\n\
+            resolve, 1, 1,\n\
+            deposit, 2, 2, 10.0\n\
+            dispute, 2, 2,\n\
+            withdrawal, 2, 2, 3.0\n\
+            chargeback, 2, 2,";
+
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(csv)))
+            .unwrap();
+        assert_eq!(
+            ledger.to_string_filtered(&[1, 2]).unwrap(),
+            "client, available, held, total, locked\n\
+            1, 7.0000, 0.0000, 7.0000, false\n\
+            2, -3.0000, 0.0000, -3.0000, true"
+        );
+    }
+
+    #[test]
+    fn resolve_partial() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.hold(1, 1);
+
+        // Partial resolve
+        ledger.resolve_partial(1, 1, Amount(4));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(4), Amount(10)));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(6)));
+
+        // Over-resolve is rejected
+        ledger.resolve_partial(1, 1, Amount(7));
+        ledger.resolve_partial(1, 1, Amount(0));
+        ledger.resolve_partial(1, 1, Amount(-1));
+        ledger.resolve_partial(2, 1, Amount(1));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(4), Amount(10)));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(6)));
+
+        // Resolving the remainder releases the dispute
+        ledger.resolve_partial(1, 1, Amount(6));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(10), Amount(10)));
+        assert!(c.held_transactions().is_empty());
+
+        // Full resolve of a partially resolved dispute
+        ledger.hold(1, 1);
+        ledger.resolve_partial(1, 1, Amount(5));
+        ledger.resolve(1, 1);
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(10), Amount(10)));
+        assert!(c.held_transactions().is_empty());
+    }
+
+    #[test]
+    fn consume_csv_partial_resolve() {
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 5.0\n\
+            dispute, 1, 1,\n\
+            resolve, 1, 1, 3.00";
+
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(csv)))
+            .unwrap();
+        assert_eq!(
+            ledger.to_string(),
+            "client, available, held, total, locked\n1, 3.0000, 2.0000, 5.0000, false"
+        );
+
+        // Over-resolves are an error in strict mode
+        let csv = format!("{csv}\nresolve, 1, 1, 2.01");
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(csv)),
+                &LedgerOptions::default().with_strict(true),
+            )
+            .unwrap_err();
+        assert!(matches!(err, LedgerErr::InvalidOperation(_, 5)));
+    }
+
+    #[test]
+    fn total_held() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.total_held(), 0);
+
+        ledger.insert_transaction(1, 1, Amount(15000));
+        ledger.insert_transaction(1, 2, Amount(5000));
+        ledger.insert_transaction(2, 3, Amount(2500));
+        ledger.insert_transaction(2, 4, Amount(100));
+        ledger.hold(1, 1);
+        ledger.hold(1, 2);
+        ledger.hold(2, 3);
+
+        assert_eq!(ledger.total_held(), 22500);
+        assert_eq!(ledger.total_held_string(), "2.2500");
+
+        ledger.resolve(1, 2);
+        assert_eq!(ledger.total_held(), 17500);
+    }
+
+    #[test]
+    fn state_hash() {
+        let mut first = Ledger::default();
+        first
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                deposit, 1, 1, 2.0\n\
+                deposit, 2, 2, 1.0\n\
+                withdrawal, 1, 3, 0.5\n\
+                dispute, 2, 2,\n\
+                deposit, 3, 4, 3.0\n\
+                dispute, 3, 4,\n\
+                chargeback, 3, 4,",
+            )))
+            .unwrap();
+
+        let mut second = Ledger::default();
+        second
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                deposit, 3, 4, 3.0\n\
+                deposit, 2, 2, 1.0\n\
+                dispute, 3, 4,\n\
+                dispute, 2, 2,\n\
+                deposit, 1, 1, 2.0\n\
+                chargeback, 3, 4,\n\
+                withdrawal, 1, 3, 0.5",
+            )))
+            .unwrap();
+
+        assert_eq!(first.state_hash(), second.state_hash());
+
+        // Any change to a balance or the locked status changes the hash
+        let hash = first.state_hash();
+        first.resolve(2, 2);
+        assert_ne!(first.state_hash(), hash);
+        first.clients.get_mut(&3).unwrap().locked = false;
+        assert_ne!(first.state_hash(), second.state_hash());
+        assert_eq!(Ledger::default().state_hash(), super::FNV_OFFSET_BASIS);
+    }
+
+    #[test]
+    fn aggregates() {
+        let mut ledger = Ledger::default();
+        assert_eq!((ledger.net_position(), ledger.gross_total()), (0, 0));
+        assert!(ledger.validate_invariants());
+
+        ledger.insert_transaction(1, 1, Amount(20000));
+        ledger.insert_transaction(1, 2, -Amount(5000));
+        ledger.insert_transaction(2, 3, Amount(7500));
+        ledger.hold(1, 2);
+        ledger.hold(2, 3);
+
+        assert_eq!(ledger.net_position(), 15000);
+        assert_eq!(ledger.total_held(), 12500);
+        assert_eq!(ledger.gross_total(), 27500);
+        assert!(ledger.validate_invariants());
+
+        ledger.clients.get_mut(&2).unwrap().available = Amount(1);
+        assert!(!ledger.validate_invariants());
+    }
+
+    #[test]
+    fn accessors() {
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\nchargeback, 1, 1,\ndispute, 2, 2,",
+            )))
+            .unwrap();
+
+        assert_eq!(ledger.clients().len(), 2);
+        assert_eq!(ledger.clients().get(&2).unwrap().total, Amount(20000));
+        assert_eq!(
+            ledger
+                .clients()
+                .get(&2)
+                .unwrap()
+                .held_transactions()
+                .get(&2),
+            Some(&Amount(20000))
+        );
+
+        assert_eq!(
+            ledger.transactions().iter().collect::<Vec<_>>(),
+            vec![
+                (&1, &(1, Amount(10000), Some(2))),
+                (&2, &(2, Amount(20000), Some(3)))
+            ]
+        );
+    }
+
+    #[test]
+    fn event_log() {
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\n\ndispute, 2, 1,\nchargeback, 1, 1,\nfoo";
+
+        // Disabled by default
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(input)))
+            .unwrap_err();
+        assert!(ledger.event_log().is_empty());
+
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_event_log(true);
+        ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(input)), &opts)
+            .unwrap_err();
+
+        assert_eq!(
+            ledger.event_log(),
+            [
+                (2, Transaction::Deposit(1, 1, Amount(10000))),
+                (4, Transaction::Dispute(2, 1)),
+                (5, Transaction::Chargeback(1, 1)),
+            ]
+        );
+
+        ledger.clear();
+        assert!(ledger.event_log().is_empty());
+    }
+
+    #[test]
+    fn client_ids() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.client_ids().next(), None);
+
+        for (client, tx) in [(5, 1), (1, 2), (3, 3), (1, 4), (2, 5)] {
+            ledger.insert_transaction(client, tx, Amount(1));
+        }
+
+        assert_eq!(ledger.client_ids().collect::<Vec<_>>(), vec![1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn consume_timestamp() {
+        let csv = "type, client, tx, amount, timestamp\n\
+            deposit, 1, 1, 1.0, 2023-01-01T00:00:00Z\n\
+            deposit, 1, 2, 1.0, 2023-01-10T00:00:00Z\n\
+            deposit, 1, 3, 1.0,\n\
+            dispute, 1, 1, , 2023-01-08T00:00:01Z\n\
+            dispute, 1, 2, , 2023-01-17T00:00:00Z\n\
+            dispute, 1, 3, , 2023-12-31T00:00:00Z\n";
+        let week = 7 * 24 * 60 * 60;
+        let opts = LedgerOptions::default()
+            .with_timestamp(true)
+            .with_dispute_window_seconds(week);
+
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts)
+            .unwrap();
+
+        assert_eq!(ledger.transaction_timestamp(1), Some(1672531200));
+        assert_eq!(ledger.transaction_timestamp(3), None);
+        assert_eq!(ledger.transaction_timestamp(4), None);
+
+        // Only the dispute more than a week after its transaction is ignored, and a transaction
+        // without a timestamp is always disputable
+        let held = ledger.clients[&1].held_transactions();
+        assert_eq!(held.keys().collect::<Vec<_>>(), vec![&2, &3]);
+
+        let err = Ledger::default()
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(csv)),
+                &opts.clone().with_strict(true),
+            )
+            .unwrap_err();
+        assert!(matches!(err, LedgerErr::InvalidOperation(_, 5)));
+
+        // Files without a header have the timestamp as the fifth column
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv_streaming(
+                "deposit,1,1,1.0,2023-01-01T00:00:00Z\ndispute,1,1,,2023-01-09T00:00:00Z"
+                    .as_bytes(),
+                &opts,
+            )
+            .unwrap();
+        assert_eq!(ledger.transaction_timestamp(1), Some(1672531200));
+        assert!(ledger.clients[&1].held_transactions().is_empty());
+
+        // Charged back transactions are forgotten
+        ledger.hold(1, 1);
+        ledger.chageback(1, 1);
+        assert_eq!(ledger.transaction_timestamp(1), None);
+        assert!(ledger.timestamps.is_empty());
+    }
+
+    #[test]
+    fn wrong_client() {
+        let csv = "type, client, tx, amount\n\
+            deposit, 1, 1, 1.0\n\
+            dispute, 2, 1,\n\
+            resolve, 2, 1,\n\
+            chargeback, 2, 1,\n\
+            deposit, 2, 2, 2.0";
+
+        // Lenient by default, the operations are skipped and processing continues
+        let mut ledger = Ledger::default();
+        let rows = ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &LedgerOptions::default())
+            .unwrap();
+        assert_eq!(rows, 5);
+        assert_eq!(
+            ledger.to_string_filtered(&[1, 2]).unwrap(),
+            "client, available, held, total, locked\n\
+            1, 1.0000, 0.0000, 1.0000, false\n\
+            2, 2.0000, 0.0000, 2.0000, false"
+        );
+
+        // Strict client matching stops at the first mismatch
+        let mut ledger = Ledger::default();
+        let opts = LedgerOptions::default().with_strict_client_matching(true);
+        let err = ledger
+            .consume_csv_with_options(BufReader::new(Cursor::new(csv)), &opts)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LedgerErr::WrongClient {
+                tx_id: 1,
+                expected_client: 1,
+                actual_client: 2
+            }
+        ));
+        assert!(!ledger.clients.contains_key(&2));
+
+        // Operations on unknown transactions, and for the owning client, are unaffected
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv_with_options(
+                BufReader::new(Cursor::new(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 2, 9,\ndispute, 1, 1,",
+                )),
+                &opts,
+            )
+            .unwrap();
+        assert!(ledger.is_held(1, 1));
+    }
+
+    #[test]
+    fn for_each_client() {
+        let mut ledger = Ledger::default();
+        let mut ids = Vec::new();
+        ledger.for_each_client(|id, _| ids.push(id));
+        assert!(ids.is_empty());
+
+        for (tx, client) in [(1, 300), (2, 7), (3, 65535), (4, 0), (5, 7)] {
+            ledger.insert_transaction(client, tx, Amount(10000));
+        }
+
+        let mut deposited = Vec::new();
+        ledger.for_each_client(|id, client| {
+            ids.push(id);
+            deposited.push(client.total_deposited());
+        });
+        assert_eq!(ids, vec![0, 7, 300, 65535]);
+        assert_eq!(deposited[1], Amount(20000));
+    }
+
+    #[test]
+    fn to_string_with_meta() {
+        let mut ledger = Ledger::default();
+        assert_eq!(
+            ledger.to_string_with_meta(),
+            "# 0 clients, 0 transactions\nclient, available, held, total, locked"
+        );
+
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                deposit, 1, 1, 1.0\n\
+                deposit, 2, 2, 2.0\n\
+                withdrawal, 1, 3, 0.5\n\
+                dispute, 2, 2,",
+            )))
+            .unwrap();
+
+        let output = ledger.to_string_with_meta();
+        let (meta, rest) = output.split_once('\n').unwrap();
+        assert_eq!(meta, "# 2 clients, 3 transactions");
+        assert_eq!(
+            meta,
+            format!(
+                "# {} clients, {} transactions",
+                ledger.clients.len(),
+                ledger.transactions.len()
+            )
+        );
+        assert_eq!(rest, ledger.to_string());
+
+        // The meta line precedes the header, and is ignored for JSON output
+        let opts = OutputOptions::default()
+            .with_meta(true)
+            .with_filter_clients(vec![2]);
+        let mut buf = Vec::new();
+        ledger.to_writer(&mut buf, &opts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "# 2 clients, 3 transactions\nclient, available, held, total, locked\n2, 0.0000, 2.0000, 2.0000, false"
+        );
+
+        let mut buf = Vec::new();
+        ledger
+            .to_writer(&mut buf, &opts.with_format(OutputFormat::Json))
+            .unwrap();
+        assert!(buf.starts_with(b"["));
+    }
+
+    #[test]
+    fn truncate_old_transactions() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.truncate_old_transactions(10), 0);
+
+        for tx in 1..=5 {
+            ledger.insert_transaction(1, tx, Amount(10));
+        }
+        ledger.insert_transaction(2, 6, Amount(10));
+        ledger.hold(1, 2);
+
+        assert_eq!(ledger.truncate_old_transactions(4), 2);
+        assert_eq!(
+            ledger.transactions.keys().copied().collect::<Vec<_>>(),
+            vec![2, 4, 5, 6]
+        );
+
+        // Balances are unchanged
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(40), Amount(50)));
+
+        // Disputes of truncated transactions are silently ignored
+        ledger.hold(1, 1);
+        ledger.hold(1, 3);
+        assert_eq!(ledger.clients.get(&1).unwrap().held_transactions().len(), 1);
+
+        // The held transaction can still be resolved, and disputed again
+        ledger.resolve(1, 2);
+        ledger.hold(1, 2);
+        assert_eq!(
+            ledger.clients.get(&1).unwrap().held_transactions().get(&2),
+            Some(&Amount(10))
+        );
+
+        // Once released, it can be truncated
+        ledger.resolve(1, 2);
+        assert_eq!(ledger.truncate_old_transactions(u32::MAX), 4);
+        assert!(ledger.transactions.is_empty());
+    }
+
+    #[test]
+    fn rollback() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, -Amount(4));
+        ledger.insert_transaction(1, 3, Amount(1));
+
+        // Rolling back a withdrawal returns the funds
+        ledger.rollback(2).unwrap();
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(11), Amount(11)));
+
+        ledger.rollback(1).unwrap();
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(1), Amount(1)));
+        assert_eq!(ledger.transactions.len(), 1);
+
+        // A rolled back transaction can no longer be disputed
+        ledger.hold(1, 1);
+        assert!(ledger
+            .clients
+            .get(&1)
+            .unwrap()
+            .held_transactions()
+            .is_empty());
+    }
+
+    #[test]
+    fn err_rollback() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.hold(1, 1);
+
+        assert!(matches!(
+            ledger.rollback(1),
+            Err(LedgerErr::AlreadyDisputed(1))
+        ));
+        assert!(matches!(ledger.rollback(2), Err(LedgerErr::NotFound(2))));
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(0), Amount(10)));
+        assert!(ledger.transactions.contains_key(&1));
+
+        // Reversing a withdrawal from a full balance would overflow
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(i64::MAX));
+        ledger.insert_transaction(1, 2, -Amount(1));
+        ledger.insert_transaction(1, 3, Amount(1));
+
+        assert!(matches!(ledger.rollback(2), Err(LedgerErr::Overflow(1))));
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(i64::MAX), Amount(i64::MAX)));
+        assert!(ledger.transactions.contains_key(&2));
+
+        ledger.clients.remove(&1);
+        assert!(matches!(
+            ledger.rollback(1),
+            Err(LedgerErr::ClientNotFound(1))
+        ));
+    }
+
+    #[test]
+    fn chargeback() {
+        let mut ledger = Ledger::default();
+
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.hold(1, 1);
+        ledger.chageback(1, 1);
+        ledger.chageback(2, 1);
+        ledger.chageback(1, 2);
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!(c.held_transactions().len(), 0);
+        assert_eq!(c.total, Amount(0));
+        assert!(c.locked);
+    }
+
+    #[test]
+    fn to_string_filtered() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.insert_transaction(2, 2, Amount(2));
+        ledger.insert_transaction(3, 3, Amount(3));
+
+        assert_eq!(
+            ledger.to_string_filtered(&[2]).unwrap(),
+            "client, available, held, total, locked\n2, 0.0002, 0.0000, 0.0002, false"
+        );
+        assert_eq!(
+            ledger.to_string_filtered(&[3, 1]).unwrap(),
+            "client, available, held, total, locked\n3, 0.0003, 0.0000, 0.0003, false\n1, 0.0001, 0.0000, 0.0001, false"
+        );
+        assert!(matches!(
+            ledger.to_string_filtered(&[1, 4]),
+            Err(LedgerErr::ClientNotFound(4))
+        ));
+    }
+
+    #[test]
+    fn to_writer() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(1));
+
+        let mut buf = Vec::new();
+        ledger
+            .to_writer(&mut buf, &OutputOptions::default())
+            .unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), ledger.to_string());
+
+        let mut buf = Vec::new();
+        ledger
+            .to_writer(&mut buf, &OutputOptions::default().with_omit_header(true))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "1, 0.0001, 0.0000, 0.0001, false"
+        );
+    }
+
+    #[test]
+    fn to_csv_string() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(2, 1, Amount(20000));
+        ledger.insert_transaction(1, 2, Amount(15000));
+        ledger.hold(1, 2);
+
+        assert_eq!(
+            ledger.to_csv_string(),
+            "client,available,held,total,locked\r\n1,0.0000,1.5000,1.5000,false\r\n2,2.0000,0.0000,2.0000,false\r\n"
+        );
+    }
+
+    #[test]
+    fn to_csv_string_round_trip() {
+        let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                deposit, 1, 1, 2.5\n\
+                withdrawal, 1, 2, 0.25\n\
+                deposit, 2, 3, 1.0\n\
+                deposit, 2, 4, 3.0\n\
+                dispute, 2, 4,\n\
+                deposit, 3, 5, 4.0\n\
+                deposit, 3, 6, 1.5\n\
+                dispute, 3, 5,\n\
+                chargeback, 3, 5,",
+            )))
+            .unwrap();
+
+        // Read every column of the output back and compare it with the ledger
+        let csv = ledger.to_csv_string();
+        let mut records = csv.split("\r\n");
+        assert_eq!(records.next(), Some("client,available,held,total,locked"));
+
+        let amount = |field: &str| crate::parse::four_dp(field).unwrap().1;
+        let parsed: Vec<(u16, i64, i64, i64, bool)> = records
+            .filter(|record| !record.is_empty())
+            .map(|record| {
+                let fields = crate::parse::split_fields(record, true);
+                assert_eq!(fields.len(), 5);
+                (
+                    fields[0].parse().unwrap(),
+                    amount(fields[1]),
+                    amount(fields[2]),
+                    amount(fields[3]),
+                    fields[4].parse().unwrap(),
+                )
+            })
+            .collect();
+
+        let mut expected: Vec<(u16, i64, i64, i64, bool)> = ledger
+            .clients
+            .iter()
+            .map(|(&id, c)| {
+                let held = c.held_total_checked().unwrap();
+                (id, c.available.0, held, c.total.0, c.locked)
+            })
+            .collect();
+        expected.sort_unstable();
+
+        assert_eq!(parsed, expected);
+        assert_eq!(
+            parsed,
+            vec![
+                (1, 22500, 0, 22500, false),
+                (2, 10000, 30000, 40000, false),
+                (3, 15000, 0, 15000, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn to_string_with_bool_format() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.hold(1, 1);
+        ledger.chageback(1, 1);
+
+        assert_eq!(
+            ledger.to_string_with_bool_format("Y", "N"),
+            "client, available, held, total, locked\n1, 0.0000, 0.0000, 0.0000, Y"
+        );
+
+        ledger.insert_transaction(2, 2, Amount(1));
+        let result = ledger.to_string_with_bool_format("Y", "N");
+        assert!(result.contains("\n2, 0.0001, 0.0000, 0.0001, N"));
+        assert!(result.contains("\n1, 0.0000, 0.0000, 0.0000, Y"));
+    }
+
+    #[test]
+    fn find_client_by_transaction() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10000));
+        ledger.insert_transaction(2, 2, Amount(10000));
+
+        // In the global map
+        assert_eq!(ledger.find_client_by_transaction(1), Some(1));
+        assert_eq!(ledger.find_client_by_transaction(2), Some(2));
+
+        // Not found
+        assert_eq!(ledger.find_client_by_transaction(3), None);
+
+        // Charged back transactions are removed from the global map
+        ledger.hold(2, 2);
+        ledger.chageback(2, 2);
+        assert_eq!(ledger.find_client_by_transaction(2), None);
+    }
+
+    #[test]
+    fn find_client_with_held_transaction() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10000));
+        ledger.insert_transaction(2, 2, Amount(10000));
+
+        // In the global map, but not held
+        assert_eq!(ledger.find_client_with_held_transaction(2), None);
+
+        // In the held map
+        ledger.hold(2, 2);
+        assert_eq!(ledger.find_client_with_held_transaction(2), Some(2));
+
+        // Not found
+        assert_eq!(ledger.find_client_with_held_transaction(3), None);
+
+        ledger.resolve(2, 2);
+        assert_eq!(ledger.find_client_with_held_transaction(2), None);
+    }
+
+    #[test]
+    fn clear() {
+        let input =
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,\ndeposit, 2, 2, 2.0";
+
+        let mut ledger = Ledger::default();
+        for id in 0..100 {
+            ledger.insert_transaction(id, id as u32, Amount(1));
+        }
+        let capacity = ledger.clients.capacity();
+
+        ledger.clear();
+        assert!(ledger.clients.is_empty());
+        assert!(ledger.transactions.is_empty());
+        assert_eq!(ledger.clients.capacity(), capacity);
+
+        // A cleared ledger behaves identically to a fresh one
+        let mut fresh = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(input)))
+            .unwrap();
+        fresh
+            .consume_csv(BufReader::new(Cursor::new(input)))
+            .unwrap();
+
+        assert_eq!(ledger.clients, fresh.clients);
+        assert_eq!(ledger.transactions, fresh.transactions);
+    }
+
+    #[test]
+    fn merge_separate_clients() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+
+        let mut other = Ledger::default();
+        other.insert_transaction(2, 2, Amount(20));
+
+        ledger.merge(other).unwrap();
+
+        assert_eq!(ledger.clients.len(), 2);
+        assert_eq!(ledger.clients.get(&2).unwrap().total, Amount(20));
+        assert_eq!(ledger.transactions.get(&2), Some(&(2, Amount(20), None)));
+    }
+
+    #[test]
+    fn merge_shared_clients() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.hold(1, 1);
+
+        let mut other = Ledger::default();
+        other.insert_transaction(1, 2, Amount(20));
+        other.insert_transaction(1, 3, Amount(5));
+        other.hold(1, 3);
+
+        ledger.merge(other).unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.available, c.total), (Amount(20), Amount(35)));
+        assert_eq!(c.held_transactions().get(&1), Some(&Amount(10)));
+        assert_eq!(c.held_transactions().get(&3), Some(&Amount(5)));
+        assert_eq!(ledger.transactions.len(), 3);
+
+        // Disputes from either ledger can be resolved after merging
+        ledger.resolve(1, 3);
+        assert_eq!(ledger.clients.get(&1).unwrap().available, Amount(25));
+    }
+
+    #[test]
+    fn merge_locked() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(2, 2, Amount(10));
+
+        let mut other = Ledger::default();
+        other.insert_transaction(1, 3, Amount(10));
+        other.insert_transaction(2, 4, Amount(10));
+        other.hold(2, 4);
+        other.chageback(2, 4);
+
+        ledger.merge(other).unwrap();
+
+        assert!(!ledger.clients.get(&1).unwrap().locked);
+        assert!(ledger.clients.get(&2).unwrap().locked);
+        assert_eq!(ledger.locked_clients(), vec![2]);
+    }
+
+    #[test]
+    fn generate_audit_report() {
+        let mut ledger = Ledger::default();
+        assert!(ledger.generate_audit_report().clients.is_empty());
+
+        ledger.insert_transaction(2, 1, Amount(10));
+        ledger.insert_transaction(2, 2, -Amount(4));
+        ledger.insert_transaction(1, 3, Amount(5));
+        ledger.hold(2, 1);
+        ledger.resolve(2, 1);
+        ledger.hold(2, 1);
+        ledger.hold(2, 2);
+        ledger.chageback(2, 2);
+
+        // Ignored disputes are not counted
+        ledger.hold(1, 1);
+        ledger.hold(1, 4);
+
+        let report = ledger.generate_audit_report();
+        assert_eq!(
+            report.clients,
+            vec![
+                ClientAudit {
+                    client_id: 1,
+                    final_available: Amount(5),
+                    final_held: Amount(0),
+                    final_total: Amount(5),
+                    is_locked: false,
+                    total_deposited: Amount(5),
+                    total_withdrawn: Amount(0),
+                    chargeback_count: 0,
+                    dispute_count: 0,
+                },
+                ClientAudit {
+                    client_id: 2,
+                    final_available: Amount(0),
+                    final_held: Amount(10),
+                    final_total: Amount(10),
+                    is_locked: true,
+                    total_deposited: Amount(10),
+                    total_withdrawn: Amount(4),
+                    chargeback_count: 1,
+                    dispute_count: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_dispute_counts() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.hold(1, 1);
+
+        let mut other = Ledger::default();
+        other.insert_transaction(1, 2, Amount(5));
+        other.hold(1, 2);
+        other.chageback(1, 2);
+
+        ledger.merge(other).unwrap();
+
+        let c = ledger.clients.get(&1).unwrap();
+        assert_eq!((c.dispute_count(), c.chargeback_count()), (2, 1));
+    }
+
+    #[test]
+    fn client_states() {
+        let mut ledger = Ledger::default();
+        assert!(ledger.active_clients().is_empty());
+        assert!(ledger.zero_balance_clients().is_empty());
+
+        // Active
+        ledger.insert_transaction(4, 1, Amount(10));
+        ledger.insert_transaction(1, 2, Amount(10));
+
+        // Locked
+        ledger.insert_transaction(2, 3, Amount(10));
+        ledger.insert_transaction(2, 4, Amount(5));
+        ledger.hold(2, 3);
+        ledger.chageback(2, 3);
 
-impl ClientData {
-    fn new(amount: i64) -> Self {
-        ClientData {
-            held: BTreeMap::new(),
-            available: amount,
-            total: amount,
-            locked: false,
-        }
-    }
-}
+        // Zero balance
+        ledger.insert_transaction(3, 5, Amount(10));
+        ledger.insert_transaction(3, 6, -Amount(10));
 
-impl Display for ClientData {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}, {}, {}, {}",
-            dp_string(self.available),
-            dp_string(self.held.values().sum()),
-            dp_string(self.total),
-            self.locked
-        )
-    }
-}
+        // Zero total, but with disputed funds
+        ledger.insert_transaction(5, 7, Amount(10));
+        ledger.insert_transaction(5, 8, -Amount(10));
+        ledger.hold(5, 7);
 
-/// Convert a i64 to a string with four decimal places (eg val / 100)
-fn dp_string(amount: i64) -> String {
-    format!("{}.{:04}", amount / 10000, amount % 10000)
-}
+        assert_eq!(ledger.locked_clients(), vec![2]);
+        assert_eq!(ledger.active_clients(), vec![1, 4]);
+        assert_eq!(ledger.zero_balance_clients(), vec![3]);
+    }
 
-#[cfg(test)]
-mod dp_string {
-    use super::dp_string;
     #[test]
-    fn test_dp_string() {
-        assert_eq!(dp_string(0), "0.0000");
-        assert_eq!(dp_string(1), "0.0001");
-        assert_eq!(dp_string(10), "0.0010");
-        assert_eq!(dp_string(100), "0.0100");
-        assert_eq!(dp_string(1000), "0.1000");
-        assert_eq!(dp_string(10000), "1.0000");
-    }
-}
+    fn merge_overflow() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(i64::MAX));
 
-#[cfg(test)]
-mod validate_header {
-    use super::validate_header;
-    use std::io::{BufReader, Cursor, Error, ErrorKind, Read};
+        let mut other = Ledger::default();
+        other.insert_transaction(2, 2, Amount(1));
+        other.insert_transaction(1, 3, Amount(1));
 
-    struct TestReader {}
+        let err = ledger.merge(other).unwrap_err();
 
-    impl Read for TestReader {
-        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
-            Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
-        }
+        // The ledger is left unchanged, including clients that could have been merged
+        assert!(matches!(err, LedgerErr::Overflow(1)));
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(i64::MAX));
+        assert!(!ledger.clients.contains_key(&2));
+        assert_eq!(ledger.transactions.len(), 1);
     }
 
     #[test]
-    fn ok() {
-        validate_header(&mut BufReader::new(Cursor::new("type, client, tx, amount"))).unwrap();
+    fn err_merge_duplicate() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+
+        let mut other = Ledger::default();
+        other.insert_transaction(2, 2, Amount(10));
+        other.insert_transaction(2, 1, Amount(10));
+
+        let err = ledger.merge(other).unwrap_err();
+
+        // The ledger is left unchanged
+        assert!(matches!(
+            err,
+            LedgerErr::Duplicate {
+                tx_id: 1,
+                first_line: None,
+                second_line: None
+            }
+        ));
+        assert_eq!(ledger.clients.len(), 1);
+        assert_eq!(ledger.transactions.len(), 1);
     }
 
     #[test]
-    fn err_runthrough() {
-        validate_header(&mut BufReader::new(TestReader {})).unwrap_err();
-        validate_header(&mut BufReader::new(Cursor::new(""))).unwrap_err();
-        validate_header(&mut BufReader::new(Cursor::new("\n"))).unwrap_err();
-        validate_header(&mut BufReader::new(Cursor::new("type,"))).unwrap_err();
-    }
-}
+    fn locked_clients() {
+        let mut ledger = Ledger::default();
+        assert!(ledger.locked_clients().is_empty());
 
-#[cfg(test)]
-mod client_data {
-    use super::ClientData;
+        for id in 1..=3 {
+            ledger.insert_transaction(id, id as u32, Amount(10));
+        }
+        ledger.hold(2, 2);
+        ledger.chageback(2, 2);
+
+        assert_eq!(ledger.locked_clients(), vec![2]);
+    }
 
     #[test]
-    fn debug() {
-        let data = ClientData::new(10);
+    fn disputes_csv() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.disputes_csv(), "client, tx, amount");
+
+        ledger.insert_transaction(2, 1, Amount(20000));
+        ledger.insert_transaction(1, 2, Amount(15000));
+        ledger.insert_transaction(1, 3, Amount(5));
+        ledger.insert_transaction(2, 4, Amount(1));
+        ledger.hold(2, 1);
+        ledger.hold(1, 3);
+        ledger.hold(1, 2);
 
         assert_eq!(
-            format!("{:?}", data),
-            "ClientData { held: {}, available: 10, total: 10, locked: false }"
+            ledger.disputes_csv(),
+            "client, tx, amount\n1, 2, 1.5000\n1, 3, 0.0005\n2, 1, 2.0000"
         );
     }
-}
 
-#[cfg(test)]
-mod ledger {
-    use super::{ClientData, Ledger};
-    use std::collections::BTreeMap;
-    use std::io::{BufReader, Cursor, Error, ErrorKind, Read};
+    #[test]
+    fn to_json_string() {
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.to_json_string().unwrap(), "[]");
 
-    struct TestReader {}
+        ledger.insert_transaction(2, 1, Amount(20000));
+        ledger.insert_transaction(1, 2, Amount(15000));
+        ledger.hold(1, 2);
+        ledger.chageback(1, 2);
 
-    impl Read for TestReader {
-        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
-            Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
-        }
+        assert_eq!(
+            ledger.to_json_string().unwrap(),
+            concat!(
+                r#"[{"client":1,"available":"0.0000","held":"0.0000","total":"0.0000","locked":true},"#,
+                r#"{"client":2,"available":"2.0000","held":"0.0000","total":"2.0000","locked":false}]"#
+            )
+        );
     }
 
-    struct TestReaderTwo<'a> {
-        inner: Cursor<&'a str>,
-        state: bool,
-    }
+    #[test]
+    fn to_writer_json() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(1));
+        ledger.insert_transaction(2, 2, Amount(2));
 
-    // Fail after second read
-    impl Read for TestReaderTwo<'_> {
-        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-            if self.state {
-                Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
-            } else {
-                self.state = true;
-                Ok(self.inner.read(buf).unwrap())
-            }
-        }
+        let mut buf = Vec::new();
+        ledger
+            .to_writer(
+                &mut buf,
+                &OutputOptions::default()
+                    .with_format(OutputFormat::Json)
+                    .with_filter_clients(vec![2]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"[{"client":2,"available":"0.0002","held":"0.0000","total":"0.0002","locked":false}]"#
+        );
     }
 
     #[test]
-    fn ok_consume() {
+    fn to_writer_precision() {
         let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(12345));
+        ledger.insert_transaction(2, 2, Amount(500));
 
+        let mut buf = Vec::new();
         ledger
-            .consume_csv(BufReader::new(Cursor::new(
-                "type, client, tx, amount
-                
-                deposit, 1, 1, 20.0
-                withdrawal,1,2,10.0
-                dispute,1,2,
-                resolve,1,2,
-            
-                deposit,2,3,113.1112
-                dispute,2,3,
-                chargeback,2,3,
-                
-                ",
-            )))
+            .to_writer(&mut buf, &OutputOptions::default().with_precision(2))
             .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("1, 1.23, 0.00, 1.23, false"));
+        assert!(output.contains("2, 0.05, 0.00, 0.05, false"));
 
-        let result = ledger.to_string();
-        let mut lines = result.lines();
-
+        let mut buf = Vec::new();
+        ledger
+            .to_writer(
+                &mut buf,
+                &OutputOptions::default()
+                    .with_format(OutputFormat::Json)
+                    .with_precision(0),
+            )
+            .unwrap();
         assert_eq!(
-            lines.next().unwrap(),
-            "client, available, held, total, locked"
+            String::from_utf8(buf).unwrap(),
+            concat!(
+                r#"[{"client":1,"available":"1","held":"0","total":"1","locked":false},"#,
+                r#"{"client":2,"available":"0","held":"0","total":"0","locked":false}]"#
+            )
         );
-
-        let accounts = vec![
-            "1, 10.0000, 0.0000, 10.0000, false",
-            "2, 0.0000, 0.0000, 0.0000, true",
-        ];
-
-        assert!(accounts.contains(&lines.next().unwrap()));
-        assert!(accounts.contains(&lines.next().unwrap()));
-        assert!(lines.next().is_none())
     }
 
     #[test]
-    fn err_consume_runthrough() {
+    fn to_writer_sort_by() {
         let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(20000));
+        ledger.insert_transaction(2, 2, Amount(30000));
+        ledger.insert_transaction(3, 3, Amount(10000));
+        ledger.insert_transaction(4, 4, Amount(30000));
+        ledger.hold(4, 4);
 
-        ledger
-            .consume_csv(BufReader::new(Cursor::new("")))
-            .unwrap_err();
+        let output = |sort_by| {
+            let mut buf = Vec::new();
+            ledger
+                .to_writer(
+                    &mut buf,
+                    &OutputOptions::default()
+                        .with_omit_header(true)
+                        .with_sort_by(sort_by),
+                )
+                .unwrap();
+            String::from_utf8(buf)
+                .unwrap()
+                .lines()
+                .map(|line| line.split(',').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
 
-        ledger
-            .consume_csv(BufReader::new(Cursor::new(&[0x0])))
-            .unwrap_err();
+        assert_eq!(output(SortBy::Client), ["1", "2", "3", "4"]);
+        assert_eq!(output(SortBy::Total), ["2", "4", "1", "3"]);
+        assert_eq!(output(SortBy::Available), ["2", "1", "3", "4"]);
 
+        // Csv output is sorted by client by default, as JSON output is
+        let mut buf = Vec::new();
         ledger
-            .consume_csv(BufReader::new(TestReader {}))
-            .unwrap_err();
+            .to_writer(&mut buf, &OutputOptions::default().with_omit_header(true))
+            .unwrap();
+        let ids: Vec<_> = String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect();
+        assert_eq!(ids, ["1", "2", "3", "4"]);
 
+        // Sorting overrides the order of the filter
+        let mut buf = Vec::new();
         ledger
-            .consume_csv(BufReader::new(TestReaderTwo {
-                inner: Cursor::new("type, client, tx, amount\n"),
-                state: false,
-            }))
-            .unwrap_err();
+            .to_writer(
+                &mut buf,
+                &OutputOptions::default()
+                    .with_format(OutputFormat::Json)
+                    .with_filter_clients(vec![3, 1])
+                    .with_sort_by(SortBy::Client),
+            )
+            .unwrap();
+        assert!(String::from_utf8(buf)
+            .unwrap()
+            .starts_with(r#"[{"client":1,"#));
+    }
 
-        ledger
-            .consume_csv(BufReader::new(Cursor::new("type, client, tx, amount\n123")))
-            .unwrap_err();
+    #[test]
+    fn sort_by_from_str() {
+        assert_eq!("client".parse(), Ok(SortBy::Client));
+        assert_eq!("total".parse(), Ok(SortBy::Total));
+        assert_eq!("available".parse(), Ok(SortBy::Available));
+        assert!("held".parse::<SortBy>().is_err());
     }
 
     #[test]
-    fn insert_transaction() {
-        let mut client_2 = ClientData::new(0);
-        client_2.locked = true;
+    fn output_format_from_str() {
+        assert_eq!("csv".parse(), Ok(OutputFormat::Csv));
+        assert_eq!("json".parse(), Ok(OutputFormat::Json));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
 
-        let mut ledger = Ledger {
-            clients: [(2_u16, client_2)].into_iter().collect(),
-            transactions: BTreeMap::new(),
-        };
+    #[test]
+    fn err_to_writer() {
+        struct TestWriter {}
 
-        ledger.insert_transaction(1, 1, 1);
-        ledger.insert_transaction(1, 2, 1);
+        impl Write for TestWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(Error::new(ErrorKind::InvalidData, "Something went wrong."))
+            }
 
-        // Locked
-        ledger.insert_transaction(2, 3, 1);
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
 
-        let client_1 = ledger.clients.get(&1).unwrap();
-        let client_2 = ledger.clients.get(&2).unwrap();
-        assert_eq!(client_1.available, 2);
-        assert_eq!(client_2.available, 0);
-        assert_eq!(client_1.total, 2);
-        assert_eq!(client_2.total, 0);
+        Ledger::default()
+            .to_writer(&mut TestWriter {}, &OutputOptions::default())
+            .unwrap_err();
     }
 
     #[test]
-    fn dispute() {
+    fn debug() {
+        assert_eq!(
+            format!("{:?}", Ledger::default()),
+            "Ledger { clients: 0, transactions: 0 }"
+        );
+
         let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, Amount(10));
+        assert_eq!(
+            format!("{:?}", ledger),
+            "Ledger { clients: 1, transactions: 2 }"
+        );
+    }
 
-        ledger.insert_transaction(1, 1, 1);
-        ledger.hold(1, 1);
-        ledger.hold(2, 1);
-        ledger.hold(1, 2);
+    #[test]
+    fn debug_alternate() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
 
-        let c = ledger.clients.get(&1).unwrap();
+        let debug = format!("{:#?}", ledger);
+        assert!(debug.starts_with("Ledger {\n    clients: {\n        1: ClientData {"));
+        assert!(debug.contains("    transactions: {\n        1: (\n            1,\n"));
+        assert!(debug.ends_with("    event_log: [],\n}"));
+    }
 
-        assert_eq!(ledger.clients.len(), 1);
-        assert_eq!(c.held.get(&1).unwrap(), &1_i64);
-        assert_eq!(c.available, 0_i64);
+    #[test]
+    fn display() {
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(1));
+        assert_eq!(
+            format!("{}", ledger),
+            "client, available, held, total, locked\n1, 0.0001, 0.0000, 0.0001, false"
+        );
     }
+}
+
+#[cfg(all(test, feature = "persist"))]
+mod persist {
+    use super::{Ledger, MAGIC};
+    use crate::{amount::Amount, LedgerErr};
+    use std::{
+        fs,
+        io::{BufReader, Cursor},
+    };
+    use tempfile::tempdir;
 
     #[test]
-    fn resolve() {
+    fn round_trip() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("ledger.bin");
+
         let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.insert_transaction(1, 2, -Amount(4));
+        ledger.insert_transaction(2, 3, Amount(20));
+        ledger.insert_transaction(3, 4, Amount(30));
+        ledger.hold(2, 3);
+        ledger.insert_transaction(2, 6, -Amount(5));
+        ledger.hold(2, 6);
+        ledger.hold(3, 4);
+        ledger.chageback(3, 4);
+        ledger.clients.get_mut(&1).unwrap().first_line = Some(2);
+        ledger
+            .insert_transaction_with_currency(4, 5, Amount(1), "EUR")
+            .unwrap();
+        ledger.timestamps.insert(5, 1672531200);
 
-        ledger.insert_transaction(1, 1, 1);
-        ledger.hold(1, 1);
-        ledger.resolve(1, 1);
-        ledger.resolve(2, 1);
-        ledger.resolve(1, 2);
+        ledger.save(&path).unwrap();
+        let loaded = Ledger::load(&path).unwrap();
 
-        let c = ledger.clients.get(&1).unwrap();
-        assert_eq!(c.held.len(), 0);
-        assert_eq!(c.available, 1_i64);
+        assert_eq!(loaded.clients, ledger.clients);
+        assert_eq!(loaded.transactions, ledger.transactions);
+        assert_eq!(loaded.timestamps, ledger.timestamps);
+        assert_eq!(loaded.transaction_timestamp(5), Some(1672531200));
     }
 
     #[test]
-    fn chargeback() {
+    fn snapshot_continue() {
         let mut ledger = Ledger::default();
+        ledger
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                deposit, 1, 1, 5.0\n\
+                deposit, 2, 2, 3.0\n\
+                dispute, 2, 2,",
+            )))
+            .unwrap();
 
-        ledger.insert_transaction(1, 1, 1);
-        ledger.hold(1, 1);
-        ledger.chageback(1, 1);
-        ledger.chageback(2, 1);
-        ledger.chageback(1, 2);
+        let mut snapshot = Vec::new();
+        ledger.save_snapshot(&mut snapshot).unwrap();
+        let mut loaded = Ledger::load_snapshot(snapshot.as_slice()).unwrap();
 
-        let c = ledger.clients.get(&1).unwrap();
-        assert_eq!(c.held.len(), 0);
-        assert_eq!(c.total, 0_i64);
-        assert_eq!(c.locked, true);
+        assert_eq!(loaded.clients, ledger.clients);
+        assert_eq!(loaded.transactions, ledger.transactions);
+
+        // Transactions recorded in the snapshot can be disputed and resolved
+        loaded
+            .consume_csv(BufReader::new(Cursor::new(
+                "type, client, tx, amount\n\
+                dispute, 1, 1,\n\
+                chargeback, 1, 1,\n\
+                resolve, 2, 2,",
+            )))
+            .unwrap();
+
+        assert_eq!(
+            loaded.to_string_filtered(&[1, 2]).unwrap(),
+            "client, available, held, total, locked\n\
+            1, 0.0000, 0.0000, 0.0000, true\n\
+            2, 3.0000, 0.0000, 3.0000, false"
+        );
     }
 
     #[test]
-    fn debug() {
+    fn err_snapshot() {
+        let err = Ledger::load_snapshot(&b"CSVL"[..]).unwrap_err();
+        assert!(matches!(err, LedgerErr::Reading(_)));
+    }
+
+    #[test]
+    fn err_magic() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("ledger.bin");
+
+        fs::write(&path, "type, client, tx, amount").unwrap();
+        let err = Ledger::load(&path).unwrap_err();
         assert_eq!(
-            format!("{:?}", Ledger::default()),
-            "Ledger { clients: {}, transactions: {} }"
-        )
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: File is not a saved ledger"
+        );
     }
 
     #[test]
-    fn display() {
+    fn err_version() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("ledger.bin");
+
+        fs::write(&path, [&MAGIC[..], &[255]].concat()).unwrap();
+        let err = Ledger::load(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: Unsupported saved ledger version 255"
+        );
+    }
+
+    #[test]
+    fn err_truncated() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("ledger.bin");
+
+        let mut ledger = Ledger::default();
+        ledger.insert_transaction(1, 1, Amount(10));
+        ledger.save(&path).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(Ledger::load(&path), Err(LedgerErr::Reading(_))));
+    }
+
+    #[test]
+    fn err_currency_len() {
         let mut ledger = Ledger::default();
-        ledger.insert_transaction(1, 1, 1);
+        ledger
+            .insert_transaction_with_currency(1, 1, Amount(10), "EUR")
+            .unwrap();
+        let mut snapshot = Vec::new();
+        ledger.save_snapshot(&mut snapshot).unwrap();
+
+        // Overwrite the length of the currency, which follows the header and fixed-size client fields
+        let offset = 4 + 1 + 4 + 2 + 4 * 8 + 2 + 8 + 4 + 4 + 1;
+        assert_eq!(snapshot[offset..offset + 4], 3u32.to_le_bytes());
+        snapshot[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = Ledger::load_snapshot(snapshot.as_slice()).unwrap_err();
         assert_eq!(
-            format!("{}", ledger),
-            "client, available, held, total, locked\n1, 0.0001, 0.0000, 0.0001, false"
+            err.to_string(),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: Saved currency is longer than 16 bytes"
         );
+
+        ledger.clients.get_mut(&1).unwrap().currency = Some("X".repeat(17));
+        assert!(matches!(
+            ledger.save_snapshot(Vec::new()),
+            Err(LedgerErr::Saving(_))
+        ));
+    }
+
+    #[test]
+    fn err_paths() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("foo/ledger.bin");
+
+        assert!(matches!(
+            Ledger::default().save(&path),
+            Err(LedgerErr::Saving(_))
+        ));
+        assert!(matches!(Ledger::load(&path), Err(LedgerErr::Opening(_))));
+    }
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod parallel {
+    use super::Ledger;
+    use crate::{amount::Amount, LedgerErr};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn consume_csv_parallel() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let paths: Vec<_> = (0..4)
+            .map(|file| {
+                let path = dir.path().join(format!("{file}.csv"));
+                let input = format!(
+                    "type, client, tx, amount\ndeposit, 1, {}, 1.0\ndeposit, {}, {}, 2.0",
+                    file * 2,
+                    file + 2,
+                    file * 2 + 1
+                );
+                fs::write(&path, input).expect("Unable to write file");
+                path
+            })
+            .collect();
+
+        let ledger = Ledger::consume_csv_parallel(&paths).unwrap();
+
+        assert_eq!(ledger.clients.len(), 5);
+        assert_eq!(ledger.clients.get(&1).unwrap().total, Amount(40000));
+        assert_eq!(ledger.clients.get(&5).unwrap().total, Amount(20000));
+        assert_eq!(ledger.transactions.len(), 8);
+        assert!(Ledger::consume_csv_parallel(&[])
+            .unwrap()
+            .clients
+            .is_empty());
+    }
+
+    #[test]
+    fn err_consume_csv_parallel() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let first = dir.path().join("first.csv");
+        let second = dir.path().join("second.csv");
+        fs::write(&first, "type, client, tx, amount\ndeposit, 1, 1, 1.0").unwrap();
+        fs::write(&second, "type, client, tx, amount\ndeposit, 2, 1, 1.0").unwrap();
+
+        let err = Ledger::consume_csv_parallel(&[first.clone(), second]).unwrap_err();
+        assert!(matches!(err, LedgerErr::Duplicate { tx_id: 1, .. }));
+
+        let err =
+            Ledger::consume_csv_parallel(&[first, dir.path().join("missing.csv")]).unwrap_err();
+        assert!(matches!(err, LedgerErr::Opening(_)));
     }
 }
@@ -1,15 +1,47 @@
 //! # `csv_ledger_lib`
 //!  A sub-library for the `csv_leger` CLI.
 //!
-//! This library contains two modules:
+//! This library contains four modules:
+//! - `amount` - Containing the `Amount` fixed-point monetary value.
+//! - `audit` - Containing the `AuditReport` per-client summary.
 //! - `ledger` - Containing the `Ledger` state store.
 //! - `parse` - Containing a zero-coppy csv parser for transactions.
+//!
+//! With the `log` feature enabled, ledger operations are reported through the `log` crate,
+//! allowing any compatible backend to record an audit trail.
+
+/// Log a debug message when the `log` feature is enabled, otherwise do nothing.
+macro_rules! log_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    }};
+}
+
+/// Log an info message when the `log` feature is enabled, otherwise do nothing.
+macro_rules! log_info {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::info!($($arg)*);
+    }};
+}
+
+/// Log a warning when the `log` feature is enabled, otherwise do nothing.
+macro_rules! log_warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+    }};
+}
 
+pub mod amount;
+pub mod audit;
 pub mod ledger;
 pub mod parse;
 
 use core::fmt;
 use nom::Err as NomErr;
+use parse::ParseErr;
 use std::{fmt::Display, io};
 
 #[derive(Debug)]
@@ -18,63 +50,430 @@ pub enum LedgerErr {
     Opening(io::Error),
     Reading(io::Error),
     Saving(io::Error),
-    Parse(String, usize),
+    /// The csv file contained no bytes at all, or its header line was blank.
+    /// A file with only a header is valid.
+    EmptyFile,
+    Parse {
+        kind: ParseKind,
+        message: String,
+        line: usize,
+        /// The original content of the line that failed to parse, when available.
+        raw: Option<String>,
+    },
+    RowLimitExceeded(usize),
+    /// More transactions were applied than the configured maximum allows.
+    TransactionLimitExceeded(usize),
+    ClientNotFound(u16),
+    InvalidOperation(String, usize),
+    /// A transaction built programmatically failed validation.
+    InvalidTransaction(String),
+    /// A transaction id was used more than once. The lines are those of the csv files each
+    /// transaction was read from, if any.
+    Duplicate {
+        tx_id: u32,
+        first_line: Option<usize>,
+        second_line: Option<usize>,
+    },
+    /// A transaction could not be found in the ledger.
+    NotFound(u32),
+    /// A transaction could not be changed as it is currently disputed.
+    AlreadyDisputed(u32),
+    /// A transaction was in a different currency to the client's existing transactions.
+    CurrencyMismatch {
+        client: u16,
+        expected: String,
+        found: String,
+    },
+    /// A dispute, resolve or chargeback named a different client to the one that owns the
+    /// transaction.
+    WrongClient {
+        tx_id: u32,
+        expected_client: u16,
+        actual_client: u16,
+    },
+    /// A line of the csv had more fields than the header, or the default layout, describes.
+    WrongFieldCount {
+        expected: usize,
+        found: usize,
+        line: usize,
+    },
+    /// A change to the balance of a client would overflow.
+    Overflow(u16),
+    /// A parse error rendered from a nom `VerboseError`, available with the `verbose-errors` feature.
+    ParseVerbose(String, usize),
+}
+
+/// The category of a parse error, as reported by nom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseKind {
+    /// The input ended before it could be parsed.
+    Incomplete,
+    /// The input was in the wrong format.
+    Format,
+    /// The input could not be parsed, and no alternative was attempted.
+    Failure,
+}
+
+impl<E> From<&NomErr<E>> for ParseKind {
+    fn from(err: &NomErr<E>) -> Self {
+        match err {
+            NomErr::Incomplete(_) => ParseKind::Incomplete,
+            NomErr::Error(_) => ParseKind::Format,
+            NomErr::Failure(_) => ParseKind::Failure,
+        }
+    }
 }
 
 impl LedgerErr {
-    fn from_parse<E>(err: NomErr<E>, index: usize) -> LedgerErr {
-        LedgerErr::Parse(
-            match err {
-                NomErr::Incomplete(_) => "Input was incomplete",
-                NomErr::Error(_) => "Input was in the wrong format",
-                NomErr::Failure(_) => "Faliure whilst parsing input",
+    fn from_parse<E>(err: NomErr<E>, index: usize, raw: Option<String>) -> LedgerErr {
+        let kind = ParseKind::from(&err);
+        LedgerErr::Parse {
+            kind,
+            message: match kind {
+                ParseKind::Incomplete => "Input was incomplete",
+                ParseKind::Format => "Input was in the wrong format",
+                ParseKind::Failure => "Faliure whilst parsing input",
             }
             .to_string(),
-            index,
-        )
+            line: index,
+            raw,
+        }
     }
-}
 
-impl Display for LedgerErr {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (msg, e) = match self {
-            LedgerErr::Opening(e) => ("opening the csv", e),
-            LedgerErr::Reading(e) => ("reading in the csv", e),
-            LedgerErr::Saving(e) => ("saving the output file", e),
-            LedgerErr::Parse(e, index) => {
-                return write!(
-                    f,
-                    "Ledger Error 🦀 - Issue whilst parsing csv: \"{}\", At line: {index}",
-                    e
+    /// Construct a parse error for a line of input that failed to parse.
+    #[cfg(not(feature = "verbose-errors"))]
+    fn from_line(err: NomErr<ParseErr<'_>>, input: &str, index: usize) -> LedgerErr {
+        LedgerErr::from_parse(err, index, Some(input.to_string()))
+    }
+
+    /// Construct a parse error for a line of input that failed to parse,
+    /// describing each parser in the error's stack.
+    #[cfg(feature = "verbose-errors")]
+    fn from_line(err: NomErr<ParseErr<'_>>, input: &str, index: usize) -> LedgerErr {
+        use nom::error::{convert_error, VerboseError};
+
+        let err = match err {
+            NomErr::Error(err) | NomErr::Failure(err) => err,
+            NomErr::Incomplete(needed) => {
+                return LedgerErr::from_parse(
+                    NomErr::<ParseErr>::Incomplete(needed),
+                    index,
+                    Some(input.to_string()),
                 )
             }
         };
 
+        // Errors constructed by `nom_err` carry a message rather than a position within the input
+        let range = input.as_ptr() as usize..=input.as_ptr() as usize + input.len();
+        let (located, messages): (Vec<_>, Vec<_>) = err.errors.into_iter().partition(|(i, _)| {
+            range.contains(&(i.as_ptr() as usize))
+                && range.contains(&(i.as_ptr() as usize + i.len()))
+        });
+
+        let mut description: Vec<String> = messages
+            .into_iter()
+            .map(|(message, _)| format!("{message} Content: \"{input}\""))
+            .collect();
+        if !located.is_empty() {
+            description.push(convert_error(input, VerboseError { errors: located }));
+        }
+
+        LedgerErr::ParseVerbose(description.join("\n"), index)
+    }
+
+    /// A stable code identifying the category of the error, for branching on errors
+    /// without matching on their message.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::LedgerErr;
+    ///
+    /// assert_eq!(LedgerErr::ClientNotFound(7).code(), "client_not_found");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match self {
+            LedgerErr::Opening(_) => "open",
+            LedgerErr::Reading(_) => "read",
+            LedgerErr::Saving(_) => "save",
+            LedgerErr::EmptyFile => "empty",
+            LedgerErr::Parse { kind, .. } => match kind {
+                ParseKind::Incomplete => "parse.incomplete",
+                ParseKind::Format => "parse.format",
+                ParseKind::Failure => "parse.failure",
+            },
+            LedgerErr::RowLimitExceeded(_) => "row_limit",
+            LedgerErr::TransactionLimitExceeded(_) => "transaction_limit",
+            LedgerErr::ClientNotFound(_) => "client_not_found",
+            LedgerErr::InvalidOperation(..) => "invalid_operation",
+            LedgerErr::InvalidTransaction(_) => "invalid_transaction",
+            LedgerErr::Duplicate { .. } => "duplicate",
+            LedgerErr::NotFound(_) => "not_found",
+            LedgerErr::AlreadyDisputed(_) => "already_disputed",
+            LedgerErr::CurrencyMismatch { .. } => "currency_mismatch",
+            LedgerErr::WrongClient { .. } => "wrong_client",
+            LedgerErr::WrongFieldCount { .. } => "wrong_field_count",
+            LedgerErr::Overflow(_) => "overflow",
+            LedgerErr::ParseVerbose(..) => "parse",
+        }
+    }
+
+    /// Render the error in a plain `error: <reason>` form, without the emoji or decoration.
+    /// Useful for log parsers that expect machine-readable output.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::LedgerErr;
+    ///
+    /// assert_eq!(
+    ///     LedgerErr::ClientNotFound(7).to_plain(),
+    ///     "error: filtering output: Client 7 was not found"
+    /// );
+    /// ```
+    pub fn to_plain(&self) -> String {
+        let (msg, e) = self.describe();
+        format!("error: {msg}: {e}")
+    }
+
+    /// Render the error as a single-line JSON object, for structured logging.
+    /// The `line` field is only present for errors tied to a line of input.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::LedgerErr;
+    ///
+    /// assert_eq!(
+    ///     LedgerErr::InvalidOperation("Chargeback without a dispute".into(), 5).to_json(),
+    ///     r#"{"error_type": "InvalidOperation", "message": "Chargeback without a dispute", "line": 5}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let (message, line) = match self {
+            LedgerErr::Parse { message, line, .. } => (message.clone(), Some(*line)),
+            LedgerErr::InvalidOperation(message, line) | LedgerErr::ParseVerbose(message, line) => {
+                (message.clone(), Some(*line))
+            }
+            LedgerErr::WrongFieldCount {
+                expected,
+                found,
+                line,
+            } => (field_count_message(*expected, *found), Some(*line)),
+            _ => (self.describe().1, None),
+        };
+
+        match line {
+            Some(line) => format!(
+                r#"{{"error_type": "{}", "message": "{}", "line": {line}}}"#,
+                self.error_type(),
+                json_escape(&message)
+            ),
+            None => format!(
+                r#"{{"error_type": "{}", "message": "{}"}}"#,
+                self.error_type(),
+                json_escape(&message)
+            ),
+        }
+    }
+
+    /// The name of the error's variant.
+    fn error_type(&self) -> &'static str {
+        match self {
+            LedgerErr::Opening(_) => "Opening",
+            LedgerErr::Reading(_) => "Reading",
+            LedgerErr::Saving(_) => "Saving",
+            LedgerErr::EmptyFile => "EmptyFile",
+            LedgerErr::Parse { .. } => "Parse",
+            LedgerErr::RowLimitExceeded(_) => "RowLimitExceeded",
+            LedgerErr::TransactionLimitExceeded(_) => "TransactionLimitExceeded",
+            LedgerErr::ClientNotFound(_) => "ClientNotFound",
+            LedgerErr::InvalidOperation(..) => "InvalidOperation",
+            LedgerErr::InvalidTransaction(_) => "InvalidTransaction",
+            LedgerErr::Duplicate { .. } => "Duplicate",
+            LedgerErr::NotFound(_) => "NotFound",
+            LedgerErr::AlreadyDisputed(_) => "AlreadyDisputed",
+            LedgerErr::CurrencyMismatch { .. } => "CurrencyMismatch",
+            LedgerErr::WrongClient { .. } => "WrongClient",
+            LedgerErr::WrongFieldCount { .. } => "WrongFieldCount",
+            LedgerErr::Overflow(_) => "Overflow",
+            LedgerErr::ParseVerbose(..) => "ParseVerbose",
+        }
+    }
+
+    /// The action that failed, alongside the reason it failed.
+    fn describe(&self) -> (&'static str, String) {
+        match self {
+            LedgerErr::Opening(e) => ("opening the csv", e.to_string()),
+            LedgerErr::Reading(e) => ("reading in the csv", e.to_string()),
+            LedgerErr::Saving(e) => ("saving the output file", e.to_string()),
+            LedgerErr::EmptyFile => (
+                "reading in the csv",
+                "The file is empty or missing its header".to_string(),
+            ),
+            LedgerErr::Parse {
+                message, line, raw, ..
+            } => (
+                "parsing csv",
+                match raw {
+                    Some(raw) => format!("\"{message}\", At line: {line}, Content: \"{raw}\""),
+                    None => format!("\"{message}\", At line: {line}"),
+                },
+            ),
+            LedgerErr::InvalidOperation(e, index) => {
+                ("applying transaction", format!("\"{e}\", At line: {index}"))
+            }
+            LedgerErr::InvalidTransaction(e) => ("building transaction", e.to_string()),
+            LedgerErr::RowLimitExceeded(max) => (
+                "consuming csv",
+                format!("Exceeded the maximum of {max} rows"),
+            ),
+            LedgerErr::TransactionLimitExceeded(max) => (
+                "consuming csv",
+                format!("Exceeded the maximum of {max} transactions"),
+            ),
+            LedgerErr::ClientNotFound(id) => {
+                ("filtering output", format!("Client {id} was not found"))
+            }
+            LedgerErr::Duplicate {
+                tx_id,
+                first_line,
+                second_line,
+            } => (
+                "recording transaction",
+                match (first_line, second_line) {
+                    (Some(first), Some(second)) => format!(
+                        "Duplicate transaction ID {tx_id} first seen at line {first}, repeated at line {second}"
+                    ),
+                    _ => format!("Duplicate transaction ID {tx_id}"),
+                },
+            ),
+            LedgerErr::NotFound(id) => (
+                "rolling back transaction",
+                format!("Transaction {id} was not found"),
+            ),
+            LedgerErr::AlreadyDisputed(id) => (
+                "rolling back transaction",
+                format!("Transaction {id} is disputed"),
+            ),
+            LedgerErr::CurrencyMismatch {
+                client,
+                expected,
+                found,
+            } => (
+                "applying transaction",
+                format!("Client {client} holds {expected}, but the transaction is in {found}"),
+            ),
+            LedgerErr::WrongClient {
+                tx_id,
+                expected_client,
+                actual_client,
+            } => (
+                "applying transaction",
+                format!(
+                    "Transaction {tx_id} belongs to client {expected_client}, not client {actual_client}"
+                ),
+            ),
+            LedgerErr::WrongFieldCount {
+                expected,
+                found,
+                line,
+            } => (
+                "parsing csv",
+                format!(
+                    "\"{}\", At line: {line}",
+                    field_count_message(*expected, *found)
+                ),
+            ),
+            LedgerErr::Overflow(id) => (
+                "updating a balance",
+                format!("The balance of client {id} would overflow"),
+            ),
+            LedgerErr::ParseVerbose(e, index) => ("parsing csv", format!("At line: {index}\n{e}")),
+        }
+    }
+}
+
+/// The message of a `LedgerErr::WrongFieldCount`.
+fn field_count_message(expected: usize, found: usize) -> String {
+    format!("Unexpected extra fields, expected {expected} but found {found}")
+}
+
+/// Escape a string for inclusion within a JSON string literal.
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Converts IO errors into `LedgerErr::Reading`, allowing `?` to be used on IO operations.
+/// Note: `LedgerErr::Opening` and `LedgerErr::Saving` must be constructed manually.
+impl From<io::Error> for LedgerErr {
+    fn from(err: io::Error) -> Self {
+        LedgerErr::Reading(err)
+    }
+}
+
+impl Display for LedgerErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (msg, e) = self.describe();
         write!(f, "Ledger Error 🦀 - Issue whilst {msg}: {}", e)
     }
 }
 
 #[cfg(test)]
 mod ledger_err {
-    use crate::LedgerErr;
+    use crate::{LedgerErr, ParseKind};
     use nom::{error::ErrorKind, Err as NomErr, Needed};
 
     #[test]
     fn from_parse() {
         assert_eq!(
-            LedgerErr::from_parse(NomErr::Incomplete::<Needed>(Needed::Unknown), 1).to_string(),
+            LedgerErr::from_parse(NomErr::Incomplete::<Needed>(Needed::Unknown), 1, None)
+                .to_string(),
             "Ledger Error 🦀 - Issue whilst parsing csv: \"Input was incomplete\", At line: 1",
         );
 
         assert_eq!(
-            LedgerErr::from_parse(NomErr::Failure(("ERROR", ErrorKind::Fail)), 1).to_string(),
+            LedgerErr::from_parse(NomErr::Failure(("ERROR", ErrorKind::Fail)), 1, None)
+                .to_string(),
             "Ledger Error 🦀 - Issue whilst parsing csv: \"Faliure whilst parsing input\", At line: 1",
         );
 
         assert_eq!(
-            LedgerErr::from_parse(NomErr::Error(("ERROR", ErrorKind::Fail)), 1).to_string(),
+            LedgerErr::from_parse(NomErr::Error(("ERROR", ErrorKind::Fail)), 1, None).to_string(),
             "Ledger Error 🦀 - Issue whilst parsing csv: \"Input was in the wrong format\", At line: 1",
         );
+
+        assert_eq!(
+            LedgerErr::from_parse(
+                NomErr::Error(("ERROR", ErrorKind::Fail)),
+                2,
+                Some("foo, 1, 1, 1.0".to_string())
+            )
+            .to_string(),
+            "Ledger Error 🦀 - Issue whilst parsing csv: \"Input was in the wrong format\", At line: 2, Content: \"foo, 1, 1, 1.0\"",
+        );
+    }
+
+    #[test]
+    fn from_io() {
+        fn read() -> Result<(), LedgerErr> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "File not found",
+            ))?;
+            Ok(())
+        }
+
+        assert!(matches!(read(), Err(LedgerErr::Reading(_))));
     }
 
     #[test]
@@ -125,8 +524,379 @@ mod ledger_err {
         );
 
         assert_eq!(
-            format!("{}", super::LedgerErr::Parse("ERROR".into(), 1)),
+            format!("{}", super::LedgerErr::EmptyFile),
+            "Ledger Error 🦀 - Issue whilst reading in the csv: The file is empty or missing its header",
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::Parse {
+                    kind: super::ParseKind::Format,
+                    message: "ERROR".into(),
+                    line: 1,
+                    raw: None
+                }
+            ),
             "Ledger Error 🦀 - Issue whilst parsing csv: \"ERROR\", At line: 1"
         );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::RowLimitExceeded(10)),
+            "Ledger Error 🦀 - Issue whilst consuming csv: Exceeded the maximum of 10 rows"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::TransactionLimitExceeded(2)),
+            "Ledger Error 🦀 - Issue whilst consuming csv: Exceeded the maximum of 2 transactions"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::ClientNotFound(7)),
+            "Ledger Error 🦀 - Issue whilst filtering output: Client 7 was not found"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::InvalidOperation("ERROR".into(), 3)),
+            "Ledger Error 🦀 - Issue whilst applying transaction: \"ERROR\", At line: 3"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::InvalidTransaction("ERROR".into())),
+            "Ledger Error 🦀 - Issue whilst building transaction: ERROR"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::ParseVerbose("ERROR".into(), 2)),
+            "Ledger Error 🦀 - Issue whilst parsing csv: At line: 2\nERROR"
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::Duplicate {
+                    tx_id: 42,
+                    first_line: Some(3),
+                    second_line: Some(7)
+                }
+            ),
+            "Ledger Error 🦀 - Issue whilst recording transaction: Duplicate transaction ID 42 first seen at line 3, repeated at line 7"
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::Duplicate {
+                    tx_id: 5,
+                    first_line: Some(3),
+                    second_line: None
+                }
+            ),
+            "Ledger Error 🦀 - Issue whilst recording transaction: Duplicate transaction ID 5"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::NotFound(5)),
+            "Ledger Error 🦀 - Issue whilst rolling back transaction: Transaction 5 was not found"
+        );
+
+        assert_eq!(
+            format!("{}", super::LedgerErr::AlreadyDisputed(5)),
+            "Ledger Error 🦀 - Issue whilst rolling back transaction: Transaction 5 is disputed"
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::CurrencyMismatch {
+                    client: 1,
+                    expected: "EUR".into(),
+                    found: "USD".into()
+                }
+            ),
+            "Ledger Error 🦀 - Issue whilst applying transaction: Client 1 holds EUR, but the transaction is in USD"
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::WrongClient {
+                    tx_id: 5,
+                    expected_client: 1,
+                    actual_client: 2
+                }
+            ),
+            "Ledger Error 🦀 - Issue whilst applying transaction: Transaction 5 belongs to client 1, not client 2"
+        );
+
+        assert_eq!(
+            format!(
+                "{}",
+                super::LedgerErr::WrongFieldCount {
+                    expected: 4,
+                    found: 5,
+                    line: 2
+                }
+            ),
+            "Ledger Error 🦀 - Issue whilst parsing csv: \"Unexpected extra fields, expected 4 but found 5\", At line: 2"
+        );
+    }
+
+    #[test]
+    fn code() {
+        let io_err = || std::io::Error::new(std::io::ErrorKind::NotFound, "File not found");
+
+        assert_eq!(LedgerErr::Opening(io_err()).code(), "open");
+        assert_eq!(LedgerErr::Reading(io_err()).code(), "read");
+        assert_eq!(LedgerErr::Saving(io_err()).code(), "save");
+        assert_eq!(LedgerErr::EmptyFile.code(), "empty");
+        assert_eq!(
+            LedgerErr::from_parse(NomErr::Incomplete::<Needed>(Needed::Unknown), 1, None).code(),
+            "parse.incomplete"
+        );
+        assert_eq!(
+            LedgerErr::from_parse(NomErr::Error(("ERROR", ErrorKind::Fail)), 1, None).code(),
+            "parse.format"
+        );
+        assert_eq!(
+            LedgerErr::from_parse(NomErr::Failure(("ERROR", ErrorKind::Fail)), 1, None).code(),
+            "parse.failure"
+        );
+        assert_eq!(LedgerErr::RowLimitExceeded(10).code(), "row_limit");
+        assert_eq!(
+            LedgerErr::TransactionLimitExceeded(2).code(),
+            "transaction_limit"
+        );
+        assert_eq!(LedgerErr::ClientNotFound(7).code(), "client_not_found");
+        assert_eq!(
+            LedgerErr::InvalidOperation("ERROR".into(), 3).code(),
+            "invalid_operation"
+        );
+        assert_eq!(
+            LedgerErr::InvalidTransaction("ERROR".into()).code(),
+            "invalid_transaction"
+        );
+        assert_eq!(
+            LedgerErr::Duplicate {
+                tx_id: 5,
+                first_line: None,
+                second_line: None
+            }
+            .code(),
+            "duplicate"
+        );
+        assert_eq!(LedgerErr::NotFound(5).code(), "not_found");
+        assert_eq!(LedgerErr::AlreadyDisputed(5).code(), "already_disputed");
+        assert_eq!(
+            LedgerErr::CurrencyMismatch {
+                client: 1,
+                expected: "EUR".into(),
+                found: "USD".into()
+            }
+            .code(),
+            "currency_mismatch"
+        );
+        assert_eq!(
+            LedgerErr::WrongClient {
+                tx_id: 5,
+                expected_client: 1,
+                actual_client: 2
+            }
+            .code(),
+            "wrong_client"
+        );
+        assert_eq!(
+            LedgerErr::WrongFieldCount {
+                expected: 4,
+                found: 5,
+                line: 2
+            }
+            .code(),
+            "wrong_field_count"
+        );
+        assert_eq!(LedgerErr::Overflow(3).code(), "overflow");
+        assert_eq!(LedgerErr::ParseVerbose("ERROR".into(), 2).code(), "parse");
+    }
+
+    #[test]
+    fn to_plain() {
+        let io_err = || std::io::Error::new(std::io::ErrorKind::NotFound, "File not found");
+
+        assert_eq!(
+            LedgerErr::Opening(io_err()).to_plain(),
+            "error: opening the csv: File not found"
+        );
+        assert_eq!(
+            LedgerErr::Reading(io_err()).to_plain(),
+            "error: reading in the csv: File not found"
+        );
+        assert_eq!(
+            LedgerErr::Saving(io_err()).to_plain(),
+            "error: saving the output file: File not found"
+        );
+        assert_eq!(
+            LedgerErr::EmptyFile.to_plain(),
+            "error: reading in the csv: The file is empty or missing its header"
+        );
+        assert_eq!(
+            LedgerErr::Parse {
+                kind: ParseKind::Format,
+                message: "ERROR".into(),
+                line: 1,
+                raw: Some("foo".into())
+            }
+            .to_plain(),
+            "error: parsing csv: \"ERROR\", At line: 1, Content: \"foo\""
+        );
+        assert_eq!(
+            LedgerErr::InvalidOperation("ERROR".into(), 3).to_plain(),
+            "error: applying transaction: \"ERROR\", At line: 3"
+        );
+        assert_eq!(
+            LedgerErr::RowLimitExceeded(10).to_plain(),
+            "error: consuming csv: Exceeded the maximum of 10 rows"
+        );
+        assert_eq!(
+            LedgerErr::TransactionLimitExceeded(2).to_plain(),
+            "error: consuming csv: Exceeded the maximum of 2 transactions"
+        );
+        assert_eq!(
+            LedgerErr::ClientNotFound(7).to_plain(),
+            "error: filtering output: Client 7 was not found"
+        );
+        assert_eq!(
+            LedgerErr::ParseVerbose("ERROR".into(), 2).to_plain(),
+            "error: parsing csv: At line: 2\nERROR"
+        );
+        assert_eq!(
+            LedgerErr::InvalidTransaction("ERROR".into()).to_plain(),
+            "error: building transaction: ERROR"
+        );
+        assert_eq!(
+            LedgerErr::Duplicate {
+                tx_id: 5,
+                first_line: None,
+                second_line: None
+            }
+            .to_plain(),
+            "error: recording transaction: Duplicate transaction ID 5"
+        );
+        assert_eq!(
+            LedgerErr::NotFound(5).to_plain(),
+            "error: rolling back transaction: Transaction 5 was not found"
+        );
+        assert_eq!(
+            LedgerErr::AlreadyDisputed(5).to_plain(),
+            "error: rolling back transaction: Transaction 5 is disputed"
+        );
+        assert_eq!(
+            LedgerErr::CurrencyMismatch {
+                client: 1,
+                expected: "EUR".into(),
+                found: "USD".into()
+            }
+            .to_plain(),
+            "error: applying transaction: Client 1 holds EUR, but the transaction is in USD"
+        );
+    }
+
+    #[test]
+    fn to_json() {
+        let io_err = || std::io::Error::new(std::io::ErrorKind::NotFound, "File not found");
+
+        assert_eq!(
+            LedgerErr::Opening(io_err()).to_json(),
+            r#"{"error_type": "Opening", "message": "File not found"}"#
+        );
+        assert_eq!(
+            LedgerErr::Reading(io_err()).to_json(),
+            r#"{"error_type": "Reading", "message": "File not found"}"#
+        );
+        assert_eq!(
+            LedgerErr::Saving(io_err()).to_json(),
+            r#"{"error_type": "Saving", "message": "File not found"}"#
+        );
+        assert_eq!(
+            LedgerErr::EmptyFile.to_json(),
+            r#"{"error_type": "EmptyFile", "message": "The file is empty or missing its header"}"#
+        );
+        assert_eq!(
+            LedgerErr::Parse {
+                kind: ParseKind::Format,
+                message: "ERROR".into(),
+                line: 5,
+                raw: Some("foo".into())
+            }
+            .to_json(),
+            r#"{"error_type": "Parse", "message": "ERROR", "line": 5}"#
+        );
+        assert_eq!(
+            LedgerErr::RowLimitExceeded(10).to_json(),
+            r#"{"error_type": "RowLimitExceeded", "message": "Exceeded the maximum of 10 rows"}"#
+        );
+        assert_eq!(
+            LedgerErr::TransactionLimitExceeded(2).to_json(),
+            r#"{"error_type": "TransactionLimitExceeded", "message": "Exceeded the maximum of 2 transactions"}"#
+        );
+        assert_eq!(
+            LedgerErr::ClientNotFound(7).to_json(),
+            r#"{"error_type": "ClientNotFound", "message": "Client 7 was not found"}"#
+        );
+        assert_eq!(
+            LedgerErr::InvalidOperation("ERROR".into(), 3).to_json(),
+            r#"{"error_type": "InvalidOperation", "message": "ERROR", "line": 3}"#
+        );
+        assert_eq!(
+            LedgerErr::InvalidTransaction("ERROR".into()).to_json(),
+            r#"{"error_type": "InvalidTransaction", "message": "ERROR"}"#
+        );
+        assert_eq!(
+            LedgerErr::Duplicate {
+                tx_id: 5,
+                first_line: None,
+                second_line: None
+            }
+            .to_json(),
+            r#"{"error_type": "Duplicate", "message": "Duplicate transaction ID 5"}"#
+        );
+        assert_eq!(
+            LedgerErr::NotFound(5).to_json(),
+            r#"{"error_type": "NotFound", "message": "Transaction 5 was not found"}"#
+        );
+        assert_eq!(
+            LedgerErr::AlreadyDisputed(5).to_json(),
+            r#"{"error_type": "AlreadyDisputed", "message": "Transaction 5 is disputed"}"#
+        );
+        assert_eq!(
+            LedgerErr::CurrencyMismatch {
+                client: 1,
+                expected: "EUR".into(),
+                found: "USD".into()
+            }
+            .to_json(),
+            r#"{"error_type": "CurrencyMismatch", "message": "Client 1 holds EUR, but the transaction is in USD"}"#
+        );
+        assert_eq!(
+            LedgerErr::WrongClient {
+                tx_id: 5,
+                expected_client: 1,
+                actual_client: 2
+            }
+            .to_json(),
+            r#"{"error_type": "WrongClient", "message": "Transaction 5 belongs to client 1, not client 2"}"#
+        );
+        assert_eq!(
+            LedgerErr::WrongFieldCount {
+                expected: 4,
+                found: 5,
+                line: 2
+            }
+            .to_json(),
+            r#"{"error_type": "WrongFieldCount", "message": "Unexpected extra fields, expected 4 but found 5", "line": 2}"#
+        );
+        assert_eq!(
+            LedgerErr::ParseVerbose("0: at line 1:\n\"foo\"\t\\\u{1}".into(), 2).to_json(),
+            r#"{"error_type": "ParseVerbose", "message": "0: at line 1:\n\"foo\"\t\\\u0001", "line": 2}"#
+        );
     }
 }
@@ -30,44 +30,502 @@
 
 extern crate nom;
 
+use crate::{amount::Amount, LedgerErr};
+use core::cmp::Ordering;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while, take_while_m_n},
+    bytes::complete::{tag, take_till, take_while, take_while1, take_while_m_n},
     character::{
-        complete::{multispace0, u16, u32},
+        complete::{char, line_ending, u16, u32},
         is_digit,
     },
-    error::{Error as SubErr, ErrorKind, ParseError},
-    sequence::{delimited, terminated},
+    combinator::{all_consuming, map, opt},
+    error::{ErrorKind, ParseError},
+    sequence::tuple,
     Err as NomErr, IResult,
 };
 
 /// An enum that represents possible transaction types.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Transaction {
-    Deposit(u16, u32, i64),
-    Withdrawal(u16, u32, i64),
+    Deposit(u16, u32, Amount),
+    Withdrawal(u16, u32, Amount),
     Dispute(u16, u32),
     Resolve(u16, u32),
+    /// A resolve that releases only part of the held amount.
+    PartialResolve(u16, u32, Amount),
     Chargeback(u16, u32),
 }
 
-/// A helper function to construct nom errors from custom strings.
-pub fn nom_err(input: &str) -> NomErr<SubErr<&str>> {
-    NomErr::Failure(SubErr {
-        input,
-        code: ErrorKind::Fail,
-    })
+impl Transaction {
+    /// The id of the client that the transaction is for.
+    pub fn client(&self) -> u16 {
+        match *self {
+            Transaction::Deposit(client, ..)
+            | Transaction::Withdrawal(client, ..)
+            | Transaction::Dispute(client, _)
+            | Transaction::Resolve(client, _)
+            | Transaction::PartialResolve(client, ..)
+            | Transaction::Chargeback(client, _) => client,
+        }
+    }
+
+    /// The id of the transaction, or of the transaction being disputed.
+    pub fn tx(&self) -> u32 {
+        match *self {
+            Transaction::Deposit(_, tx, _)
+            | Transaction::Withdrawal(_, tx, _)
+            | Transaction::Dispute(_, tx)
+            | Transaction::Resolve(_, tx)
+            | Transaction::PartialResolve(_, tx, _)
+            | Transaction::Chargeback(_, tx) => tx,
+        }
+    }
+
+    /// The key that transactions are ordered by: the transaction id, client id, the position of
+    /// the variant in the lifecycle of a transaction, and finally the amount.
+    fn sort_key(&self) -> (u32, u16, u8, Option<Amount>) {
+        let (variant, amount) = match *self {
+            Transaction::Deposit(.., amount) => (0, Some(amount)),
+            Transaction::Withdrawal(.., amount) => (1, Some(amount)),
+            Transaction::Dispute(..) => (2, None),
+            Transaction::Resolve(..) => (3, None),
+            Transaction::PartialResolve(.., amount) => (4, Some(amount)),
+            Transaction::Chargeback(..) => (5, None),
+        };
+
+        (self.tx(), self.client(), variant, amount)
+    }
+}
+
+/// Transactions are ordered by transaction id, then client id, then variant, with deposits
+/// before withdrawals and disputes before their resolves and chargebacks.
+impl Ord for Transaction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for Transaction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The zero-based position of each column within a line of the CSV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnLayout {
+    pub type_col: usize,
+    pub client_col: usize,
+    pub tx_col: usize,
+    pub amount_col: usize,
+    /// The position of the optional currency column.
+    pub currency_col: Option<usize>,
+    /// The position of the timestamp column, present when `ParseOptions::timestamp` is set.
+    pub timestamp_col: Option<usize>,
+}
+
+/// The canonical `type, client, tx, amount` column order.
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        ColumnLayout {
+            type_col: 0,
+            client_col: 1,
+            tx_col: 2,
+            amount_col: 3,
+            currency_col: None,
+            timestamp_col: None,
+        }
+    }
+}
+
+impl ColumnLayout {
+    /// The canonical column order, followed by a `timestamp` column when `ParseOptions::timestamp`
+    /// is set. Used for files without a header.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::parse::{ColumnLayout, ParseOptions};
+    ///
+    /// let opts = ParseOptions::default().with_timestamp(true);
+    /// assert_eq!(ColumnLayout::from_options(&opts).timestamp_col, Some(4));
+    /// assert_eq!(ColumnLayout::from_options(&ParseOptions::default()), ColumnLayout::default());
+    /// ```
+    pub fn from_options(opts: &ParseOptions) -> Self {
+        ColumnLayout {
+            timestamp_col: opts.timestamp.then_some(4),
+            ..ColumnLayout::default()
+        }
+    }
+
+    /// The number of fields in a line with this layout, up to and including the last known column.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::parse::parse_header;
+    ///
+    /// assert_eq!(parse_header("type, client, tx, amount").unwrap().column_count(), 4);
+    /// assert_eq!(parse_header("currency, type, client, tx, amount").unwrap().column_count(), 5);
+    /// ```
+    pub fn column_count(&self) -> usize {
+        [self.type_col, self.client_col, self.tx_col, self.amount_col]
+            .into_iter()
+            .chain(self.currency_col)
+            .chain(self.timestamp_col)
+            .max()
+            .unwrap_or_default()
+            + 1
+    }
+}
+
+/// The columns described by a CSV header, as returned by `parse_header`.
+pub type HeaderSchema = ColumnLayout;
+
+/// The kind of transaction constructed by a `TransactionBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransactionKind {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+/// A builder for constructing a validated `Transaction` one field at a time.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::{amount::Amount, parse::{Transaction, TransactionBuilder}};
+///
+/// let transaction = TransactionBuilder::deposit(1)
+///     .with_tx(2)
+///     .with_amount(15000)
+///     .build();
+/// assert_eq!(transaction.unwrap(), Transaction::Deposit(1, 2, Amount(15000)));
+///
+/// // Deposits must have a positive amount
+/// assert!(TransactionBuilder::deposit(1).with_tx(2).build().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionBuilder {
+    kind: TransactionKind,
+    client: u16,
+    tx: Option<u32>,
+    amount: Option<i64>,
+}
+
+impl TransactionBuilder {
+    fn new(kind: TransactionKind, client: u16) -> Self {
+        TransactionBuilder {
+            kind,
+            client,
+            tx: None,
+            amount: None,
+        }
+    }
+
+    /// Start building a deposit for the given client.
+    pub fn deposit(client: u16) -> Self {
+        TransactionBuilder::new(TransactionKind::Deposit, client)
+    }
+
+    /// Start building a withdrawal for the given client.
+    pub fn withdrawal(client: u16) -> Self {
+        TransactionBuilder::new(TransactionKind::Withdrawal, client)
+    }
+
+    /// Start building a dispute for the given client.
+    pub fn dispute(client: u16) -> Self {
+        TransactionBuilder::new(TransactionKind::Dispute, client)
+    }
+
+    /// Start building a resolve for the given client.
+    /// A resolve with an amount is built as a `Transaction::PartialResolve`.
+    pub fn resolve(client: u16) -> Self {
+        TransactionBuilder::new(TransactionKind::Resolve, client)
+    }
+
+    /// Start building a chargeback for the given client.
+    pub fn chargeback(client: u16) -> Self {
+        TransactionBuilder::new(TransactionKind::Chargeback, client)
+    }
+
+    /// Set the id of the transaction.
+    pub fn with_tx(mut self, tx: u32) -> Self {
+        self.tx = Some(tx);
+        self
+    }
+
+    /// Set the amount of the transaction, in ten-thousandths.
+    pub fn with_amount(mut self, amount: i64) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    /// Validate the fields and construct the transaction.
+    /// Returns `LedgerErr::InvalidTransaction` if the transaction id is missing, a deposit,
+    /// withdrawal or resolve has an amount that is not positive, or a dispute or chargeback
+    /// has an amount.
+    pub fn build(self) -> Result<Transaction, LedgerErr> {
+        let invalid = |msg: &str| Err(LedgerErr::InvalidTransaction(msg.to_string()));
+
+        let Some(tx) = self.tx else {
+            return invalid("Transaction is missing an id");
+        };
+        let client = self.client;
+
+        match (self.kind, self.amount) {
+            (_, Some(amount)) if amount <= 0 => invalid("Amount must be greater than zero"),
+            (TransactionKind::Deposit, Some(amount)) => {
+                Ok(Transaction::Deposit(client, tx, Amount(amount)))
+            }
+            (TransactionKind::Withdrawal, Some(amount)) => {
+                Ok(Transaction::Withdrawal(client, tx, Amount(amount)))
+            }
+            (TransactionKind::Deposit | TransactionKind::Withdrawal, None) => {
+                invalid("Deposit or Withdrawal is missing an amount")
+            }
+            (TransactionKind::Resolve, Some(amount)) => {
+                Ok(Transaction::PartialResolve(client, tx, Amount(amount)))
+            }
+            (TransactionKind::Resolve, None) => Ok(Transaction::Resolve(client, tx)),
+            (TransactionKind::Dispute, None) => Ok(Transaction::Dispute(client, tx)),
+            (TransactionKind::Chargeback, None) => Ok(Transaction::Chargeback(client, tx)),
+            (TransactionKind::Dispute | TransactionKind::Chargeback, Some(_)) => {
+                invalid("Dispute or Chargeback with an amount")
+            }
+        }
+    }
+}
+
+/// How whitespace (spaces, tabs and line endings) around each field is treated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Trim {
+    /// Ignore whitespace before and after each field.
+    #[default]
+    Both,
+    /// Ignore whitespace after each field only.
+    Trailing,
+    /// Treat all whitespace as part of the field.
+    None,
 }
 
-/// A parser that ignores whitespace around the input parser.
-fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(
-    inner: F,
-) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
+impl Trim {
+    /// Remove the whitespace around a field according to the policy.
+    ///
+    /// Example:
+    /// ```rust
+    /// use csv_ledger_lib::parse::Trim;
+    ///
+    /// assert_eq!(Trim::Both.apply(" 1.0 "), "1.0");
+    /// assert_eq!(Trim::Trailing.apply(" 1.0 "), " 1.0");
+    /// assert_eq!(Trim::None.apply(" 1.0 "), " 1.0 ");
+    /// ```
+    pub fn apply(self, field: &str) -> &str {
+        let whitespace = |chr: char| matches!(chr, ' ' | '\t' | '\r' | '\n');
+        match self {
+            Trim::Both => field.trim_matches(whitespace),
+            Trim::Trailing => field.trim_end_matches(whitespace),
+            Trim::None => field,
+        }
+    }
+}
+
+/// Options used to configure the behaviour of the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Tolerate and ignore any columns other than `type`, `client`, `tx` and `amount`.
+    pub allow_extra_columns: bool,
+    /// Allow fields to be wrapped in double quotes, which may contain commas and newlines.
+    pub quoting: bool,
+    /// The character separating the fields of a line, such as `;` for European style csv files.
+    pub delimiter: char,
+    /// The character separating the whole and fractional parts of an amount.
+    /// When set to anything other than `.`, a `.` may be used to group thousands (eg `1.000,50`).
+    pub decimal_separator: char,
+    /// The maximum number of decimal places accepted in an amount, up to four.
+    pub max_decimal_places: usize,
+    /// How whitespace around each field, including the header, is treated.
+    pub trim: Trim,
+    /// Reject client ids, transaction ids and whole amounts written with a leading zero,
+    /// such as `007`. A lone `0` is always accepted.
+    pub reject_leading_zeros: bool,
+    /// Read an ISO-8601 `timestamp` column, such as `2023-01-01T00:00:00Z`. Files without a header
+    /// have it as a fifth column. Without this option, a `timestamp` column is an unknown column.
+    pub timestamp: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            allow_extra_columns: false,
+            quoting: false,
+            delimiter: ',',
+            decimal_separator: '.',
+            max_decimal_places: 4,
+            trim: Trim::Both,
+            reject_leading_zeros: false,
+            timestamp: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Set whether unknown columns should be ignored.
+    pub fn with_allow_extra_columns(mut self, allow_extra_columns: bool) -> Self {
+        self.allow_extra_columns = allow_extra_columns;
+        self
+    }
+
+    /// Set whether fields may be wrapped in double quotes.
+    pub fn with_quoting(mut self, quoting: bool) -> Self {
+        self.quoting = quoting;
+        self
+    }
+
+    /// Set the character separating the fields of a line.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the character separating the whole and fractional parts of an amount.
+    pub fn with_decimal_separator(mut self, decimal_separator: char) -> Self {
+        self.decimal_separator = decimal_separator;
+        self
+    }
+
+    /// Set the maximum number of decimal places accepted in an amount, up to four.
+    pub fn with_max_decimal_places(mut self, max_decimal_places: usize) -> Self {
+        self.max_decimal_places = max_decimal_places;
+        self
+    }
+
+    /// Set how whitespace around each field is treated.
+    pub fn with_trim(mut self, trim: Trim) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Set whether numbers written with a leading zero should be rejected.
+    pub fn with_reject_leading_zeros(mut self, reject_leading_zeros: bool) -> Self {
+        self.reject_leading_zeros = reject_leading_zeros;
+        self
+    }
+
+    /// Set whether to read a timestamp column.
+    pub fn with_timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+}
+
+/// The error type returned by the transaction and header parsers.
+/// Enabling the `verbose-errors` feature switches this to a `VerboseError`, which records the
+/// stack of parsers that failed.
+#[cfg(not(feature = "verbose-errors"))]
+pub type ParseErr<'a> = nom::error::Error<&'a str>;
+
+/// The error type returned by the transaction and header parsers.
+/// Enabling the `verbose-errors` feature switches this to a `VerboseError`, which records the
+/// stack of parsers that failed.
+#[cfg(feature = "verbose-errors")]
+pub type ParseErr<'a> = nom::error::VerboseError<&'a str>;
+
+/// A helper function to construct nom errors from custom strings, with the given kind.
+pub fn nom_err<'a, E>(input: &'a str, kind: ErrorKind) -> NomErr<E>
+where
+    E: ParseError<&'a str>,
+{
+    NomErr::Failure(E::from_error_kind(input, kind))
+}
+
+/// A helper function to construct nom errors from custom strings, with `ErrorKind::Fail`.
+pub fn nom_err_fail<'a, E>(input: &'a str) -> NomErr<E>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+    E: ParseError<&'a str>,
 {
-    delimited(multispace0, inner, multispace0)
+    nom_err(input, ErrorKind::Fail)
+}
+
+/// Split a line of the CSV into its fields.
+/// When quoting is enabled, commas within double quotes do not split a field and the
+/// surrounding quotes are removed. Escaped quotes (`""`) are left as is.
+#[inline]
+pub fn split_fields(input: &str, quoting: bool) -> Vec<&str> {
+    split_fields_with_delimiter(input, ',', quoting)
+}
+
+/// Split a line of the CSV into its fields, separated by the given delimiter.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::split_fields_with_delimiter;
+///
+/// assert_eq!(split_fields_with_delimiter("deposit;1;1;1,5", ';', false), vec!["deposit", "1", "1", "1,5"]);
+/// assert_eq!(split_fields_with_delimiter("\"a;b\";c", ';', true), vec!["a;b", "c"]);
+/// ```
+pub fn split_fields_with_delimiter(input: &str, delimiter: char, quoting: bool) -> Vec<&str> {
+    fields(input, delimiter, quoting).collect()
+}
+
+/// Iterate over the fields of a line of the CSV, separated by the given delimiter, without
+/// allocating. Fields are split and unquoted as in `split_fields_with_delimiter`.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::fields;
+///
+/// assert_eq!(fields("deposit, 1, 1, 1.0", ',', false).count(), 4);
+/// assert_eq!(fields("\"a;b\";c", ';', true).nth(1), Some("c"));
+/// ```
+pub fn fields(input: &str, delimiter: char, quoting: bool) -> Fields<'_> {
+    Fields {
+        input: Some(input),
+        delimiter,
+        quoting,
+    }
+}
+
+/// An iterator over the fields of a line of the CSV, created by `fields`.
+#[derive(Debug, Clone)]
+pub struct Fields<'a> {
+    /// The unread remainder of the line, or `None` once the final field has been returned.
+    input: Option<&'a str>,
+    delimiter: char,
+    quoting: bool,
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let input = self.input?;
+        let mut quoted = false;
+        let (field, rest) = input
+            .char_indices()
+            .find(|&(_, chr)| {
+                if self.quoting && chr == '"' {
+                    quoted = !quoted;
+                }
+                chr == self.delimiter && !quoted
+            })
+            .map_or((input, None), |(index, chr)| {
+                (&input[..index], Some(&input[index + chr.len_utf8()..]))
+            });
+        self.input = rest;
+
+        Some(if self.quoting { unquote(field) } else { field })
+    }
+}
+
+/// Remove the double quotes surrounding a field, ignoring any whitespace outside of them.
+fn unquote(field: &str) -> &str {
+    let trimmed = field.trim();
+    match trimmed.strip_prefix('"').and_then(|f| f.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => field,
+    }
 }
 
 /// Test if a character is a digit.
@@ -75,38 +533,215 @@ pub fn digit(chr: char) -> bool {
     chr.is_ascii() && is_digit(chr as u8)
 }
 
-/// Parse a i64 number from a string, optionally allowing a maximum number of digits to be specified.
-pub fn double(input: &str, max: Option<usize>) -> IResult<&str, i64> {
+/// Parse a i64 number from a string, requiring at least `min` digits and optionally
+/// allowing a maximum number of digits to be specified.
+/// An empty number is parsed as zero when `min` is zero.
+///
+/// Migrating from the previous `double(input, max)` signature: use `double(input, 1, max)`,
+/// which requires at least one digit as before.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::double;
+///
+/// assert_eq!(double("0042", 4, Some(4)), Ok(("", 42)));
+/// assert!(double("42", 4, Some(4)).is_err());
+/// ```
+pub fn double(input: &str, min: usize, max: Option<usize>) -> IResult<&str, i64> {
     let (input, num) = match max {
-        Some(m) => take_while_m_n(1, m, digit)(input),
+        Some(m) => take_while_m_n(0, m, digit)(input),
         None => take_while(digit)(input),
     }?;
 
+    if num.len() < min {
+        return Err(nom_err("Number has too few digits.", ErrorKind::Digit));
+    } else if num.is_empty() {
+        return Ok((input, 0));
+    }
+
     // Convert the string to i64
     Ok((
         input,
         num.parse::<i64>()
-            .map_err(|_| nom_err("Could not parse number as i64."))?,
+            .map_err(|_| nom_err("Could not parse number as i64.", ErrorKind::TooLarge))?,
     ))
 }
 
 #[inline]
 /// Parse an up to four decimal place number as an i64 by multiplying by 10000.
 pub fn four_dp(input: &str) -> IResult<&str, i64> {
-    let (input, pre_dp) = double(input, None)?;
+    fixed_dp(input, 4)
+}
+
+/// Parse an up to `precision` decimal place number as an i64 by multiplying by `10^precision`.
+/// The precision is capped at 18, the most that an i64 can represent.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::fixed_dp;
+///
+/// assert_eq!(fixed_dp("1.5", 2), Ok(("", 150)));
+/// assert_eq!(fixed_dp("0.12345678", 8), Ok(("", 12345678)));
+/// assert_eq!(fixed_dp("1.123", 2), Ok(("3", 112)));
+/// ```
+pub fn fixed_dp(input: &str, precision: u32) -> IResult<&str, i64> {
+    let precision = precision.min(18);
+    fixed_point(input, '.', precision as usize, precision)
+}
+
+/// Parse an up to four decimal place number using the given decimal separator.
+/// When the separator is not `.`, the whole part may be grouped into thousands using `.`.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::four_dp_with_separator;
+///
+/// assert_eq!(four_dp_with_separator("1.000,5", ','), Ok(("", 10005000)));
+/// assert_eq!(four_dp_with_separator("1000,5", ','), Ok(("", 10005000)));
+/// ```
+pub fn four_dp_with_separator(input: &str, separator: char) -> IResult<&str, i64> {
+    fixed_point(input, separator, 4, 4)
+}
+
+/// The error message for an amount containing more than one decimal separator.
+const MULTIPLE_DECIMAL_POINTS: &str = "Multiple decimal points in amount.";
+
+/// The error message for a deposit or withdrawal with an empty or absent amount.
+const AMOUNT_REQUIRED: &str = "Amount field required for deposit/withdrawal";
+
+/// The longest currency accepted, in bytes, so that every ledger can be saved and loaded.
+pub const MAX_CURRENCY_LEN: usize = 16;
+
+/// Check that a number does not start with a leading zero, when leading zeros are rejected.
+/// Only the whole part of the number, after an optional `+`, is checked.
+fn leading_zero<'a, E>(field: &'a str, opts: &ParseOptions) -> Result<&'a str, NomErr<E>>
+where
+    E: ParseError<&'a str>,
+{
+    let number = field.strip_prefix('+').unwrap_or(field);
+    let digits = number.bytes().take_while(u8::is_ascii_digit).count();
+
+    match opts.reject_leading_zeros && digits > 1 && number.starts_with('0') {
+        true => Err(nom_err("Number has a leading zero.", ErrorKind::Digit)),
+        false => Ok(field),
+    }
+}
+
+/// Parse a number with up to `places` decimal places (at most `scale`) as an i64 multiplied
+/// by `10^scale`. A single leading `+` is accepted and ignored.
+pub(crate) fn fixed_point(
+    input: &str,
+    separator: char,
+    places: usize,
+    scale: u32,
+) -> IResult<&str, i64> {
+    let too_large = || nom_err("Could not parse number as i64.", ErrorKind::TooLarge);
+    let input = input.strip_prefix('+').unwrap_or(input);
+    let (mut input, mut pre_dp) = double(input, 1, None)?;
+
+    // Optionally parse groups of thousands
+    if separator != '.' {
+        while let Ok((rest, _)) = tag::<_, _, (&str, ErrorKind)>(".")(input) {
+            let (rest, group) = take_while_m_n(3, 3, digit)(rest)?;
+            pre_dp = pre_dp
+                .checked_mul(1000)
+                .and_then(|value| value.checked_add(group.parse().ok()?))
+                .ok_or_else(too_large)?;
+            input = rest;
+        }
+    }
+
+    let whole = pre_dp
+        .checked_mul(10_i64.pow(scale))
+        .ok_or_else(too_large)?;
 
     // Optionally parse decimal places
-    if let Ok((input, _)) = tag::<_, _, (&str, ErrorKind)>(".")(input) {
-        let (input, post_dp) = double(input, Some(4))?;
+    if let Ok((decimals, _)) = char::<_, (&str, ErrorKind)>(separator)(input) {
+        let (input, post_dp) = double(decimals, 1, Some(places.min(scale as usize)))?;
+        if char::<_, (&str, ErrorKind)>(separator)(input).is_ok() {
+            return Err(nom_err(MULTIPLE_DECIMAL_POINTS, ErrorKind::Char));
+        }
 
-        // Convert decimal places to whole numbers
-        return Ok((
-            input,
-            (pre_dp * 10000 + post_dp * 10_i64.pow(3 - (post_dp as f32).log10() as u32)),
-        ));
+        // Convert decimal places to whole numbers, scaling by the number of digits read rather
+        // than the value, so that leading zeros are kept (`.05` is 500, not 5000)
+        let digits = (decimals.len() - input.len()) as u32;
+        let fraction = post_dp * 10_i64.pow(scale - digits);
+        return Ok((input, whole.checked_add(fraction).ok_or_else(too_large)?));
+    }
+
+    Ok((input, whole))
+}
+
+/// Parse an ISO-8601 timestamp, such as `2023-01-01T00:00:00Z`, as seconds since the Unix epoch.
+/// The time must be followed by `Z` or a `+HH:MM`/`-HH:MM` offset from UTC. Fractional seconds
+/// are accepted and truncated.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::timestamp;
+///
+/// assert_eq!(timestamp("2023-01-01T00:00:00Z"), Ok(("", 1672531200)));
+/// assert_eq!(timestamp("2023-01-01T01:30:00.25+01:30"), Ok(("", 1672531200)));
+/// assert!(timestamp("2023-02-29T00:00:00Z").is_err());
+/// ```
+pub fn timestamp(input: &str) -> IResult<&str, i64> {
+    let out_of_range = || nom_err("Timestamp is out of range.", ErrorKind::Verify);
+    let number = |len: usize| move |i| double(i, len, Some(len));
+
+    let (input, (year, _, month, _, day)) =
+        tuple((number(4), char('-'), number(2), char('-'), number(2)))(input)?;
+    let (input, (_, hour, _, minute, _, second)) = tuple((
+        char('T'),
+        number(2),
+        char(':'),
+        number(2),
+        char(':'),
+        number(2),
+    ))(input)?;
+    let (input, _) = opt(tuple((char('.'), take_while1(digit))))(input)?;
+    let (input, offset) = alt((
+        map(char('Z'), |_| (1, 0, 0)),
+        map(
+            tuple((alt((char('+'), char('-'))), number(2), char(':'), number(2))),
+            |(sign, hours, _, minutes)| (if sign == '-' { -1 } else { 1 }, hours, minutes),
+        ),
+    ))(input)?;
+
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    let (sign, offset_hours, offset_minutes) = offset;
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+        || offset_hours > 23
+        || offset_minutes > 59
+    {
+        return Err(out_of_range());
     }
 
-    Ok((input, (pre_dp * 10000)))
+    // Count the days since the epoch, using a calendar with years starting in March
+    let (year, month) = if month > 2 {
+        (year, month - 3)
+    } else {
+        (year - 1, month + 9)
+    };
+    let year_of_era = year.rem_euclid(400);
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + (153 * month + 2) / 5 + day - 1;
+    let days = year.div_euclid(400) * 146_097 + day_of_era - 719_468;
+
+    Ok((
+        input,
+        days * 86_400 + hour * 3600 + minute * 60 + second
+            - sign * (offset_hours * 3600 + offset_minutes * 60),
+    ))
 }
 
 /// Parse a line of the CSV as a Transaction.
@@ -114,16 +749,17 @@ pub fn four_dp(input: &str) -> IResult<&str, i64> {
 ///
 /// Example:
 /// ```rust
-/// use csv_ledger_lib::parse::{Transaction, parse_transaction};
+/// use csv_ledger_lib::{amount::Amount, parse::{Transaction, parse_transaction}};
 ///
 /// fn main() {
 ///     // Valid Inputs:
-///     assert_eq!(parse_transaction("deposit, 1, 1, 20.0"), Ok(Transaction::Deposit(1, 1, 200000)));
-///     assert_eq!(parse_transaction(" deposit,  2, 20  ,6.99  "), Ok(Transaction::Deposit(2, 20, 69900)));
-///     assert_eq!(parse_transaction("withdrawal, 3, 7, 22"), Ok(Transaction::Withdrawal(3, 7, 220000)));
+///     assert_eq!(parse_transaction("deposit, 1, 1, 20.0"), Ok(Transaction::Deposit(1, 1, Amount(200000))));
+///     assert_eq!(parse_transaction(" deposit,  2, 20  ,6.99  "), Ok(Transaction::Deposit(2, 20, Amount(69900))));
+///     assert_eq!(parse_transaction("withdrawal, 3, 7, 22"), Ok(Transaction::Withdrawal(3, 7, Amount(220000))));
 ///
 ///     assert_eq!(parse_transaction("dispute, 2, 2,"), Ok(Transaction::Dispute(2, 2)));
 ///     assert_eq!(parse_transaction("resolve, 2, 2,"), Ok(Transaction::Resolve(2, 2)));
+///     assert_eq!(parse_transaction("resolve, 2, 2, 3.00"), Ok(Transaction::PartialResolve(2, 2, Amount(30000))));
 ///
 ///     assert_eq!(parse_transaction("dispute, 3, 7,"), Ok(Transaction::Dispute(3, 7)));
 ///     assert_eq!(parse_transaction("chargeback, 3, 7,"), Ok(Transaction::Chargeback(3, 7)));
@@ -135,326 +771,1605 @@ pub fn four_dp(input: &str) -> IResult<&str, i64> {
 /// }
 /// ```
 #[inline]
-pub fn parse_transaction(input: &str) -> Result<Transaction, NomErr<SubErr<&str>>> {
-    // Parse the type of Transaction
-    let (input, key) = terminated(
-        ws(alt((
-            tag("deposit"),
-            tag("withdrawal"),
-            tag("dispute"),
-            tag("resolve"),
-            tag("chargeback"),
-        ))),
-        tag(","),
-    )(input)?;
-
-    // Parse the account and Transaction ID
-    let (input, client) = terminated(ws(u16), tag(","))(input)?;
-    let (input, tx) = terminated(ws(u32), tag(","))(input)?;
-
-    // Parse the Transaction amount
-    let amount = delimited(multispace0, four_dp, multispace0)(input).ok();
-
-    // Check that the line has been consumed completely
-    if let Some((input, _)) = amount {
-        if !input.is_empty() {
-            Err(nom_err("Input was not empty after parsing transaction."))?;
+pub fn parse_transaction(input: &str) -> Result<Transaction, NomErr<ParseErr<'_>>> {
+    match transaction_parser()(input)? {
+        ("", transaction) => Ok(transaction),
+        _ => Err(nom_err_fail(
+            "Input was not empty after parsing transaction.",
+        )),
+    }
+}
+
+/// A nom parser for a single line of the CSV as a Transaction, using the default layout and
+/// options. The line ending is consumed, and any following lines are returned as the remaining
+/// input, allowing the parser to be composed with other nom combinators.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::{amount::Amount, parse::{transaction_parser, Transaction}};
+/// use nom::multi::many0;
+///
+/// let (rest, transactions) = many0(transaction_parser())("deposit, 1, 1, 1.0\ndispute, 1, 1,\n").unwrap();
+///
+/// assert_eq!(rest, "");
+/// assert_eq!(
+///     transactions,
+///     vec![Transaction::Deposit(1, 1, Amount(10000)), Transaction::Dispute(1, 1)]
+/// );
+/// ```
+pub fn transaction_parser<'a>() -> impl FnMut(&'a str) -> IResult<&'a str, Transaction, ParseErr<'a>>
+{
+    |input: &'a str| {
+        // An empty input is a recoverable error, so that repeating combinators stop cleanly
+        if input.is_empty() {
+            return Err(NomErr::Error(ParseErr::from_error_kind(
+                input,
+                ErrorKind::Eof,
+            )));
+        }
+
+        let (input, line) = take_till(|chr| chr == '\n')(input)?;
+        let transaction = parse_transaction_with_options(line, &ParseOptions::default())?;
+        let (input, _) = opt(line_ending)(input)?;
+
+        Ok((input, transaction))
+    }
+}
+
+/// Parse a line of the CSV as a Transaction, using the provided options.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::amount::Amount;
+/// use csv_ledger_lib::parse::{ParseOptions, Transaction, parse_transaction_with_options};
+///
+/// fn main() {
+///     let opts = ParseOptions::default().with_allow_extra_columns(true);
+///
+///     assert_eq!(
+///         parse_transaction_with_options("deposit, 1, 1, 20.0, notes", &opts),
+///         Ok(Transaction::Deposit(1, 1, Amount(200000)))
+///     );
+/// }
+/// ```
+#[inline]
+pub fn parse_transaction_with_options<'a>(
+    input: &'a str,
+    opts: &ParseOptions,
+) -> Result<Transaction, NomErr<ParseErr<'a>>> {
+    parse_transaction_with_layout(input, &ColumnLayout::from_options(opts), opts)
+}
+
+/// Parse a line of the CSV as a Transaction, with fields separated by the given delimiter.
+/// Combine `ParseOptions::with_delimiter` and `ParseOptions::with_decimal_separator` to parse
+/// European style files, which also use `,` as the decimal separator.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::amount::Amount;
+/// use csv_ledger_lib::parse::{
+///     parse_transaction_with_delimiter, parse_transaction_with_options, ParseOptions, Transaction,
+/// };
+///
+/// fn main() {
+///     assert_eq!(
+///         parse_transaction_with_delimiter("deposit; 1; 1; 1.5", ';'),
+///         Ok(Transaction::Deposit(1, 1, Amount(15000)))
+///     );
+///
+///     let opts = ParseOptions::default()
+///         .with_delimiter(';')
+///         .with_decimal_separator(',');
+///     assert_eq!(
+///         parse_transaction_with_options("deposit;1;1;1,5", &opts),
+///         Ok(Transaction::Deposit(1, 1, Amount(15000)))
+///     );
+/// }
+/// ```
+#[inline]
+pub fn parse_transaction_with_delimiter(
+    input: &str,
+    delimiter: char,
+) -> Result<Transaction, NomErr<ParseErr<'_>>> {
+    parse_transaction_with_options(input, &ParseOptions::default().with_delimiter(delimiter))
+}
+
+/// Parse a line of the CSV as a Transaction, reading each field from the column given by the layout.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::amount::Amount;
+/// use csv_ledger_lib::parse::{
+///     parse_header_with_options, parse_transaction_with_layout, ParseOptions, Transaction,
+/// };
+///
+/// fn main() {
+///     let opts = ParseOptions::default();
+///     let layout = parse_header_with_options("client, type, amount, tx", &opts).unwrap();
+///
+///     assert_eq!(
+///         parse_transaction_with_layout("1, deposit, 20.0, 2", &layout, &opts),
+///         Ok(Transaction::Deposit(1, 2, Amount(200000)))
+///     );
+/// }
+/// ```
+pub fn parse_transaction_with_layout<'a>(
+    input: &'a str,
+    layout: &ColumnLayout,
+    opts: &ParseOptions,
+) -> Result<Transaction, NomErr<ParseErr<'a>>> {
+    let (mut key, mut client, mut tx, mut amount) = (None, None, None, None);
+
+    for (index, field) in fields(input, opts.delimiter, opts.quoting).enumerate() {
+        let field = opts.trim.apply(field);
+        if index == layout.type_col {
+            // Parse the type of Transaction
+            let (_, value) = all_consuming(alt((
+                tag("deposit"),
+                tag("withdrawal"),
+                tag("dispute"),
+                tag("resolve"),
+                tag("chargeback"),
+            )))(field)?;
+            key = Some(value);
+        } else if index == layout.client_col {
+            // Parse the account and Transaction ID
+            client = Some(all_consuming(u16)(leading_zero(field, opts)?)?.1);
+        } else if index == layout.tx_col {
+            tx = Some(all_consuming(u32)(leading_zero(field, opts)?)?.1);
+        } else if index == layout.amount_col {
+            // Parse the Transaction amount, an empty or blank field is treated as no amount
+            amount = Some(match field {
+                value if value.trim().is_empty() => None,
+                // Report a repeated decimal point as a problem with the amount itself
+                value if value.matches(opts.decimal_separator).count() > 1 => {
+                    return Err(nom_err(MULTIPLE_DECIMAL_POINTS, ErrorKind::Char))
+                }
+                value => Some(
+                    all_consuming(|i| {
+                        leading_zero(i, opts)?;
+                        fixed_point(i, opts.decimal_separator, opts.max_decimal_places, 4)
+                    })(value)
+                    .map(|(_, amount)| amount),
+                ),
+            });
+        } else if Some(index) == layout.currency_col {
+            // The currency is read separately by `parse_currency`
+            if field.len() > MAX_CURRENCY_LEN {
+                return Err(nom_err_fail("Currency is longer than 16 bytes."));
+            }
+        } else if Some(index) == layout.timestamp_col {
+            // The timestamp is read separately by `parse_timestamp`, an empty field is no timestamp
+            if !field.is_empty() {
+                all_consuming(timestamp)(field)
+                    .map_err(|_| nom_err_fail("Timestamp is not a valid ISO-8601 time."))?;
+            }
+        } else if !opts.allow_extra_columns {
+            return Err(nom_err_fail(
+                "Unexpected extra fields after the last column.",
+            ));
         }
     }
 
+    let (key, client, tx, amount) = match (key, client, tx, amount) {
+        (Some(key), Some(client), Some(tx), Some(amount)) => (key, client, tx, amount),
+        // A deposit or withdrawal without an amount column is reported the same as an empty amount
+        (Some("deposit" | "withdrawal"), Some(_), Some(_), None) => {
+            return Err(nom_err_fail(AMOUNT_REQUIRED))
+        }
+        _ => return Err(nom_err_fail("Transaction is missing a column.")),
+    };
+
     // Convert result into Transaction
     Ok(match (key, amount) {
-        ("deposit", Some((_, value))) => Transaction::Deposit(client, tx, value),
-        ("withdrawal", Some((_, value))) => Transaction::Withdrawal(client, tx, value),
+        ("deposit", Some(Ok(value))) => Transaction::Deposit(client, tx, Amount(value)),
+        ("withdrawal", Some(Ok(value))) => Transaction::Withdrawal(client, tx, Amount(value)),
         ("dispute", None) => Transaction::Dispute(client, tx),
         ("resolve", None) => Transaction::Resolve(client, tx),
+        ("resolve", Some(Ok(value))) => Transaction::PartialResolve(client, tx, Amount(value)),
         ("chargeback", None) => Transaction::Chargeback(client, tx),
-        (_, _) => Err(nom_err(if key == "deposit" || key == "withdrawal" {
-            "Deposit or Withdrawal with a missing or invalid amount."
-        } else {
-            "Dispute, Resolve or Chargeback with an amount."
+        ("deposit" | "withdrawal", None) => Err(nom_err_fail(AMOUNT_REQUIRED))?,
+        (_, _) => Err(nom_err_fail(match key {
+            "deposit" | "withdrawal" => "Deposit or Withdrawal with an invalid amount.",
+            "resolve" => "Resolve with an invalid amount.",
+            _ => "Dispute or Chargeback with an amount.",
         }))?,
     })
 }
 
+/// Read the currency of a line of the CSV, when the layout has a currency column.
+/// Returns `None` if there is no currency column, or the field is empty or longer than
+/// `MAX_CURRENCY_LEN`.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::{parse_currency, parse_header, ParseOptions};
+///
+/// let opts = ParseOptions::default();
+/// let layout = parse_header("type, client, tx, amount, currency").unwrap();
+///
+/// assert_eq!(parse_currency("deposit, 1, 1, 1.0, EUR", &layout, &opts), Some("EUR"));
+/// assert_eq!(parse_currency("dispute, 1, 1, ,", &layout, &opts), None);
+/// ```
+pub fn parse_currency<'a>(
+    input: &'a str,
+    layout: &ColumnLayout,
+    opts: &ParseOptions,
+) -> Option<&'a str> {
+    let field = opts
+        .trim
+        .apply(fields(input, opts.delimiter, opts.quoting).nth(layout.currency_col?)?);
+
+    (!field.is_empty() && field.len() <= MAX_CURRENCY_LEN).then_some(field)
+}
+
+/// Read the timestamp of a line of the CSV as seconds since the Unix epoch, when the layout has a
+/// timestamp column. Returns `None` if there is no timestamp column, or the field is empty or
+/// not a valid timestamp.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::{parse_header_with_options, parse_timestamp, ParseOptions};
+///
+/// let opts = ParseOptions::default().with_timestamp(true);
+/// let layout = parse_header_with_options("type, client, tx, amount, timestamp", &opts).unwrap();
+///
+/// assert_eq!(
+///     parse_timestamp("deposit, 1, 1, 1.0, 2023-01-01T00:00:00Z", &layout, &opts),
+///     Some(1672531200)
+/// );
+/// assert_eq!(parse_timestamp("dispute, 1, 1, ,", &layout, &opts), None);
+/// ```
+pub fn parse_timestamp(input: &str, layout: &ColumnLayout, opts: &ParseOptions) -> Option<i64> {
+    let field = opts
+        .trim
+        .apply(fields(input, opts.delimiter, opts.quoting).nth(layout.timestamp_col?)?);
+
+    all_consuming(timestamp)(field).ok().map(|(_, time)| time)
+}
+
 /// Parse the CSV header to validate that the CSV is in the correct format.
+/// Returns the layout of the columns, which may be in any order.
 /// Please note that whitespace will be ignored.
 ///
 /// Example:
 /// ```rust
-/// use csv_ledger_lib::parse::parse_header;
+/// use csv_ledger_lib::parse::{parse_header, ColumnLayout};
 ///
 /// fn main() {
-///     assert!(parse_header("type, client, tx, amount").is_ok());
+///     assert_eq!(parse_header("type, client, tx, amount"), Ok(ColumnLayout::default()));
 ///     assert!(parse_header(" type,  client, tx  ,amount  ").is_ok());
+///     assert!(parse_header("client, type, amount, tx").is_ok());
 ///
 ///     assert!(parse_header("type, client, tx").is_err());
 /// }
 /// ```
 #[inline]
-pub fn parse_header(input: &str) -> Result<(), NomErr<SubErr<&str>>> {
-    let (input, _) = terminated(ws(tag("type")), tag(","))(input)?;
-    let (input, _) = terminated(ws(tag("client")), tag(","))(input)?;
-    let (input, _) = terminated(ws(tag("tx")), tag(","))(input)?;
-    let (input, _) = ws(tag("amount"))(input)?;
+pub fn parse_header(input: &str) -> Result<HeaderSchema, NomErr<ParseErr<'_>>> {
+    parse_header_with_options(input, &ParseOptions::default())
+}
+
+/// Parse the CSV header, requiring the columns to be in the canonical `type, client, tx, amount`
+/// order. Please note that whitespace will be ignored.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::parse_header_strict;
+///
+/// fn main() {
+///     assert_eq!(parse_header_strict(" type, client ,tx, amount"), Ok(()));
+///
+///     assert!(parse_header_strict("client, type, amount, tx").is_err());
+///     assert!(parse_header_strict("type, client, tx").is_err());
+/// }
+/// ```
+#[inline]
+pub fn parse_header_strict(input: &str) -> Result<(), NomErr<ParseErr<'_>>> {
+    match parse_header(input)? {
+        schema if schema == HeaderSchema::default() => Ok(()),
+        _ => Err(nom_err_fail(
+            "Header columns are not in the order type, client, tx, amount.",
+        )),
+    }
+}
+
+/// Parse the CSV header, with columns separated by the given delimiter.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::{parse_header_with_delimiter, ColumnLayout};
+///
+/// fn main() {
+///     assert_eq!(parse_header_with_delimiter("type;client;tx;amount", ';'), Ok(ColumnLayout::default()));
+///     assert!(parse_header_with_delimiter("type, client, tx, amount", ';').is_err());
+/// }
+/// ```
+#[inline]
+pub fn parse_header_with_delimiter(
+    input: &str,
+    delimiter: char,
+) -> Result<HeaderSchema, NomErr<ParseErr<'_>>> {
+    parse_header_with_options(input, &ParseOptions::default().with_delimiter(delimiter))
+}
+
+/// Parse the CSV header using the provided options.
+/// When `allow_extra_columns` is set, any unknown columns are ignored.
+///
+/// Example:
+/// ```rust
+/// use csv_ledger_lib::parse::{ParseOptions, parse_header_with_options};
+///
+/// fn main() {
+///     let opts = ParseOptions::default().with_allow_extra_columns(true);
+///
+///     assert!(parse_header_with_options("type, client, tx, amount, notes", &opts).is_ok());
+/// }
+/// ```
+#[inline]
+pub fn parse_header_with_options<'a>(
+    input: &'a str,
+    opts: &ParseOptions,
+) -> Result<HeaderSchema, NomErr<ParseErr<'a>>> {
+    let (mut type_col, mut client_col, mut tx_col, mut amount_col) = (None, None, None, None);
+    let (mut currency_col, mut timestamp_col) = (None, None);
+
+    for (index, field) in fields(input, opts.delimiter, opts.quoting).enumerate() {
+        let column = match opts.trim.apply(field) {
+            "type" => &mut type_col,
+            "client" => &mut client_col,
+            "tx" => &mut tx_col,
+            "amount" => &mut amount_col,
+            "currency" => &mut currency_col,
+            "timestamp" if opts.timestamp => &mut timestamp_col,
+            _ if opts.allow_extra_columns => continue,
+            _ => return Err(nom_err_fail("Header contains an unknown column.")),
+        };
+
+        if column.replace(index).is_some() {
+            return Err(nom_err_fail("Header contains a duplicate column."));
+        }
+    }
+
+    match (type_col, client_col, tx_col, amount_col) {
+        (Some(type_col), Some(client_col), Some(tx_col), Some(amount_col))
+            if timestamp_col.is_some() || !opts.timestamp =>
+        {
+            Ok(HeaderSchema {
+                type_col,
+                client_col,
+                tx_col,
+                amount_col,
+                currency_col,
+                timestamp_col,
+            })
+        }
+        _ => Err(nom_err_fail("Header is missing a column.")),
+    }
+}
+
+#[cfg(test)]
+mod parse_transaction {
+    use crate::{
+        amount::Amount,
+        parse::{
+            parse_transaction, parse_transaction_with_options, ParseOptions, Transaction, Trim,
+        },
+    };
+
+    #[test]
+    fn deposit() {
+        let res = parse_transaction("deposit, 1, 2, 3.1").unwrap();
+        assert_eq!(res, Transaction::Deposit(1, 2, Amount(31000)));
+    }
+
+    #[test]
+    fn withdrawal() {
+        let res = parse_transaction("withdrawal, 1, 2, 3.0").unwrap();
+        assert_eq!(res, Transaction::Withdrawal(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn dispute() {
+        let res = parse_transaction("dispute, 1, 2,").unwrap();
+        assert_eq!(res, Transaction::Dispute(1, 2));
+    }
+
+    #[test]
+    fn resolve() {
+        let res = parse_transaction("resolve, 1, 2,").unwrap();
+        assert_eq!(res, Transaction::Resolve(1, 2));
+    }
+
+    #[test]
+    fn chargeback() {
+        let res = parse_transaction("chargeback, 1, 2,").unwrap();
+        assert_eq!(res, Transaction::Chargeback(1, 2));
+    }
+
+    #[test]
+    fn ok_no_decimal() {
+        let res = parse_transaction("deposit, 1, 2, 3").unwrap();
+        assert_eq!(res, Transaction::Deposit(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn ok_leading_plus() {
+        assert_eq!(
+            parse_transaction("deposit,1,1,+5.00"),
+            Ok(Transaction::Deposit(1, 1, Amount(50000)))
+        );
+        assert_eq!(
+            parse_transaction("withdrawal, 1, 2, +5.00"),
+            Ok(Transaction::Withdrawal(1, 2, Amount(50000)))
+        );
+    }
+
+    #[test]
+    fn err_double_sign() {
+        parse_transaction("deposit,1,1,++5.00").unwrap_err();
+        parse_transaction("deposit,1,1,+-5.00").unwrap_err();
+    }
+
+    #[test]
+    fn ok_no_white_space() {
+        let res = parse_transaction("deposit,1,2,3.0").unwrap();
+
+        assert_eq!(res, Transaction::Deposit(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn ok_with_white_space() {
+        let res = parse_transaction("       deposit   ,1  ,   2,  3.0  ").unwrap();
+        assert_eq!(res, Transaction::Deposit(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn ok_trailing_white_space() {
+        for ending in ["   ", "\t", "\n", "\r\n", " \t \r\n"] {
+            assert_eq!(
+                parse_transaction(&format!("deposit, 1, 2, 5.0{ending}")),
+                Ok(Transaction::Deposit(1, 2, Amount(50000)))
+            );
+            assert_eq!(
+                parse_transaction(&format!("withdrawal, 1, 2, 5.0{ending}")),
+                Ok(Transaction::Withdrawal(1, 2, Amount(50000)))
+            );
+            assert_eq!(
+                parse_transaction(&format!("dispute, 1, 2,{ending}")),
+                Ok(Transaction::Dispute(1, 2))
+            );
+            assert_eq!(
+                parse_transaction(&format!("resolve, 1, 2,{ending}")),
+                Ok(Transaction::Resolve(1, 2))
+            );
+            assert_eq!(
+                parse_transaction(&format!("chargeback, 1, 2,{ending}")),
+                Ok(Transaction::Chargeback(1, 2))
+            );
+        }
+    }
+
+    #[test]
+    fn ok_no_amount() {
+        let res = parse_transaction("dispute,1,2,").unwrap();
+        assert_eq!(res, Transaction::Dispute(1, 2));
+    }
+
+    #[test]
+    fn err_parser_runthrough() {
+        parse_transaction("x").unwrap_err();
+        parse_transaction("deposit,x").unwrap_err();
+        parse_transaction("deposit,1,x").unwrap_err();
+        parse_transaction("deposit,1,2,x").unwrap_err();
+        parse_transaction(&format!("deposit,1,2,2{}", f32::MAX)).unwrap_err();
+    }
+
+    #[test]
+    fn err_invalid_u16() {
+        parse_transaction("deposit,65536,2,3.0").unwrap_err();
+    }
+
+    #[test]
+    fn err_invalid_deposit() {
+        parse_transaction("deposit,1,2,").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_deposit_absent_value() {
+        assert_eq!(
+            parse_transaction("deposit, 1, 1").unwrap_err(),
+            parse_transaction("deposit, 1, 1,").unwrap_err()
+        );
+        assert_eq!(
+            parse_transaction("withdrawal, 1, 1").unwrap_err().to_string(),
+            "Parsing Failure: Error { input: \"Amount field required for deposit/withdrawal\", code: Fail }"
+        );
+        assert_eq!(
+            parse_transaction("deposit, 1, 1, x").unwrap_err().to_string(),
+            "Parsing Failure: Error { input: \"Deposit or Withdrawal with an invalid amount.\", code: Fail }"
+        );
+    }
+
+    #[test]
+    fn ok_dispute_blank_value() {
+        let opts = ParseOptions::default().with_trim(Trim::None);
+        for input in ["dispute, 1, 1,", "dispute, 1, 1, ", "dispute, 1, 1,\t"] {
+            assert_eq!(parse_transaction(input), Ok(Transaction::Dispute(1, 1)));
+        }
+        assert_eq!(
+            parse_transaction_with_options("dispute,1,1, ", &opts),
+            Ok(Transaction::Dispute(1, 1))
+        );
+    }
+
+    #[test]
+    fn err_dispute_missing_value() {
+        parse_transaction("dispute,1,").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_withdrawal_missing_value() {
+        let res = parse_transaction("withdrawal,1,2,").unwrap_err();
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Amount field required for deposit/withdrawal\", code: Fail }"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_deposit_missing_value() {
+        let res = parse_transaction("deposit,1,2,").unwrap_err();
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Amount field required for deposit/withdrawal\", code: Fail }"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_dispute_extra_value() {
+        let res = parse_transaction("dispute,1,2,3.0").unwrap_err();
+
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Dispute or Chargeback with an amount.\", code: Fail }"
+        );
+    }
+
+    #[test]
+    fn ok_partial_resolve() {
+        assert_eq!(
+            parse_transaction("resolve, 1, 2, 3.00"),
+            Ok(Transaction::PartialResolve(1, 2, Amount(30000)))
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_resolve_invalid_value() {
+        let res = parse_transaction("resolve,1,2,foo").unwrap_err();
+
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Resolve with an invalid amount.\", code: Fail }"
+        );
+    }
+
+    #[test]
+    fn err_extra_value() {
+        parse_transaction("withdrawal,1,2,3.0,foo").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_extra_value_message() {
+        let res = parse_transaction("withdrawal,1,2,3.0,foo").unwrap_err();
+
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Unexpected extra fields after the last column.\", code: Fail }"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "verbose-errors"))]
+    fn err_multiple_decimal_points() {
+        let res = parse_transaction("deposit,1,1,1.2.3").unwrap_err();
+
+        assert_eq!(
+            res.to_string(),
+            "Parsing Failure: Error { input: \"Multiple decimal points in amount.\", code: Char }"
+        );
+    }
+}
+
+#[cfg(test)]
+mod parse_transaction_with_options {
+    use crate::{
+        amount::Amount,
+        parse::{
+            parse_transaction_with_delimiter, parse_transaction_with_options, ParseOptions,
+            Transaction,
+        },
+    };
+
+    #[test]
+    fn ok_leading_zeros_lenient() {
+        let opts = ParseOptions::default();
+        assert_eq!(
+            parse_transaction_with_options("deposit, 007, 01, 01.0", &opts),
+            Ok(Transaction::Deposit(7, 1, Amount(10000)))
+        );
+    }
+
+    #[test]
+    fn err_leading_zeros_strict() {
+        let opts = ParseOptions::default().with_reject_leading_zeros(true);
+        for input in [
+            "deposit, 007, 1, 1.0",
+            "deposit, 1, 01, 1.0",
+            "deposit, 1, 1, 01.0",
+            "withdrawal, 1, 1, +00.5",
+            "dispute, 1, 007,",
+        ] {
+            parse_transaction_with_options(input, &opts).unwrap_err();
+        }
+
+        // A lone zero is not a leading zero
+        assert_eq!(
+            parse_transaction_with_options("deposit, 0, 0, 0.5", &opts),
+            Ok(Transaction::Deposit(0, 0, Amount(5000)))
+        );
+        assert_eq!(
+            parse_transaction_with_options("deposit, 10, 100, 10.0", &opts),
+            Ok(Transaction::Deposit(10, 100, Amount(100000)))
+        );
+    }
+
+    #[test]
+    fn ok_extra_columns() {
+        let opts = ParseOptions::default().with_allow_extra_columns(true);
+        let res = parse_transaction_with_options("deposit, 1, 2, 3.0, foo", &opts).unwrap();
+        assert_eq!(res, Transaction::Deposit(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn err_extra_columns_strict() {
+        parse_transaction_with_options("deposit, 1, 2, 3.0, foo", &ParseOptions::default())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn err_extra_columns_invalid_amount() {
+        let opts = ParseOptions::default().with_allow_extra_columns(true);
+        parse_transaction_with_options("deposit, 1, 2, 3.0 foo, bar", &opts).unwrap_err();
+    }
+
+    #[test]
+    fn ok_quoted() {
+        let opts = ParseOptions::default()
+            .with_allow_extra_columns(true)
+            .with_quoting(true);
+        let res = parse_transaction_with_options("\"deposit\", 1, 2, \"3.0\", \"a, b\"", &opts);
+        assert_eq!(res.unwrap(), Transaction::Deposit(1, 2, Amount(30000)));
+    }
+
+    #[test]
+    fn ok_decimal_separator() {
+        let opts = ParseOptions::default()
+            .with_quoting(true)
+            .with_decimal_separator(',');
+        let res = parse_transaction_with_options("deposit, 1, 2, \"1.000,50\"", &opts);
+        assert_eq!(res.unwrap(), Transaction::Deposit(1, 2, Amount(10005000)));
+
+        // The default separator is unchanged
+        let opts = ParseOptions::default().with_quoting(true);
+        parse_transaction_with_options("deposit, 1, 2, \"1.000,50\"", &opts).unwrap_err();
+    }
+
+    #[test]
+    fn ok_delimiter() {
+        assert_eq!(
+            parse_transaction_with_delimiter("deposit;1;2;3.0", ';'),
+            Ok(Transaction::Deposit(1, 2, Amount(30000)))
+        );
+        assert_eq!(
+            parse_transaction_with_delimiter("dispute\t1\t2\t", '\t'),
+            Ok(Transaction::Dispute(1, 2))
+        );
+        parse_transaction_with_delimiter("deposit, 1, 2, 3.0", ';').unwrap_err();
+
+        // European style, with a comma as the decimal separator
+        let opts = ParseOptions::default()
+            .with_delimiter(';')
+            .with_decimal_separator(',');
+        assert_eq!(
+            parse_transaction_with_options("deposit;1;1;1,5", &opts),
+            Ok(Transaction::Deposit(1, 1, Amount(15000)))
+        );
+        assert_eq!(
+            parse_transaction_with_options("withdrawal; 1; 1; 1.000,25", &opts),
+            Ok(Transaction::Withdrawal(1, 1, Amount(10002500)))
+        );
+    }
+
+    #[test]
+    fn max_decimal_places() {
+        let opts = ParseOptions::default().with_max_decimal_places(2);
+        let res = parse_transaction_with_options("deposit, 1, 2, 3.25", &opts);
+        assert_eq!(res.unwrap(), Transaction::Deposit(1, 2, Amount(32500)));
+
+        parse_transaction_with_options("deposit, 1, 2, 3.255", &opts).unwrap_err();
+    }
+
+    #[test]
+    fn err_quoted_disabled() {
+        let opts = ParseOptions::default().with_allow_extra_columns(true);
+        parse_transaction_with_options("\"deposit\", 1, 2, 3.0", &opts).unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod nom_err {
+    use super::{nom_err, nom_err_fail};
+    use nom::{
+        error::{Error as SubErr, ErrorKind},
+        Err as NomErr,
+    };
+
+    #[test]
+    fn kind() {
+        assert_eq!(
+            nom_err("foo", ErrorKind::Tag),
+            NomErr::Failure(SubErr {
+                input: "foo",
+                code: ErrorKind::Tag
+            })
+        );
+        assert_eq!(
+            nom_err_fail::<SubErr<&str>>("foo"),
+            nom_err("foo", ErrorKind::Fail)
+        );
+    }
+}
+
+#[cfg(test)]
+mod double {
+    use super::double;
+    use nom::{
+        error::{Error as SubErr, ErrorKind},
+        Err as NomErr,
+    };
+
+    #[test]
+    fn ok() {
+        assert_eq!(double("12", 1, None), Ok(("", 12)));
+        assert_eq!(double("12345", 1, Some(4)), Ok(("5", 1234)));
+        assert_eq!(double("007", 3, Some(3)), Ok(("", 7)));
+        assert_eq!(double("x", 0, None), Ok(("x", 0)));
+    }
+
+    #[test]
+    fn err_runthrough() {
+        double("", 1, None).unwrap_err();
+        double("1", 2, None).unwrap_err();
+        double("99999999999999999999", 1, None).unwrap_err();
+    }
+
+    #[test]
+    fn err_kind() {
+        let kind = |err| match err {
+            NomErr::Failure(SubErr { code, .. }) => code,
+            _ => panic!("Expected a failure"),
+        };
+
+        assert_eq!(kind(double("1", 2, None).unwrap_err()), ErrorKind::Digit);
+        assert_eq!(
+            kind(double("99999999999999999999", 1, None).unwrap_err()),
+            ErrorKind::TooLarge
+        );
+    }
+}
+
+#[cfg(test)]
+mod four_dp {
+    #[test]
+    fn ok() {
+        let value = super::four_dp("1").unwrap().1;
+        assert_eq!(value, 10000);
+    }
+
+    #[test]
+    fn ok_one_sig_fig() {
+        let value = super::four_dp("1.1").unwrap().1;
+        assert_eq!(value, 11000);
+    }
+
+    #[test]
+    fn ok_four_sig_fig() {
+        let value = super::four_dp("1.1111").unwrap().1;
+        assert_eq!(value, 11111);
+    }
+
+    #[test]
+    fn ok_four_decimal_places() {
+        assert_eq!(super::four_dp("1.0001"), Ok(("", 10001)));
+        assert_eq!(super::four_dp("1.0010"), Ok(("", 10010)));
+        assert_eq!(super::four_dp("1.0100"), Ok(("", 10100)));
+        assert_eq!(super::four_dp("1.1000"), Ok(("", 11000)));
+        assert_eq!(super::four_dp("1.1111"), Ok(("", 11111)));
+        assert_eq!(super::four_dp("1.0000"), Ok(("", 10000)));
+    }
+
+    #[test]
+    fn ok_leading_zero_decimal_places() {
+        assert_eq!(super::four_dp("0.05"), Ok(("", 500)));
+        assert_eq!(super::four_dp("0.005"), Ok(("", 50)));
+        assert_eq!(super::four_dp("2.01"), Ok(("", 20100)));
+        // A fifth decimal place is left unconsumed
+        assert_eq!(super::four_dp("1.00001"), Ok(("1", 10000)));
+    }
+
+    #[test]
+    fn err_runthrough() {
+        super::four_dp("").unwrap_err();
+        super::four_dp("1.").unwrap_err();
+    }
+
+    #[test]
+    fn ok_leading_plus() {
+        assert_eq!(super::four_dp("+5.00"), Ok(("", 50000)));
+        assert_eq!(
+            super::four_dp_with_separator("+1.000,5", ','),
+            Ok(("", 10005000))
+        );
+    }
+
+    #[test]
+    fn err_sign() {
+        super::four_dp("++5.00").unwrap_err();
+        super::four_dp("+").unwrap_err();
+        super::four_dp("-5.00").unwrap_err();
+    }
+
+    #[test]
+    fn err_multiple_decimal_points() {
+        use nom::{error::ErrorKind, Err as NomErr};
+
+        assert!(matches!(
+            super::four_dp("1.2.3"),
+            Err(NomErr::Failure(nom::error::Error {
+                code: ErrorKind::Char,
+                ..
+            }))
+        ));
+        super::four_dp_with_separator("1,2,3", ',').unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod fixed_dp {
+    use super::fixed_dp;
+
+    #[test]
+    fn ok() {
+        assert_eq!(fixed_dp("1", 0), Ok(("", 1)));
+        assert_eq!(fixed_dp("1.25", 2), Ok(("", 125)));
+        assert_eq!(fixed_dp("1.5", 4), Ok(("", 15000)));
+        assert_eq!(fixed_dp("1.12345678", 8), Ok(("", 112345678)));
+        assert_eq!(fixed_dp("1.99999999", 8), Ok(("", 199999999)));
+        assert_eq!(fixed_dp("1.05", 2), Ok(("", 105)));
+        assert_eq!(fixed_dp("0.00000001", 8), Ok(("", 1)));
+        assert_eq!(fixed_dp("1.5", 20), fixed_dp("1.5", 18));
+    }
+
+    #[test]
+    fn err_runthrough() {
+        fixed_dp("1.5", 0).unwrap_err();
+        fixed_dp("1.", 2).unwrap_err();
+        fixed_dp("1.2.3", 8).unwrap_err();
+        fixed_dp("10", 18).unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod four_dp_with_separator {
+    use super::four_dp_with_separator;
+
+    #[test]
+    fn ok_comma() {
+        assert_eq!(four_dp_with_separator("1,5", ','), Ok(("", 15000)));
+        assert_eq!(four_dp_with_separator("1.000,5", ','), Ok(("", 10005000)));
+        assert_eq!(four_dp_with_separator("1,05", ','), Ok(("", 10500)));
+        assert_eq!(
+            four_dp_with_separator("1.000.000", ','),
+            Ok(("", 10000000000))
+        );
+    }
+
+    #[test]
+    fn ok_period() {
+        // Thousands are not grouped when using the default separator
+        assert_eq!(four_dp_with_separator("1.5", '.'), Ok(("", 15000)));
+        assert_eq!(four_dp_with_separator("1,5", '.'), Ok((",5", 10000)));
+    }
+
+    #[test]
+    fn err_runthrough() {
+        four_dp_with_separator("1.00,5", ',').unwrap_err();
+        four_dp_with_separator("1,", ',').unwrap_err();
+        four_dp_with_separator("9.223.372.036.854.775.808", ',').unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod transaction {
+    use crate::amount::Amount;
+
+    #[test]
+    fn tx() {
+        assert_eq!(super::Transaction::Deposit(1, 2, Amount(3)).tx(), 2);
+        assert_eq!(super::Transaction::Withdrawal(1, 4, Amount(3)).tx(), 4);
+        assert_eq!(super::Transaction::Dispute(1, 5).tx(), 5);
+        assert_eq!(super::Transaction::Resolve(1, 6).tx(), 6);
+        assert_eq!(super::Transaction::PartialResolve(1, 7, Amount(3)).tx(), 7);
+        assert_eq!(super::Transaction::Chargeback(1, 8).tx(), 8);
+    }
+
+    #[test]
+    fn ord() {
+        use super::Transaction::*;
+
+        let mut transactions = vec![
+            Chargeback(1, 1),
+            Deposit(2, 2, Amount(5)),
+            Resolve(1, 1),
+            Dispute(1, 1),
+            Deposit(1, 2, Amount(5)),
+            PartialResolve(1, 1, Amount(2)),
+            Withdrawal(1, 1, Amount(1)),
+            Deposit(1, 1, Amount(10)),
+            Deposit(1, 1, Amount(3)),
+        ];
+        transactions.sort();
+
+        assert_eq!(
+            transactions,
+            vec![
+                Deposit(1, 1, Amount(3)),
+                Deposit(1, 1, Amount(10)),
+                Withdrawal(1, 1, Amount(1)),
+                Dispute(1, 1),
+                Resolve(1, 1),
+                PartialResolve(1, 1, Amount(2)),
+                Chargeback(1, 1),
+                Deposit(1, 2, Amount(5)),
+                Deposit(2, 2, Amount(5)),
+            ]
+        );
+        assert_eq!(
+            Dispute(1, 1).partial_cmp(&Deposit(1, 1, Amount(1))),
+            Some(std::cmp::Ordering::Greater)
+        );
+    }
+
+    #[test]
+    fn ord_btree_set() {
+        use super::Transaction::*;
+        use std::collections::BTreeSet;
+
+        let set: BTreeSet<_> = [
+            Dispute(1, 2),
+            Deposit(1, 2, Amount(5)),
+            Dispute(1, 2),
+            Deposit(1, 1, Amount(5)),
+            Deposit(1, 2, Amount(5)),
+            Deposit(1, 2, Amount(6)),
+        ]
+        .into_iter()
+        .collect();
 
-    if !input.is_empty() {
-        return Err(nom_err("Input was not empty after parsing transaction."));
+        assert_eq!(
+            set.into_iter().collect::<Vec<_>>(),
+            vec![
+                Deposit(1, 1, Amount(5)),
+                Deposit(1, 2, Amount(5)),
+                Deposit(1, 2, Amount(6)),
+                Dispute(1, 2),
+            ]
+        );
     }
 
-    Ok(())
-}
+    #[test]
+    fn client() {
+        assert_eq!(super::Transaction::Deposit(1, 2, Amount(3)).client(), 1);
+        assert_eq!(super::Transaction::Withdrawal(4, 2, Amount(3)).client(), 4);
+        assert_eq!(super::Transaction::Dispute(5, 2).client(), 5);
+        assert_eq!(super::Transaction::Resolve(6, 2).client(), 6);
+        assert_eq!(
+            super::Transaction::PartialResolve(7, 2, Amount(3)).client(),
+            7
+        );
+        assert_eq!(super::Transaction::Chargeback(8, 2).client(), 8);
+    }
 
-#[cfg(test)]
-mod parse_transaction {
-    use crate::parse::{parse_transaction, Transaction};
+    #[test]
+    fn debug() {
+        assert_eq!(
+            format!("{:?}", super::Transaction::Deposit(1, 1, Amount(2))),
+            "Deposit(1, 1, Amount(2))"
+        );
+        assert_eq!(
+            format!("{:?}", super::Transaction::Withdrawal(1, 1, Amount(2))),
+            "Withdrawal(1, 1, Amount(2))"
+        );
+        assert_eq!(
+            format!("{:?}", super::Transaction::Dispute(1, 1)),
+            "Dispute(1, 1)"
+        );
+        assert_eq!(
+            format!("{:?}", super::Transaction::Resolve(1, 1)),
+            "Resolve(1, 1)"
+        );
+        assert_eq!(
+            format!("{:?}", super::Transaction::PartialResolve(1, 1, Amount(2))),
+            "PartialResolve(1, 1, Amount(2))"
+        );
+        assert_eq!(
+            format!("{:?}", super::Transaction::Chargeback(1, 1)),
+            "Chargeback(1, 1)"
+        );
+    }
 
     #[test]
-    fn deposit() {
-        let res = parse_transaction("deposit, 1, 2, 3.1").unwrap();
-        assert_eq!(res, Transaction::Deposit(1, 2, 31000));
+    fn partial_eq() {
+        assert_eq!(
+            super::Transaction::Deposit(1, 1, Amount(20)),
+            super::Transaction::Deposit(1, 1, Amount(20))
+        );
+        assert_eq!(
+            super::Transaction::Withdrawal(1, 1, Amount(20)),
+            super::Transaction::Withdrawal(1, 1, Amount(20))
+        );
+        assert_eq!(
+            super::Transaction::Dispute(1, 1),
+            super::Transaction::Dispute(1, 1)
+        );
+        assert_eq!(
+            super::Transaction::Resolve(1, 1),
+            super::Transaction::Resolve(1, 1)
+        );
+        assert_eq!(
+            super::Transaction::Chargeback(1, 1),
+            super::Transaction::Chargeback(1, 1)
+        );
     }
+}
+
+#[cfg(test)]
+mod trim {
+    use super::{parse_header_with_options, parse_transaction_with_options, ParseOptions, Trim};
+    use crate::amount::Amount;
+    use crate::parse::Transaction;
+
+    const PADDED: &str = " deposit , 1 , 1 , 1.0 ";
 
     #[test]
-    fn withdrawal() {
-        let res = parse_transaction("withdrawal, 1, 2, 3.0").unwrap();
-        assert_eq!(res, Transaction::Withdrawal(1, 2, 30000));
+    fn both() {
+        let opts = ParseOptions::default().with_trim(Trim::Both);
+        assert_eq!(
+            parse_transaction_with_options(PADDED, &opts),
+            Ok(Transaction::Deposit(1, 1, Amount(10000)))
+        );
+        assert_eq!(opts, ParseOptions::default());
     }
 
     #[test]
-    fn dispute() {
-        let res = parse_transaction("dispute, 1, 2,").unwrap();
-        assert_eq!(res, Transaction::Dispute(1, 2));
+    fn trailing() {
+        let opts = ParseOptions::default().with_trim(Trim::Trailing);
+        parse_transaction_with_options(PADDED, &opts).unwrap_err();
+
+        // Only leading whitespace is significant
+        assert_eq!(
+            parse_transaction_with_options("deposit ,1 ,1 ,1.0 ", &opts),
+            Ok(Transaction::Deposit(1, 1, Amount(10000)))
+        );
+        parse_header_with_options("type, client, tx, amount", &opts).unwrap_err();
+        parse_header_with_options("type ,client ,tx ,amount\t", &opts).unwrap();
     }
 
     #[test]
-    fn resolve() {
-        let res = parse_transaction("resolve, 1, 2,").unwrap();
-        assert_eq!(res, Transaction::Resolve(1, 2));
+    fn none() {
+        let opts = ParseOptions::default().with_trim(Trim::None);
+        parse_transaction_with_options(PADDED, &opts).unwrap_err();
+        parse_transaction_with_options("deposit ,1,1,1.0", &opts).unwrap_err();
+
+        assert_eq!(
+            parse_transaction_with_options("deposit,1,1,1.0", &opts),
+            Ok(Transaction::Deposit(1, 1, Amount(10000)))
+        );
+        assert_eq!(
+            parse_transaction_with_options("dispute,1,1,", &opts),
+            Ok(Transaction::Dispute(1, 1))
+        );
+        parse_header_with_options("type, client, tx, amount", &opts).unwrap_err();
     }
 
     #[test]
-    fn chargeback() {
-        let res = parse_transaction("chargeback, 1, 2,").unwrap();
-        assert_eq!(res, Transaction::Chargeback(1, 2));
+    fn apply() {
+        assert_eq!(Trim::Both.apply("\t a b\r\n"), "a b");
+        assert_eq!(Trim::Trailing.apply("\t a b\r\n"), "\t a b");
+        assert_eq!(Trim::None.apply("\t a b\r\n"), "\t a b\r\n");
+
+        // Only spaces, tabs and line endings are whitespace
+        assert_eq!(Trim::Both.apply("\u{a0}a"), "\u{a0}a");
     }
+}
+
+#[cfg(test)]
+mod parse_header {
+    use crate::parse::{parse_header, parse_header_strict, ColumnLayout};
 
     #[test]
-    fn ok_no_decimal() {
-        let res = parse_transaction("deposit, 1, 2, 3").unwrap();
-        assert_eq!(res, Transaction::Deposit(1, 2, 30000));
+    fn strict() {
+        assert_eq!(parse_header_strict("type,client,tx,amount"), Ok(()));
+        assert!(parse_header_strict("type, client, amount, tx").is_err());
+        assert!(parse_header_strict("type, client, tx, amount, currency").is_err());
+        assert!(parse_header_strict("type, client, tx, amount, notes").is_err());
     }
 
     #[test]
     fn ok_no_white_space() {
-        let res = parse_transaction("deposit,1,2,3.0").unwrap();
-
-        assert_eq!(res, Transaction::Deposit(1, 2, 30000));
+        parse_header("type,client,tx,amount").expect("Error whilst parsing header.");
     }
 
     #[test]
     fn ok_with_white_space() {
-        let res = parse_transaction("       deposit   ,1  ,   2,  3.0  ").unwrap();
-        assert_eq!(res, Transaction::Deposit(1, 2, 30000));
+        parse_header("   type    ,  client,   tx  ,    amount    ")
+            .expect("Error whilst parsing header.");
     }
 
     #[test]
-    fn ok_no_amount() {
-        let res = parse_transaction("dispute,1,2,").unwrap();
-        assert_eq!(res, Transaction::Dispute(1, 2));
+    fn ok_reordered() {
+        assert_eq!(
+            parse_header("client, type, amount, tx").unwrap(),
+            ColumnLayout {
+                type_col: 1,
+                client_col: 0,
+                tx_col: 3,
+                amount_col: 2,
+                currency_col: None,
+                timestamp_col: None,
+            }
+        );
     }
 
     #[test]
-    fn err_parser_runthrough() {
-        parse_transaction("x").unwrap_err();
-        parse_transaction("deposit,x").unwrap_err();
-        parse_transaction("deposit,1,x").unwrap_err();
-        parse_transaction("deposit,1,2,x").unwrap_err();
-        parse_transaction(&format!("deposit,1,2,2{}", f32::MAX)).unwrap_err();
+    fn ok_currency() {
+        assert_eq!(
+            parse_header("type, client, tx, amount, currency").unwrap(),
+            ColumnLayout {
+                currency_col: Some(4),
+                ..ColumnLayout::default()
+            }
+        );
     }
 
     #[test]
-    fn err_invalid_u16() {
-        parse_transaction("deposit,65536,2,3.0").unwrap_err();
+    fn err_invalid_input() {
+        parse_header("client,type,ammount,tx").unwrap_err();
     }
 
     #[test]
-    fn err_invalid_deposit() {
-        parse_transaction("deposit,1,2,").unwrap_err();
+    fn err_duplicate_column() {
+        parse_header("type,client,tx,amount,tx").unwrap_err();
     }
 
     #[test]
-    fn err_dispute_missing_value() {
-        parse_transaction("dispute,1,").unwrap_err();
+    fn err_missing_value() {
+        parse_header("type,client,tx,").unwrap_err();
     }
 
     #[test]
-    fn err_withdrawal_missing_value() {
-        let res = parse_transaction("withdrawal,1,2,").unwrap_err();
-        assert_eq!(
-            res.to_string(),
-            "Parsing Failure: Error { input: \"Deposit or Withdrawal with a missing or invalid amount.\", code: Fail }"
-        );
+    fn err_parser_runthrough() {
+        parse_header("x").unwrap_err();
+        parse_header("type,x").unwrap_err();
+        parse_header("type,client,x").unwrap_err();
+        parse_header("type,client,tx,x").unwrap_err();
     }
 
     #[test]
-    fn err_deposit_missing_value() {
-        let res = parse_transaction("deposit,1,2,").unwrap_err();
-        assert_eq!(
-            res.to_string(),
-            "Parsing Failure: Error { input: \"Deposit or Withdrawal with a missing or invalid amount.\", code: Fail }"
-        );
+    fn err_extra_value() {
+        parse_header("type,client,tx,amount,foo").unwrap_err();
     }
+}
+
+#[cfg(test)]
+mod parse_transaction_with_layout {
+    use crate::{
+        amount::Amount,
+        parse::{parse_transaction_with_layout, ColumnLayout, ParseOptions, Transaction},
+    };
+
+    const LAYOUT: ColumnLayout = ColumnLayout {
+        type_col: 1,
+        client_col: 0,
+        tx_col: 3,
+        amount_col: 2,
+        currency_col: None,
+        timestamp_col: None,
+    };
 
     #[test]
-    fn err_dispute_extra_value() {
-        let res = parse_transaction("dispute,1,2,3.0").unwrap_err();
+    fn ok_reordered() {
+        let opts = ParseOptions::default();
 
         assert_eq!(
-            res.to_string(),
-            "Parsing Failure: Error { input: \"Dispute, Resolve or Chargeback with an amount.\", code: Fail }"
+            parse_transaction_with_layout("1, deposit, 3.0, 2", &LAYOUT, &opts).unwrap(),
+            Transaction::Deposit(1, 2, Amount(30000))
+        );
+        assert_eq!(
+            parse_transaction_with_layout("1, dispute, , 2", &LAYOUT, &opts).unwrap(),
+            Transaction::Dispute(1, 2)
         );
     }
 
     #[test]
-    fn err_extra_value() {
-        parse_transaction("withdrawal,1,2,3.0,foo").unwrap_err();
+    fn err_reordered() {
+        let opts = ParseOptions::default();
+
+        parse_transaction_with_layout("deposit, 1, 2, 3.0", &LAYOUT, &opts).unwrap_err();
+        parse_transaction_with_layout("1, deposit, 3.0", &LAYOUT, &opts).unwrap_err();
+        parse_transaction_with_layout("1, dispute, 3.0, 2", &LAYOUT, &opts).unwrap_err();
     }
 }
 
 #[cfg(test)]
-mod four_dp {
+mod parse_header_with_options {
+    use crate::parse::{parse_header_with_options, ParseOptions};
+
     #[test]
-    fn ok() {
-        let value = super::four_dp("1").unwrap().1;
-        assert_eq!(value, 10000);
+    fn ok_delimiter() {
+        let opts = ParseOptions::default().with_delimiter(';');
+        parse_header_with_options("type; client; tx; amount", &opts)
+            .expect("Error whilst parsing header.");
+        parse_header_with_options("type, client, tx, amount", &opts).unwrap_err();
     }
 
     #[test]
-    fn ok_one_sig_fig() {
-        let value = super::four_dp("1.1").unwrap().1;
-        assert_eq!(value, 11000);
+    fn ok_extra_columns() {
+        let opts = ParseOptions::default().with_allow_extra_columns(true);
+        parse_header_with_options("type, client, tx, amount, notes", &opts)
+            .expect("Error whilst parsing header.");
     }
 
     #[test]
-    fn ok_four_sig_fig() {
-        let value = super::four_dp("1.1111").unwrap().1;
-        assert_eq!(value, 11111);
+    fn err_extra_columns_strict() {
+        parse_header_with_options("type, client, tx, amount, notes", &ParseOptions::default())
+            .unwrap_err();
     }
 
     #[test]
-    fn err_runthrough() {
-        super::four_dp("").unwrap_err();
-        super::four_dp("1.").unwrap_err();
+    fn ok_quoted() {
+        let opts = ParseOptions::default().with_quoting(true);
+        parse_header_with_options("\"type\", client, \"tx\", amount", &opts)
+            .expect("Error whilst parsing header.");
     }
 }
 
 #[cfg(test)]
-mod transaction {
+mod split_fields {
+    use crate::parse::{fields, split_fields, split_fields_with_delimiter};
 
     #[test]
-    fn debug() {
-        assert_eq!(
-            format!("{:?}", super::Transaction::Deposit(1, 1, 2)),
-            "Deposit(1, 1, 2)"
-        );
+    fn unquoted() {
+        assert_eq!(split_fields("a, \"b,c\"", false), vec!["a", " \"b", "c\""]);
+    }
+
+    #[test]
+    fn delimiter() {
         assert_eq!(
-            format!("{:?}", super::Transaction::Withdrawal(1, 1, 2)),
-            "Withdrawal(1, 1, 2)"
+            split_fields_with_delimiter("a;b,c", ';', false),
+            vec!["a", "b,c"]
         );
         assert_eq!(
-            format!("{:?}", super::Transaction::Dispute(1, 1)),
-            "Dispute(1, 1)"
+            split_fields_with_delimiter("\"a;b\";c", ';', true),
+            vec!["a;b", "c"]
         );
         assert_eq!(
-            format!("{:?}", super::Transaction::Resolve(1, 1)),
-            "Resolve(1, 1)"
+            split_fields_with_delimiter("a€\"b€c\"", '€', true),
+            vec!["a", "b€c"]
         );
+    }
+
+    #[test]
+    fn quoted() {
+        assert_eq!(split_fields("a, \"b,c\"", true), vec!["a", "b,c"]);
+        assert_eq!(split_fields("\"a\nb\",\"\"", true), vec!["a\nb", ""]);
         assert_eq!(
-            format!("{:?}", super::Transaction::Chargeback(1, 1)),
-            "Chargeback(1, 1)"
+            split_fields("\"a \"\"b\"\"\", c", true),
+            vec!["a \"\"b\"\"", " c"]
         );
     }
 
     #[test]
-    fn partial_eq() {
+    fn iterator() {
+        let mut iter = fields("a€\"b€c\"€", '€', true);
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.next(), Some("b€c"));
+        assert_eq!(iter.next(), Some(""));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(fields("", ',', true).collect::<Vec<_>>(), vec![""]);
+        assert_eq!(fields("a,b", ',', false).nth(2), None);
+    }
+}
+
+#[cfg(test)]
+mod transaction_builder {
+    use super::{Transaction, TransactionBuilder};
+    use crate::{amount::Amount, LedgerErr};
+
+    #[test]
+    fn ok() {
         assert_eq!(
-            super::Transaction::Deposit(1, 1, 20),
-            super::Transaction::Deposit(1, 1, 20)
+            TransactionBuilder::deposit(1)
+                .with_tx(2)
+                .with_amount(3)
+                .build()
+                .unwrap(),
+            Transaction::Deposit(1, 2, Amount(3))
         );
         assert_eq!(
-            super::Transaction::Withdrawal(1, 1, 20),
-            super::Transaction::Withdrawal(1, 1, 20)
+            TransactionBuilder::withdrawal(1)
+                .with_tx(2)
+                .with_amount(3)
+                .build()
+                .unwrap(),
+            Transaction::Withdrawal(1, 2, Amount(3))
         );
         assert_eq!(
-            super::Transaction::Dispute(1, 1),
-            super::Transaction::Dispute(1, 1)
+            TransactionBuilder::dispute(1).with_tx(2).build().unwrap(),
+            Transaction::Dispute(1, 2)
         );
         assert_eq!(
-            super::Transaction::Resolve(1, 1),
-            super::Transaction::Resolve(1, 1)
+            TransactionBuilder::resolve(1).with_tx(2).build().unwrap(),
+            Transaction::Resolve(1, 2)
         );
         assert_eq!(
-            super::Transaction::Chargeback(1, 1),
-            super::Transaction::Chargeback(1, 1)
+            TransactionBuilder::resolve(1)
+                .with_tx(2)
+                .with_amount(3)
+                .build()
+                .unwrap(),
+            Transaction::PartialResolve(1, 2, Amount(3))
+        );
+        assert_eq!(
+            TransactionBuilder::chargeback(1)
+                .with_tx(2)
+                .build()
+                .unwrap(),
+            Transaction::Chargeback(1, 2)
         );
     }
+
+    #[test]
+    fn err_missing_tx() {
+        let err = TransactionBuilder::deposit(1)
+            .with_amount(3)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, LedgerErr::InvalidTransaction(_)));
+    }
+
+    #[test]
+    fn err_amount() {
+        for builder in [
+            TransactionBuilder::deposit(1).with_tx(2),
+            TransactionBuilder::withdrawal(1).with_tx(2),
+            TransactionBuilder::deposit(1).with_tx(2).with_amount(0),
+            TransactionBuilder::withdrawal(1).with_tx(2).with_amount(-1),
+            TransactionBuilder::resolve(1).with_tx(2).with_amount(0),
+            TransactionBuilder::dispute(1).with_tx(2).with_amount(1),
+            TransactionBuilder::chargeback(1).with_tx(2).with_amount(1),
+        ] {
+            let err = builder.build().unwrap_err();
+            assert!(matches!(err, LedgerErr::InvalidTransaction(_)));
+        }
+    }
 }
 
 #[cfg(test)]
-mod ws {
-    use super::*;
-    use nom::bytes::complete::tag;
+mod parse_currency {
+    use super::{
+        parse_currency, parse_header, parse_transaction_with_layout, ColumnLayout, ParseOptions,
+        Transaction,
+    };
+    use crate::amount::Amount;
 
     #[test]
-    fn ok_ws() {
-        let (input, tag) = ws(tag::<_, _, ()>("hello"))("  hello  ").unwrap();
+    fn ok() {
+        let opts = ParseOptions::default();
+        let layout = parse_header("currency, type, client, tx, amount").unwrap();
+
+        let line = " USD , deposit, 1, 2, 1.0";
+        assert_eq!(parse_currency(line, &layout, &opts), Some("USD"));
+        assert_eq!(
+            parse_transaction_with_layout(line, &layout, &opts),
+            Ok(Transaction::Deposit(1, 2, Amount(10000)))
+        );
 
-        assert_eq!(input, "");
-        assert_eq!(tag, "hello");
+        assert_eq!(parse_currency(", dispute, 1, 2,", &layout, &opts), None);
     }
 
     #[test]
-    fn invalid_inner<'a>() {
-        ws(tag("hello"))("").unwrap_err() as nom::Err<(&'a str, nom::error::ErrorKind)>;
+    fn no_column() {
+        let opts = ParseOptions::default();
+        assert_eq!(
+            parse_currency("deposit, 1, 2, 1.0", &ColumnLayout::default(), &opts),
+            None
+        );
     }
 }
 
 #[cfg(test)]
-mod parse_header {
-    use crate::parse::parse_header;
+mod transaction_parser {
+    use crate::{
+        amount::Amount,
+        parse::{transaction_parser, Transaction},
+    };
+    use nom::{multi::many0, Err as NomErr};
 
     #[test]
-    fn ok_no_white_space() {
-        parse_header("type,client,tx,amount").expect("Error whilst parsing header.");
+    fn ok_single() {
+        assert_eq!(
+            transaction_parser()("deposit, 1, 2, 3.1"),
+            Ok(("", Transaction::Deposit(1, 2, Amount(31000))))
+        );
     }
 
     #[test]
-    fn ok_with_white_space() {
-        parse_header("   type    ,  client,   tx  ,    amount    ")
-            .expect("Error whilst parsing header.");
+    fn ok_remaining_input() {
+        assert_eq!(
+            transaction_parser()("dispute, 1, 2,\r\nresolve, 1, 2,"),
+            Ok(("resolve, 1, 2,", Transaction::Dispute(1, 2)))
+        );
     }
 
     #[test]
-    fn err_invalid_input() {
-        parse_header("client,type,ammount,tx").unwrap_err();
+    fn ok_many0() {
+        let (rest, transactions) = many0(transaction_parser())(
+            "deposit, 1, 1, 1.0\nwithdrawal, 1, 2, 0.5\ndispute, 1, 1,\nchargeback, 1, 1,",
+        )
+        .unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction::Deposit(1, 1, Amount(10000)),
+                Transaction::Withdrawal(1, 2, Amount(5000)),
+                Transaction::Dispute(1, 1),
+                Transaction::Chargeback(1, 1),
+            ]
+        );
     }
 
     #[test]
-    fn err_missing_value() {
-        parse_header("type,client,tx,").unwrap_err();
+    fn ok_many0_empty() {
+        let (rest, transactions) = many0(transaction_parser())("").unwrap();
+        assert_eq!(rest, "");
+        assert!(transactions.is_empty());
     }
 
     #[test]
-    fn err_parser_runthrough() {
-        parse_header("x").unwrap_err();
-        parse_header("type,x").unwrap_err();
-        parse_header("type,client,x").unwrap_err();
-        parse_header("type,client,tx,x").unwrap_err();
+    fn err_many0_invalid_line() {
+        let res = many0(transaction_parser())("deposit, 1, 1, 1.0\ndeposit, 1, 2,\n");
+        assert!(matches!(res, Err(NomErr::Failure(_))));
     }
 
     #[test]
-    fn err_extra_value() {
-        parse_header("type,client,tx,amount,foo").unwrap_err();
+    fn err_empty() {
+        assert!(matches!(transaction_parser()(""), Err(NomErr::Error(_))));
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod timestamp {
+    use crate::{
+        amount::Amount,
+        parse::{
+            parse_header_with_options, parse_timestamp, parse_transaction_with_options, timestamp,
+            ParseOptions, Transaction,
+        },
+    };
+
+    #[test]
+    fn ok() {
+        assert_eq!(timestamp("1970-01-01T00:00:00Z"), Ok(("", 0)));
+        assert_eq!(timestamp("2023-01-01T00:00:00Z"), Ok(("", 1672531200)));
+        assert_eq!(timestamp("2000-02-29T23:59:59Z"), Ok(("", 951868799)));
+        assert_eq!(timestamp("1969-12-31T23:59:59Z"), Ok(("", -1)));
+        assert_eq!(timestamp("0000-03-01T00:00:00Z"), Ok(("", -62162035200)));
+        assert_eq!(timestamp("2023-01-01T00:00:00.999Z"), Ok(("", 1672531200)));
+        assert_eq!(timestamp("2023-01-01T00:00:00-00:30"), Ok(("", 1672533000)));
+    }
+
+    #[test]
+    fn err_invalid() {
+        for input in [
+            "",
+            "2023-01-01",
+            "2023-01-01 00:00:00Z",
+            "2023-01-01T00:00:00",
+            "2023-1-01T00:00:00Z",
+            "2023-13-01T00:00:00Z",
+            "2023-00-01T00:00:00Z",
+            "2023-04-31T00:00:00Z",
+            "1900-02-29T00:00:00Z",
+            "2023-01-01T24:00:00Z",
+            "2023-01-01T00:60:00Z",
+            "2023-01-01T00:00:60Z",
+            "2023-01-01T00:00:00.Z",
+            "2023-01-01T00:00:00+24:00",
+        ] {
+            assert!(timestamp(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn ok_transaction() {
+        let opts = ParseOptions::default().with_timestamp(true);
+        let layout = parse_header_with_options("type,client,tx,amount,timestamp", &opts).unwrap();
+        let line = "deposit,1,1,1.0,2023-01-01T00:00:00Z";
+
+        assert_eq!(
+            parse_transaction_with_options(line, &opts),
+            Ok(Transaction::Deposit(1, 1, Amount(10000)))
+        );
+        assert_eq!(parse_timestamp(line, &layout, &opts), Some(1672531200));
+
+        // An empty timestamp is no timestamp
+        assert_eq!(
+            parse_transaction_with_options("dispute,1,1,,", &opts),
+            Ok(Transaction::Dispute(1, 1))
+        );
+        assert_eq!(parse_timestamp("dispute,1,1,,", &layout, &opts), None);
+    }
+
+    #[test]
+    fn err_transaction() {
+        let opts = ParseOptions::default().with_timestamp(true);
+        parse_transaction_with_options("deposit,1,1,1.0,2023-01-01", &opts).unwrap_err();
+
+        // Without the option, the timestamp is an extra column
+        parse_transaction_with_options(
+            "deposit,1,1,1.0,2023-01-01T00:00:00Z",
+            &ParseOptions::default(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn header() {
+        let opts = ParseOptions::default().with_timestamp(true);
+        assert_eq!(
+            parse_header_with_options("timestamp, type, client, tx, amount", &opts)
+                .unwrap()
+                .timestamp_col,
+            Some(0)
+        );
+
+        // The column is required with the option, and unknown without it
+        parse_header_with_options("type, client, tx, amount", &opts).unwrap_err();
+        parse_header_with_options(
+            "type, client, tx, amount, timestamp",
+            &ParseOptions::default(),
+        )
+        .unwrap_err();
     }
 }
@@ -0,0 +1,116 @@
+#![cfg(feature = "tokio")]
+
+use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn consume_csv_async() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 2, 2, 2.0\n\
+        \n\
+        withdrawal, 1, 3, 0.5\n\
+        dispute, 2, 2,\n\
+        deposit, 1, 4, 3.25";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let mut sync_ledger = Ledger::default();
+    let sync_rows = sync_ledger
+        .consume_csv_with_options(
+            BufReader::new(File::open(&path).unwrap()),
+            &Default::default(),
+        )
+        .unwrap();
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let mut async_ledger = Ledger::default();
+    let async_rows = async_ledger
+        .consume_csv_async(tokio::io::BufReader::new(file))
+        .await
+        .unwrap();
+
+    assert_eq!(async_rows, sync_rows);
+    assert_eq!(async_ledger.clients, sync_ledger.clients);
+    assert_eq!(async_ledger.transactions, sync_ledger.transactions);
+}
+
+#[tokio::test]
+async fn err_consume_csv_async() {
+    let mut ledger = Ledger::default();
+    let err = ledger
+        .consume_csv_async("type, client, tx, amount\ndeposit, 1, 1, foo".as_bytes())
+        .await
+        .unwrap_err();
+
+    assert!(err.code().starts_with("parse"));
+}
+
+#[tokio::test]
+async fn consume_csv_async_no_header() {
+    let mut ledger = Ledger::default();
+    let rows = ledger
+        .consume_csv_async("deposit, 1, 1, 1.0\ndispute, 1, 1,".as_bytes())
+        .await
+        .unwrap();
+
+    assert_eq!(rows, 2);
+    assert_eq!(
+        ledger.to_string(),
+        "client, available, held, total, locked\n1, 0.0000, 1.0000, 1.0000, false"
+    );
+}
+
+#[tokio::test]
+async fn consume_csv_async_with_options() {
+    let csv =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 2.0\ndeposit, 1, 3, 4.0";
+    let mut ledger = Ledger::default();
+    let opts = LedgerOptions::default().with_skip_rows(1).with_max_rows(1);
+    let rows = ledger
+        .consume_csv_async_with_options(csv.as_bytes(), &opts)
+        .await
+        .unwrap();
+
+    assert_eq!(rows, 1);
+    assert_eq!(
+        ledger.to_string(),
+        "client, available, held, total, locked\n1, 2.0000, 0.0000, 2.0000, false"
+    );
+}
+
+#[tokio::test]
+async fn err_consume_csv_async_line_too_long() {
+    let csv = format!(
+        "type, client, tx, amount\ndeposit, 1, 1, 1.{}",
+        "0".repeat(64)
+    );
+    let mut ledger = Ledger::default();
+    let opts = LedgerOptions::default().with_max_line_length(32);
+    let err = ledger
+        .consume_csv_async_with_options(csv.as_bytes(), &opts)
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "Ledger Error 🦀 - Issue whilst reading in the csv: Line 2 exceeds the maximum length of 32 bytes"
+    );
+}
+
+#[tokio::test]
+async fn err_consume_csv_async_truncated() {
+    let mut ledger = Ledger::default();
+    let err = ledger
+        .consume_csv_async("type,client,tx,amount\ndeposit,1,1,1.".as_bytes())
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), "parse.incomplete");
+}
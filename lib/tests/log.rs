@@ -0,0 +1,75 @@
+#![cfg(feature = "log")]
+
+use csv_ledger_lib::ledger::Ledger;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    io::{BufReader, Cursor},
+    sync::Mutex,
+};
+
+/// A logger that records every message in memory.
+struct TestLogger(Mutex<Vec<(Level, String)>>);
+
+impl Log for TestLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn consume_csv_logs_operations() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let mut ledger = Ledger::default();
+    ledger
+        .consume_csv(BufReader::new(Cursor::new(
+            "type, client, tx, amount\n\
+            deposit, 1, 1, 1.0\n\
+            dispute, 2, 1,\n\
+            dispute, 1, 1,\n\
+            chargeback, 1, 1,\n\
+            deposit, 1, 2, 1.0",
+        )))
+        .unwrap();
+
+    assert_eq!(
+        *LOGGER.0.lock().unwrap(),
+        vec![
+            (
+                Level::Debug,
+                "Inserted transaction 1 of 1.0000 for client 1".to_string()
+            ),
+            (
+                Level::Warn,
+                "Line 3: Transaction 1 belongs to client 1, not client 2".to_string()
+            ),
+            (
+                Level::Warn,
+                "Skipped dispute of transaction 1 for client 2".to_string()
+            ),
+            (Level::Debug, "Held transaction 1 for client 1".to_string()),
+            (
+                Level::Debug,
+                "Charged back transaction 1 for client 1".to_string()
+            ),
+            (
+                Level::Warn,
+                "Skipped transaction 2 for client 1: the account is locked or would overflow"
+                    .to_string()
+            ),
+            (Level::Info, "Consumed 5 transactions from csv".to_string()),
+        ]
+    );
+}
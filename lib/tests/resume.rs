@@ -0,0 +1,55 @@
+use csv_ledger_lib::ledger::{Ledger, LedgerOptions};
+use std::io::{BufReader, Cursor};
+
+const INPUT: &str = "type, client, tx, amount\n\
+    deposit, 1, 1, 1.0\n\
+    deposit, 2, 2, 2.0\n\
+    withdrawal, 1, 3, 0.5\n\
+    dispute, 2, 2,\n\
+    \n\
+    deposit, 1, 4, 3.25\n\
+    resolve, 2, 2,\n\
+    withdrawal, 2, 5, 1.5\n\
+    dispute, 1, 4,";
+
+#[test]
+fn resume_from_row() {
+    let mut full = Ledger::default();
+    full.consume_csv(BufReader::new(Cursor::new(INPUT)))
+        .unwrap();
+
+    // Process the first half of the file, and record the client states
+    let mut ledger = Ledger::default();
+    let first = ledger
+        .consume_csv_with_options(
+            BufReader::new(Cursor::new(INPUT)),
+            &LedgerOptions::default().with_max_rows(4),
+        )
+        .unwrap();
+    assert_eq!(first, 4);
+    assert_eq!(
+        ledger.to_string_filtered(&[1, 2]).unwrap(),
+        "client, available, held, total, locked\n\
+        1, 0.5000, 0.0000, 0.5000, false\n\
+        2, 0.0000, 2.0000, 2.0000, false"
+    );
+
+    // Resume with the second half of the file
+    let second = ledger
+        .consume_csv_with_options(
+            BufReader::new(Cursor::new(INPUT)),
+            &LedgerOptions::default().with_skip_rows(first),
+        )
+        .unwrap();
+    assert_eq!(second, 4);
+    assert_eq!(
+        ledger.to_string_filtered(&[1, 2]).unwrap(),
+        full.to_string_filtered(&[1, 2]).unwrap()
+    );
+    assert_eq!(
+        ledger.to_string_filtered(&[1, 2]).unwrap(),
+        "client, available, held, total, locked\n\
+        1, 0.5000, 3.2500, 3.7500, false\n\
+        2, 0.5000, 0.0000, 0.5000, false"
+    );
+}
@@ -0,0 +1,13 @@
+//! Compile-time checks that the public types can be shared across threads and async tasks.
+
+use csv_ledger_lib::{
+    ledger::{ClientData, Ledger},
+    parse::Transaction,
+    LedgerErr,
+};
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(Ledger: Send, Sync);
+assert_impl_all!(ClientData: Send, Sync);
+assert_impl_all!(Transaction: Send, Sync);
+assert_impl_all!(LedgerErr: Send, Sync);
@@ -0,0 +1,45 @@
+use csv_ledger_lib::{ledger::Ledger, LedgerErr};
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+#[test]
+fn try_from_path() {
+    let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+    write!(
+        file,
+        "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 2, 2, 2.0\n\
+        withdrawal, 1, 3, 0.5\n\
+        dispute, 2, 2,"
+    )
+    .expect("Failed to write temporary file");
+
+    let from_path = Ledger::try_from(file.path()).unwrap();
+    let from_path_buf = Ledger::try_from(file.path().to_path_buf()).unwrap();
+
+    assert_eq!(from_path.transactions.len(), 3);
+    assert_eq!(
+        from_path.to_string_filtered(&[1, 2]).unwrap(),
+        "client, available, held, total, locked\n\
+        1, 0.5000, 0.0000, 0.5000, false\n\
+        2, 0.0000, 2.0000, 2.0000, false"
+    );
+    assert_eq!(
+        from_path.to_string_filtered(&[1, 2]).unwrap(),
+        from_path_buf.to_string_filtered(&[1, 2]).unwrap()
+    );
+}
+
+#[test]
+fn err_try_from_path() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    assert!(matches!(
+        Ledger::try_from(dir.path().join("missing.csv")),
+        Err(LedgerErr::Opening(_))
+    ));
+
+    let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+    write!(file, "type, client, tx\ndeposit, 1, 1, 1.0").unwrap();
+    assert!(Ledger::try_from(file.path()).is_err());
+}
@@ -1,122 +1,702 @@
-use clap::Parser;
-use csv_ledger_lib::{ledger::Ledger, LedgerErr};
+use clap::{Parser, Subcommand};
+use csv_ledger_lib::{
+    ledger::{validate_header, Ledger, LedgerOptions, OutputFormat, OutputOptions, SortBy},
+    LedgerErr,
+};
 
 use std::{
     env,
+    ffi::OsString,
     fs::{self, File},
-    io::BufReader,
-    path::PathBuf,
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
     process::ExitCode,
+    str::FromStr,
 };
+
+/// The formats that errors can be logged as.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format \"{s}\", expected text or json")),
+        }
+    }
+}
+
+/// The first two bytes of a gzip stream.
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The names of the subcommands, used to detect when the default subcommand has been omitted.
+const COMMANDS: [&str; 4] = ["process", "validate", "lint", "help"];
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
-struct Args {
-    /// The path to the input CSV File.
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Consume the transactions and output the account statements. This is the default when no
+    /// subcommand is given.
+    Process(ProcessArgs),
+
+    /// Only check that the header of the csv file is valid, without reading the rest of the file.
+    Validate(CommonArgs),
+
+    /// Apply every transaction without writing any output, reporting the first invalid row or
+    /// operation, such as a chargeback of a transaction that is not disputed.
+    Lint(CommonArgs),
+}
+
+/// The arguments shared by every subcommand.
+#[derive(clap::Args, Debug)]
+struct CommonArgs {
+    /// The path to the input CSV File. With the `json-input` feature, a file with a `.json`
+    /// extension is read as a JSON array of transactions.
     path: PathBuf,
 
+    #[clap(long = "allow-extra-columns")]
+    /// Tolerate and ignore any unknown columns. By default, extra columns are rejected.
+    allow_extra_columns: bool,
+
+    #[clap(long = "with-timestamp")]
+    /// Read an ISO-8601 timestamp column, such as `2023-01-01T00:00:00Z`.
+    /// Files without a header have it as a fifth column.
+    with_timestamp: bool,
+
+    #[clap(
+        long = "precision",
+        env = "CSV_LEDGER_PRECISION",
+        default_value = "4",
+        value_parser = clap::value_parser!(u32).range(0..=4)
+    )]
+    /// The number of decimal places accepted in amounts and written to the output.
+    precision: u32,
+
+    #[clap(short = 'q', long = "quiet")]
+    /// Print errors in a plain, machine-readable form.
+    quiet: bool,
+
+    #[clap(long = "log-format", default_value = "text", value_name = "text|json")]
+    /// The format of errors written to stderr. JSON errors are written as a single line.
+    log_format: LogFormat,
+}
+
+/// The arguments of the `process` subcommand.
+#[derive(clap::Args, Debug)]
+struct ProcessArgs {
+    #[clap(flatten)]
+    common: CommonArgs,
+
     #[clap(short = 'o', long = "output")]
     /// A path to save the output a a file. By default, the output will be printed to stdout.
     output: Option<PathBuf>,
+
+    #[clap(long = "disputes-output")]
+    /// A path to save all currently open disputes as a csv file.
+    disputes_output: Option<PathBuf>,
+
+    #[clap(long = "filter-client", value_name = "ID")]
+    /// Only output the given client. May be provided multiple times to output several clients.
+    filter_client: Vec<u16>,
+
+    #[clap(long = "omit-header")]
+    /// Skip writing the header row of the output.
+    omit_header: bool,
+
+    #[clap(long = "with-meta")]
+    /// Prefix csv output with a comment line counting the clients and transactions.
+    with_meta: bool,
+
+    #[clap(long = "format", default_value = "csv", value_name = "csv|json")]
+    /// The format of the output.
+    format: OutputFormat,
+
+    #[clap(long = "sort-by", value_name = "total|client|available")]
+    /// The order to output clients in. Balances are sorted largest first.
+    sort_by: Option<SortBy>,
+
+    #[clap(
+        long = "from-line",
+        default_value = "1",
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    /// Resume processing from the Nth transaction row, skipping the rows before it.
+    /// The header and blank lines are not counted.
+    from_line: u64,
+
+    #[clap(long = "validate-header-only")]
+    /// Only check that the header of the csv file is valid. Equivalent to the `validate`
+    /// subcommand.
+    validate_header_only: bool,
+
+    #[clap(short = 'v', long = "verbose")]
+    /// Log each applied transaction to stderr.
+    verbose: bool,
+
+    #[clap(long = "stats")]
+    /// Print an audit report of every client, including dispute and chargeback counts, to stderr.
+    stats: bool,
 }
 
-impl Args {
+impl Cli {
     /// Parse cli args or read mocked test enviroment variables.
     /// Whilst this method is ugly, it allows for higher code coverage than using `try_parse` alone.
-    fn parse_input() -> Result<Args, clap::Error> {
+    fn parse_input() -> Result<Cli, clap::Error> {
         if cfg!(feature = "test_args") && env::var("CSV_LEDGER_TEST_ARGS").is_ok() {
             match env::var("CSV_LEDGER_PATH") {
-                Ok(p) => Ok(Args {
-                    path: p.into(),
-                    output: env::var("CSV_LEDGER_OUTPUT").ok().map(|s| s.into()),
-                }),
+                Ok(p) => {
+                    let common = CommonArgs {
+                        path: p.into(),
+                        allow_extra_columns: env::var("CSV_LEDGER_ALLOW_EXTRA_COLUMNS").is_ok(),
+                        with_timestamp: env::var("CSV_LEDGER_WITH_TIMESTAMP").is_ok(),
+                        precision: env::var("CSV_LEDGER_PRECISION")
+                            .ok()
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(4),
+                        quiet: env::var("CSV_LEDGER_QUIET").is_ok(),
+                        log_format: env::var("CSV_LEDGER_LOG_FORMAT")
+                            .ok()
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or_default(),
+                    };
+
+                    let command = match env::var("CSV_LEDGER_COMMAND").as_deref() {
+                        Ok("validate") => Command::Validate(common),
+                        Ok("lint") => Command::Lint(common),
+                        _ => Command::Process(ProcessArgs {
+                            common,
+                            output: env::var("CSV_LEDGER_OUTPUT").ok().map(|s| s.into()),
+                            disputes_output: env::var("CSV_LEDGER_DISPUTES_OUTPUT")
+                                .ok()
+                                .map(|s| s.into()),
+                            filter_client: env::var("CSV_LEDGER_FILTER_CLIENT")
+                                .map(|s| s.split(',').filter_map(|id| id.parse().ok()).collect())
+                                .unwrap_or_default(),
+                            omit_header: env::var("CSV_LEDGER_OMIT_HEADER").is_ok(),
+                            with_meta: env::var("CSV_LEDGER_WITH_META").is_ok(),
+                            format: env::var("CSV_LEDGER_FORMAT")
+                                .ok()
+                                .and_then(|s| s.parse().ok())
+                                .unwrap_or_default(),
+                            sort_by: env::var("CSV_LEDGER_SORT_BY")
+                                .ok()
+                                .and_then(|s| s.parse().ok()),
+                            from_line: env::var("CSV_LEDGER_FROM_LINE")
+                                .ok()
+                                .and_then(|s| s.parse().ok())
+                                .unwrap_or(1),
+                            validate_header_only: env::var("CSV_LEDGER_VALIDATE_HEADER_ONLY")
+                                .is_ok(),
+                            verbose: env::var("CSV_LEDGER_VERBOSE").is_ok(),
+                            stats: env::var("CSV_LEDGER_STATS").is_ok(),
+                        }),
+                    };
+
+                    Ok(Cli { command })
+                }
                 Err(_) => Err(clap::Error::with_description(
                     "CSV_LEDGER_PATH environment variable not set.".to_string(),
                     clap::ErrorKind::MissingRequiredArgument,
                 )),
             }
         } else {
-            Args::try_parse()
+            Cli::try_parse_from(with_default_command(env::args_os()))
+        }
+    }
+}
+
+/// Insert the `process` subcommand when none is given, so that `csv_ledger foo.csv` continues to
+/// work. Top-level help and version flags are left untouched.
+fn with_default_command<I, T>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let explicit = args.get(1).is_some_and(|arg| {
+        let arg = arg.to_string_lossy();
+        COMMANDS.contains(&arg.as_ref())
+            || ["-h", "--help", "-V", "--version"].contains(&arg.as_ref())
+    });
+
+    if !explicit {
+        args.insert(args.len().min(1), "process".into());
+    }
+
+    args
+}
+
+impl Command {
+    /// The arguments shared by every subcommand.
+    fn common(&self) -> &CommonArgs {
+        match self {
+            Command::Process(args) => &args.common,
+            Command::Validate(common) | Command::Lint(common) => common,
+        }
+    }
+}
+
+impl CommonArgs {
+    /// Construct the options used to consume the csv file.
+    fn ledger_options(&self) -> LedgerOptions {
+        LedgerOptions::default()
+            .with_allow_extra_columns(self.allow_extra_columns)
+            .with_timestamp(self.with_timestamp)
+            .with_max_decimal_places(self.precision as usize)
+    }
+}
+
+impl ProcessArgs {
+    /// Construct the options used to consume the csv file.
+    fn ledger_options(&self) -> LedgerOptions {
+        self.common
+            .ledger_options()
+            .with_skip_rows(self.from_line.saturating_sub(1) as usize)
+    }
+
+    /// Construct the options used to output the ledger.
+    fn output_options(&self) -> OutputOptions {
+        let opts = OutputOptions::default()
+            .with_filter_clients(self.filter_client.clone())
+            .with_omit_header(self.omit_header)
+            .with_meta(self.with_meta)
+            .with_format(self.format)
+            .with_precision(self.common.precision);
+
+        match self.sort_by {
+            Some(sort_by) => opts.with_sort_by(sort_by),
+            None => opts,
         }
     }
 }
 
 fn main() -> ExitCode {
-    let args = match Args::parse_input() {
-        Ok(args) => args,
+    let cli = match Cli::parse_input() {
+        Ok(cli) => cli,
         Err(err) => {
             eprintln!("{err}");
             return ExitCode::FAILURE;
         }
     };
 
-    if let Err(err) = perform_parse_and_output(args.path, args.output) {
-        eprintln!("{err}");
+    let result = match cli.command {
+        Command::Process(ref args) if args.validate_header_only => {
+            perform_header_check(&args.common.path, &args.ledger_options())
+        }
+        Command::Process(ref args) => perform_process(args),
+        Command::Validate(ref common) => {
+            perform_header_check(&common.path, &common.ledger_options())
+        }
+        Command::Lint(ref common) => perform_lint(&common.path, &common.ledger_options()),
+    };
+
+    if let Err(err) = result {
+        let common = cli.command.common();
+        if common.log_format == LogFormat::Json {
+            eprintln!("{}", err.to_json());
+        } else if common.quiet {
+            eprintln!("{}", err.to_plain());
+        } else {
+            eprintln!("{err}");
+        }
         return ExitCode::FAILURE;
     }
 
     ExitCode::SUCCESS
 }
 
-#[inline]
-/// Run the main functionality of the CLI.
-pub fn perform_parse_and_output(path: PathBuf, output: Option<PathBuf>) -> Result<(), LedgerErr> {
-    // Open the csv file
-    let file = File::open(path).map_err(LedgerErr::Opening)?;
+/// Run the `process` subcommand, writing the account statements to the output file or stdout.
+fn perform_process(args: &ProcessArgs) -> Result<(), LedgerErr> {
+    let opts = args.ledger_options();
+    let output_opts = args.output_options();
+    let path = args.common.path.clone();
+    let disputes_output = args.disputes_output.clone();
+    let mut stderr = io::stderr();
+    let mut log = args.verbose.then_some(&mut stderr as &mut dyn Write);
+    let mut stats_stderr = io::stderr();
+    let mut stats = args.stats.then_some(&mut stats_stderr as &mut dyn Write);
+
+    match &args.output {
+        Some(output_path) => File::create(output_path)
+            .map_err(LedgerErr::Saving)
+            .and_then(|mut file| {
+                perform_parse_and_output(
+                    path,
+                    &mut file,
+                    disputes_output,
+                    &opts,
+                    &output_opts,
+                    log,
+                    stats,
+                )
+            }),
+        None => {
+            let mut stdout = io::stdout().lock();
+            perform_parse_and_output(
+                path,
+                &mut stdout,
+                disputes_output,
+                &opts,
+                &output_opts,
+                log.take(),
+                stats.take(),
+            )
+            .and_then(|_| writeln!(stdout).map_err(LedgerErr::Saving))
+        }
+    }
+}
 
-    // Create a new ledger and consume the csv file
+#[inline]
+/// Run the main functionality of the CLI, writing the account statements to `output`.
+pub fn perform_parse_and_output(
+    path: PathBuf,
+    output: &mut dyn Write,
+    disputes_output: Option<PathBuf>,
+    opts: &LedgerOptions,
+    output_opts: &OutputOptions,
+    log: Option<&mut dyn Write>,
+    stats: Option<&mut dyn Write>,
+) -> Result<(), LedgerErr> {
+    // Create a new ledger and consume the input file
     let mut ledger = Ledger::default();
-    ledger.consume_csv(BufReader::new(file))?;
+    consume_file(&mut ledger, &path, opts, log)?;
 
     // Output the result
-    if let Some(output_path) = output {
-        fs::write(output_path, ledger.to_string()).map_err(LedgerErr::Saving)?;
-    } else {
-        println!("{}", ledger);
+    ledger.to_writer(output, output_opts)?;
+
+    // Output the open disputes
+    if let Some(disputes_path) = disputes_output {
+        fs::write(disputes_path, ledger.disputes_csv()).map_err(LedgerErr::Saving)?;
+    }
+
+    // Output the audit report
+    if let Some(stats) = stats {
+        writeln!(stats, "{}", ledger.generate_audit_report()).map_err(LedgerErr::Saving)?;
     }
 
     Ok(())
 }
 
+#[inline]
+/// Check that the header of the csv file is valid, reading only the first line.
+/// A JSON file has no header, so every transaction in it is checked instead.
+pub fn perform_header_check(path: &Path, opts: &LedgerOptions) -> Result<(), LedgerErr> {
+    if is_json(path) {
+        return consume_file(&mut Ledger::default(), path, opts, None).map(|_| ());
+    }
+
+    let file = File::open(path).map_err(LedgerErr::Opening)?;
+    let file = decompress(file).map_err(LedgerErr::Reading)?;
+    validate_header(&mut BufReader::new(file), &opts.parse).map(|_| ())
+}
+
+#[inline]
+/// Apply every transaction of the input file in strict mode, discarding the resulting ledger.
+pub fn perform_lint(path: &Path, opts: &LedgerOptions) -> Result<(), LedgerErr> {
+    let opts = opts.clone().with_strict(true);
+    consume_file(&mut Ledger::default(), path, &opts, None).map(|_| ())
+}
+
+/// Test if the input file is read as JSON, which it is given a `.json` extension with the
+/// `json-input` feature.
+fn is_json(path: &Path) -> bool {
+    cfg!(feature = "json-input")
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+}
+
+/// Consume the input file into the ledger, decompressing it if it is gzipped, and reading it as
+/// JSON or csv depending on its extension. Returns the number of transactions processed.
+fn consume_file(
+    ledger: &mut Ledger,
+    path: &Path,
+    opts: &LedgerOptions,
+    log: Option<&mut dyn Write>,
+) -> Result<usize, LedgerErr> {
+    let file = File::open(path).map_err(LedgerErr::Opening)?;
+    let file = BufReader::new(decompress(file).map_err(LedgerErr::Reading)?);
+
+    #[cfg(feature = "json-input")]
+    if is_json(path) {
+        return ledger.consume_json_with_log(file, opts, log);
+    }
+    ledger.consume_csv_with_log(file, opts, log)
+}
+
+/// Wrap the input in a gzip decoder if it starts with the gzip magic bytes, so that compressed
+/// input is detected regardless of its file extension. Without the `gzip` feature, the input is
+/// always read as is.
+pub fn decompress<'a, R>(reader: R) -> io::Result<Box<dyn Read + 'a>>
+where
+    R: Read + 'a,
+{
+    #[cfg_attr(not(feature = "gzip"), allow(unused_mut))]
+    let mut reader = BufReader::new(reader);
+
+    #[cfg(feature = "gzip")]
+    if io::BufRead::fill_buf(&mut reader)?.starts_with(&GZIP_MAGIC) {
+        return Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader)));
+    }
+
+    Ok(Box::new(reader))
+}
+
+#[cfg(test)]
+mod decompress {
+    use std::io::{Cursor, Read};
+
+    /// Read the whole of a decompressed input as a string.
+    fn read(input: Vec<u8>) -> String {
+        let mut output = String::new();
+        super::decompress(Cursor::new(input))
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn ok_plain() {
+        assert_eq!(
+            read(b"type, client, tx, amount".to_vec()),
+            "type, client, tx, amount"
+        );
+        assert_eq!(read(Vec::new()), "");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn ok_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"type, client, tx, amount").unwrap();
+
+        assert_eq!(read(encoder.finish().unwrap()), "type, client, tx, amount");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn err_corrupt_gzip() {
+        let mut output = String::new();
+        let result = super::decompress(Cursor::new(vec![0x1f, 0x8b, 0, 0]))
+            .unwrap()
+            .read_to_string(&mut output);
+
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod perform_header_check {
+    use csv_ledger_lib::{ledger::LedgerOptions, LedgerErr};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn ok() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        // Rows after the header are never read
+        fs::write(
+            &path,
+            "type, client, tx, amount
+foo, bar",
+        )
+        .expect("Unable to write file");
+
+        super::perform_header_check(&path, &LedgerOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn err_header() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(
+            &path,
+            "type, client, tx
+deposit, 1, 1",
+        )
+        .expect("Unable to write file");
+
+        let result = super::perform_header_check(&path, &LedgerOptions::default());
+        assert!(matches!(result, Err(LedgerErr::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn err_read_file() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("foo/test.csv");
+
+        let result = super::perform_header_check(&path, &LedgerOptions::default());
+        assert!(matches!(result, Err(LedgerErr::Opening(_))));
+    }
+}
+
+#[cfg(test)]
+mod perform_lint {
+    use csv_ledger_lib::{ledger::LedgerOptions, LedgerErr};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn ok() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(&path, "type, client, tx, amount\ndeposit, 1, 1, 1.0")
+            .expect("Unable to write file");
+
+        super::perform_lint(&path, &LedgerOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn err_invalid_operation() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(
+            &path,
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\nchargeback, 1, 1,",
+        )
+        .expect("Unable to write file");
+
+        let result = super::perform_lint(&path, &LedgerOptions::default());
+        assert!(matches!(result, Err(LedgerErr::InvalidOperation(_, 3))));
+    }
+
+    #[test]
+    fn err_read_file() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("foo/test.csv");
+
+        let result = super::perform_lint(&path, &LedgerOptions::default());
+        assert!(matches!(result, Err(LedgerErr::Opening(_))));
+    }
+}
+
 #[cfg(test)]
 mod perform_parse_and_output {
-    use std::{fs, path::Path};
+    use csv_ledger_lib::{
+        ledger::{LedgerOptions, OutputOptions},
+        LedgerErr,
+    };
+    use std::{fs, io};
     use tempfile::tempdir;
 
     #[test]
-    fn ok_stdout() {
+    fn ok_output() {
         let dir = tempdir().expect("Failed to create temporary directory");
         let path = dir.path().join("test.csv");
         let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
 
         fs::write(&path, input).expect("Failed to create temporary file");
 
-        let result = super::perform_parse_and_output(path.clone().into(), None);
-        assert!(result.is_ok());
+        let mut output = Vec::new();
+        super::perform_parse_and_output(
+            path,
+            &mut output,
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client, available, held, total, locked\n1, 1.0000, 0.0000, 1.0000, false"
+        );
     }
 
     #[test]
-    fn ok_output_file() {
+    fn ok_log() {
         let dir = tempdir().expect("Failed to create temporary directory");
         let path = dir.path().join("test.csv");
-        let output = dir.path().join("test_output.csv");
         let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
 
-        fs::write(&path, input).expect("Unable to write file");
+        fs::write(&path, input).expect("Failed to create temporary file");
+
+        let mut log = Vec::new();
+        super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            Some(&mut log),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "line 2: applied Deposit client=1 tx=1 amount=1.0000\n"
+        );
+    }
+
+    #[test]
+    fn ok_stats() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,";
+
+        fs::write(&path, input).expect("Failed to create temporary file");
 
-        let result =
-            super::perform_parse_and_output(path.clone().into(), Some(output.clone().into()));
+        let mut stats = Vec::new();
+        super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            Some(&mut stats),
+        )
+        .unwrap();
 
-        result.unwrap();
-        assert!(Path::new(&output).is_file());
+        assert_eq!(
+            String::from_utf8(stats).unwrap().lines().last(),
+            Some("     1 |    0.0000 | 1.0000 | 1.0000 |  false |    1.0000 |    0.0000 |        1 |           0")
+        );
     }
 
     #[test]
     fn err_read_file() {
         let dir = tempdir().expect("Failed to create temporary directory");
-        let path = dir.path().join("/foo/test.csv");
+        let path = dir.path().join("foo/test.csv");
 
-        let result = super::perform_parse_and_output(path.clone().into(), None);
-        assert!(result.is_err());
+        let result = super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        );
+        assert!(matches!(result, Err(LedgerErr::Opening(_))));
     }
 
     #[test]
@@ -127,46 +707,419 @@ mod perform_parse_and_output {
 
         fs::write(&path, input).expect("Failed to create temporary file");
 
-        let result = super::perform_parse_and_output(path.clone().into(), None);
+        let result = super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        );
         assert!(result.is_err());
     }
 
     #[test]
-    fn err_output_file() {
+    fn ok_extra_columns() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let input = "type, client, tx, amount, notes\ndeposit, 1, 1, 1.0, foo";
+
+        fs::write(&path, input).expect("Failed to create temporary file");
+
+        let opts = LedgerOptions::default().with_allow_extra_columns(true);
+        let result = super::perform_parse_and_output(
+            path.clone(),
+            &mut io::sink(),
+            None,
+            &opts,
+            &OutputOptions::default(),
+            None,
+            None,
+        );
+        assert!(result.is_ok());
+
+        let result = super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn ok_filter_single_client() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0";
+
+        fs::write(&path, input).expect("Unable to write file");
+
+        let mut output = Vec::new();
+        super::perform_parse_and_output(
+            path,
+            &mut output,
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default().with_filter_clients(vec![2]),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client, available, held, total, locked\n2, 2.0000, 0.0000, 2.0000, false"
+        );
+    }
+
+    #[test]
+    fn ok_filter_multiple_clients() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let input =
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\ndeposit, 3, 3, 3.0";
+
+        fs::write(&path, input).expect("Unable to write file");
+
+        let mut output = Vec::new();
+        super::perform_parse_and_output(
+            path,
+            &mut output,
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default().with_filter_clients(vec![1, 3]),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client, available, held, total, locked\n1, 1.0000, 0.0000, 1.0000, false\n3, 3.0000, 0.0000, 3.0000, false"
+        );
+    }
+
+    #[test]
+    fn err_filter_unknown_client() {
         let dir = tempdir().expect("Failed to create temporary directory");
         let path = dir.path().join("test.csv");
-        let output = dir.path().join("/example/test_output.csv");
         let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
 
         fs::write(&path, input).expect("Unable to write file");
 
-        let result =
-            super::perform_parse_and_output(path.clone().into(), Some(output.clone().into()));
-        assert!(result.is_err());
+        let result = super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            None,
+            &LedgerOptions::default(),
+            &OutputOptions::default().with_filter_clients(vec![2]),
+            None,
+            None,
+        );
+        assert!(matches!(result, Err(LedgerErr::ClientNotFound(2))));
+    }
+
+    #[test]
+    fn ok_disputes_output() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let disputes = dir.path().join("disputes.csv");
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,";
+
+        fs::write(&path, input).expect("Unable to write file");
+
+        super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            Some(disputes.clone()),
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(disputes).unwrap(),
+            "client, tx, amount\n1, 1, 1.0000"
+        );
+    }
+
+    #[test]
+    fn err_disputes_output() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let disputes = dir.path().join("example/disputes.csv");
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
+
+        fs::write(&path, input).expect("Unable to write file");
+
+        let result = super::perform_parse_and_output(
+            path,
+            &mut io::sink(),
+            Some(disputes),
+            &LedgerOptions::default(),
+            &OutputOptions::default(),
+            None,
+            None,
+        );
+        assert!(matches!(result, Err(LedgerErr::Saving(_))));
     }
 }
 
 #[cfg(test)]
 mod args {
-    use super::Args;
+    use super::{with_default_command, Cli, Command, CommonArgs, LogFormat, ProcessArgs};
     use clap::Parser;
+    use csv_ledger_lib::ledger::{OutputFormat, SortBy};
+
+    /// Parse the arguments of the `process` subcommand, which may be given implicitly.
+    fn process<const N: usize>(args: [&str; N]) -> Result<ProcessArgs, clap::Error> {
+        Cli::try_parse_from(with_default_command(args)).map(|cli| match cli.command {
+            Command::Process(args) => args,
+            command => panic!("Expected the process subcommand, found {command:?}"),
+        })
+    }
 
     #[test]
     fn debug() {
-        let args = Args {
-            path: "./tests/test.csv".into(),
+        let args = ProcessArgs {
+            common: CommonArgs {
+                path: "./tests/test.csv".into(),
+                allow_extra_columns: false,
+                with_timestamp: false,
+                precision: 4,
+                quiet: false,
+                log_format: LogFormat::Text,
+            },
             output: Some("./tests/test_output.csv".into()),
+            disputes_output: None,
+            filter_client: vec![1],
+            omit_header: false,
+            with_meta: false,
+            format: OutputFormat::Csv,
+            sort_by: None,
+            from_line: 1,
+            validate_header_only: false,
+            verbose: false,
+            stats: false,
         };
 
         assert_eq!(
             format!("{:?}", args),
-            "Args { path: \"./tests/test.csv\", output: Some(\"./tests/test_output.csv\") }"
+            "ProcessArgs { common: CommonArgs { path: \"./tests/test.csv\", allow_extra_columns: false, with_timestamp: false, precision: 4, quiet: false, log_format: Text }, output: Some(\"./tests/test_output.csv\"), disputes_output: None, filter_client: [1], omit_header: false, with_meta: false, format: Csv, sort_by: None, from_line: 1, validate_header_only: false, verbose: false, stats: false }"
+        );
+    }
+
+    #[test]
+    fn default_command() {
+        assert_eq!(
+            with_default_command(["csv_ledger", "foo.csv"]),
+            ["csv_ledger", "process", "foo.csv"]
+        );
+        assert_eq!(
+            with_default_command(["csv_ledger", "lint", "foo.csv"]),
+            ["csv_ledger", "lint", "foo.csv"]
+        );
+        assert_eq!(
+            with_default_command(["csv_ledger", "--help"]),
+            ["csv_ledger", "--help"]
+        );
+        assert_eq!(
+            with_default_command(["csv_ledger"]),
+            ["csv_ledger", "process"]
+        );
+    }
+
+    #[test]
+    fn parse_process() {
+        let implicit = process(["csv_ledger", "-o", "out.csv", "foo.csv"]).unwrap();
+        let explicit = process(["csv_ledger", "process", "-o", "out.csv", "foo.csv"]).unwrap();
+
+        assert_eq!(format!("{implicit:?}"), format!("{explicit:?}"));
+        assert_eq!(explicit.common.path.to_str(), Some("foo.csv"));
+        assert_eq!(explicit.output.unwrap().to_str(), Some("out.csv"));
+    }
+
+    #[test]
+    fn parse_validate() {
+        let cli = Cli::try_parse_from(with_default_command([
+            "csv_ledger",
+            "validate",
+            "--allow-extra-columns",
+            "foo.csv",
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Command::Validate(CommonArgs {
+                allow_extra_columns: true,
+                ..
+            })
+        ));
+        assert_eq!(cli.command.common().path.to_str(), Some("foo.csv"));
+
+        // Output arguments belong to the process subcommand
+        Cli::try_parse_from(["csv_ledger", "validate", "--omit-header", "foo.csv"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_lint() {
+        let cli = Cli::try_parse_from(with_default_command([
+            "csv_ledger",
+            "lint",
+            "--precision=2",
+            "-q",
+            "foo.csv",
+        ]))
+        .unwrap();
+
+        assert!(matches!(cli.command, Command::Lint(_)));
+        assert!(cli.command.common().quiet);
+        assert_eq!(
+            cli.command
+                .common()
+                .ledger_options()
+                .parse
+                .max_decimal_places,
+            2
         );
+
+        Cli::try_parse_from(["csv_ledger", "lint", "--stats", "foo.csv"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_filter_client() {
+        let args = process([
+            "csv_ledger",
+            "--filter-client",
+            "1",
+            "--filter-client",
+            "2",
+            "foo.csv",
+        ])
+        .unwrap();
+        assert_eq!(args.filter_client, vec![1, 2]);
+        assert_eq!(args.common.path.to_str(), Some("foo.csv"));
+    }
+
+    #[test]
+    fn parse_omit_header() {
+        let args = process(["csv_ledger", "--omit-header", "foo.csv"]).unwrap();
+        assert!(args.omit_header);
+        assert!(args.output_options().omit_header);
+    }
+
+    #[test]
+    fn parse_with_timestamp() {
+        let args = process(["csv_ledger", "--with-timestamp", "foo.csv"]).unwrap();
+        assert!(args.common.with_timestamp);
+        assert!(args.ledger_options().parse.timestamp);
+    }
+
+    #[test]
+    fn parse_with_meta() {
+        let args = process(["csv_ledger", "--with-meta", "foo.csv"]).unwrap();
+        assert!(args.with_meta);
+        assert!(args.output_options().meta);
+    }
+
+    #[test]
+    fn parse_from_line() {
+        let args = process(["csv_ledger", "foo.csv"]).unwrap();
+        assert_eq!(args.ledger_options().skip_rows, 0);
+
+        let args = process(["csv_ledger", "--from-line", "3", "foo.csv"]).unwrap();
+        assert_eq!(args.from_line, 3);
+        assert_eq!(args.ledger_options().skip_rows, 2);
+
+        assert!(process(["csv_ledger", "--from-line", "0", "foo.csv"]).is_err());
+    }
+
+    #[test]
+    fn parse_format() {
+        let args = process(["csv_ledger", "foo.csv"]).unwrap();
+        assert_eq!(args.format, OutputFormat::Csv);
+
+        let args = process(["csv_ledger", "--format=json", "foo.csv"]).unwrap();
+        assert_eq!(args.output_options().format, OutputFormat::Json);
+
+        process(["csv_ledger", "--format=xml", "foo.csv"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_sort_by() {
+        let args = process(["csv_ledger", "foo.csv"]).unwrap();
+        assert_eq!(args.output_options().sort_by, None);
+
+        let args = process(["csv_ledger", "--sort-by=total", "foo.csv"]).unwrap();
+        assert_eq!(args.output_options().sort_by, Some(SortBy::Total));
+
+        process(["csv_ledger", "--sort-by=held", "foo.csv"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_allow_extra_columns() {
+        let args = process(["csv_ledger", "--allow-extra-columns", "foo.csv"]).unwrap();
+        assert!(args.common.allow_extra_columns);
+        assert!(args.ledger_options().parse.allow_extra_columns);
+    }
+
+    #[test]
+    fn parse_validate_header_only() {
+        let args = process(["csv_ledger", "--validate-header-only", "foo.csv"]).unwrap();
+        assert!(args.validate_header_only);
+    }
+
+    #[test]
+    fn parse_quiet() {
+        let args = process(["csv_ledger", "-q", "foo.csv"]).unwrap();
+        assert!(args.common.quiet);
+    }
+
+    #[test]
+    fn parse_log_format() {
+        let args = process(["csv_ledger", "foo.csv"]).unwrap();
+        assert_eq!(args.common.log_format, LogFormat::Text);
+
+        let args = process(["csv_ledger", "--log-format=json", "foo.csv"]).unwrap();
+        assert_eq!(args.common.log_format, LogFormat::Json);
+
+        process(["csv_ledger", "--log-format=xml", "foo.csv"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_verbose() {
+        let args = process(["csv_ledger", "-v", "foo.csv"]).unwrap();
+        assert!(args.verbose);
+    }
+
+    #[test]
+    fn parse_stats() {
+        let args = process(["csv_ledger", "--stats", "foo.csv"]).unwrap();
+        assert!(args.stats);
+    }
+
+    #[test]
+    fn parse_precision() {
+        let args = process(["csv_ledger", "--precision=2", "foo.csv"]).unwrap();
+        assert_eq!(args.common.precision, 2);
+        assert_eq!(args.output_options().precision, Some(2));
+        assert_eq!(args.ledger_options().parse.max_decimal_places, 2);
+
+        process(["csv_ledger", "--precision=5", "foo.csv"]).unwrap_err();
     }
 
     #[test]
     fn parse_err() {
-        Args::try_parse_from(["foo.csv"]).unwrap_err();
+        process(["foo.csv"]).unwrap_err();
+        Cli::try_parse_from(["csv_ledger", "lint"]).unwrap_err();
     }
 }
 
@@ -181,6 +1134,22 @@ mod main {
         env::remove_var("CSV_LEDGER_TEST_ARGS");
         env::remove_var("CSV_LEDGER_OUTPUT");
         env::remove_var("CSV_LEDGER_PATH");
+        env::remove_var("CSV_LEDGER_ALLOW_EXTRA_COLUMNS");
+        env::remove_var("CSV_LEDGER_WITH_TIMESTAMP");
+        env::remove_var("CSV_LEDGER_FILTER_CLIENT");
+        env::remove_var("CSV_LEDGER_OMIT_HEADER");
+        env::remove_var("CSV_LEDGER_WITH_META");
+        env::remove_var("CSV_LEDGER_FORMAT");
+        env::remove_var("CSV_LEDGER_SORT_BY");
+        env::remove_var("CSV_LEDGER_FROM_LINE");
+        env::remove_var("CSV_LEDGER_DISPUTES_OUTPUT");
+        env::remove_var("CSV_LEDGER_VALIDATE_HEADER_ONLY");
+        env::remove_var("CSV_LEDGER_QUIET");
+        env::remove_var("CSV_LEDGER_VERBOSE");
+        env::remove_var("CSV_LEDGER_STATS");
+        env::remove_var("CSV_LEDGER_PRECISION");
+        env::remove_var("CSV_LEDGER_LOG_FORMAT");
+        env::remove_var("CSV_LEDGER_COMMAND");
     }
 
     #[test]
@@ -214,6 +1183,64 @@ mod main {
         main();
     }
 
+    #[test]
+    fn ok_validate_header_only() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(&path, "type, client, tx, amount").expect("Unable to write file");
+
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", path);
+        env::set_var("CSV_LEDGER_VALIDATE_HEADER_ONLY", "true");
+        main();
+    }
+
+    #[test]
+    fn ok_validate() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(&path, "type, client, tx, amount").expect("Unable to write file");
+
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", path);
+        env::set_var("CSV_LEDGER_COMMAND", "validate");
+        main();
+    }
+
+    #[test]
+    fn err_lint() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+
+        fs::write(&path, "type, client, tx, amount\nchargeback, 1, 1,")
+            .expect("Unable to write file");
+
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", path);
+        env::set_var("CSV_LEDGER_COMMAND", "lint");
+        main();
+    }
+
+    #[test]
+    fn err_output_file() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("test.csv");
+        let output = dir.path().join("example/test_output.csv");
+
+        fs::write(&path, "type, client, tx, amount").expect("Unable to write file");
+
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", path);
+        env::set_var("CSV_LEDGER_OUTPUT", output);
+        main();
+    }
+
     #[test]
     fn err_invalid_path() {
         reset_args();
@@ -223,6 +1250,26 @@ mod main {
         main();
     }
 
+    #[test]
+    fn err_quiet() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", dir.path().join("foo.csv"));
+        env::set_var("CSV_LEDGER_QUIET", "true");
+        main();
+    }
+
+    #[test]
+    fn err_log_format_json() {
+        reset_args();
+        let dir = tempdir().expect("Failed to create temporary directory");
+        env::set_var("CSV_LEDGER_TEST_ARGS", "true");
+        env::set_var("CSV_LEDGER_PATH", dir.path().join("foo.csv"));
+        env::set_var("CSV_LEDGER_LOG_FORMAT", "json");
+        main();
+    }
+
     #[test]
     fn err_missing_path() {
         reset_args();
@@ -0,0 +1,458 @@
+use std::{fs, process::Command};
+use tempfile::tempdir;
+
+#[test]
+fn omit_header() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("1, 1.0000, 0.0000, 1.0000, false"));
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn format_json() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--format=json")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        r#"[{"client":1,"available":"1.0000","held":"0.0000","total":"1.0000","locked":false}]"#
+    );
+}
+
+#[test]
+fn sort_by_total() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\n\
+        deposit, 1, 1, 2.0\n\
+        deposit, 2, 2, 5.0\n\
+        deposit, 3, 3, 1.0\n\
+        deposit, 4, 4, 3.0";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--sort-by=total")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "client, available, held, total, locked\n\
+        2, 5.0000, 0.0000, 5.0000, false\n\
+        4, 3.0000, 0.0000, 3.0000, false\n\
+        1, 2.0000, 0.0000, 2.0000, false\n\
+        3, 1.0000, 0.0000, 1.0000, false"
+    );
+}
+
+#[test]
+fn quiet() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+
+    fs::write(&path, "type, client, tx").expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--quiet")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: parsing csv: "));
+    assert!(!stderr.contains('🦀'));
+}
+
+#[test]
+fn log_format_json() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+
+    fs::write(&path, "type, client, tx").expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--log-format=json")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(r#"{"error_type": "Parse", "message": ""#));
+    assert!(stderr.ends_with("\"line\": 1}\n"));
+}
+
+#[test]
+fn precision() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.25";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .env("CSV_LEDGER_PRECISION", "2")
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 1.25, 0.00, 1.25, false"
+    );
+
+    fs::write(&path, "type, client, tx, amount\ndeposit, 1, 1, 1.255")
+        .expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .env("CSV_LEDGER_PRECISION", "2")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn verbose() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--verbose")
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 0.0000, 1.0000, 1.0000, false"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "line 2: applied Deposit client=1 tx=1 amount=1.0000\nline 3: applied Dispute client=1 tx=1\n"
+    );
+}
+
+#[test]
+fn stats() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,\nchargeback, 1, 1,";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--stats")
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 0.0000, 0.0000, 0.0000, true"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "client | available |   held |  total | locked | deposited | withdrawn | disputes | chargebacks\n\
+         -------+-----------+--------+--------+--------+-----------+-----------+----------+------------\n     \
+              1 |    0.0000 | 0.0000 | 0.0000 |   true |    1.0000 |    0.0000 |        1 |           1\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "json-input")]
+fn json_input() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.json");
+    let input = r#"[{"type": "deposit", "client": 1, "tx": 1, "amount": 1.0}]"#;
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 1.0000, 0.0000, 1.0000, false"
+    );
+}
+
+#[test]
+#[cfg(feature = "json-input")]
+fn json_input_options() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.json");
+    let input = r#"[
+        {"type": "deposit", "client": 1, "tx": 1, "amount": 1.0},
+        {"type": "deposit", "client": 1, "tx": 2, "amount": 2.5},
+        {"type": "chargeback", "client": 1, "tx": 2}
+    ]"#;
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .args(["--omit-header", "--verbose", "--from-line", "2"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 2.5000, 0.0000, 2.5000, false"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "line 2: applied Deposit client=1 tx=2 amount=2.5000\nline 3: applied Chargeback client=1 tx=2\n"
+    );
+
+    // The precision limits the decimal places accepted, as it does for csv
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .args(["--precision", "0"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+
+    // Validate reads the file as JSON, and lint rejects the chargeback without a dispute
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("validate")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .args(["lint", "--quiet"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Chargeback on a transaction that is not disputed"));
+}
+
+#[test]
+fn with_meta() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\ndeposit, 1, 3, 1.0";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--with-meta")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("# 2 clients, 3 transactions"));
+    assert_eq!(lines.next(), Some("client, available, held, total, locked"));
+}
+
+#[test]
+fn from_line() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\n\ndeposit, 2, 2, 2.0\ndeposit, 1, 3, 3.0";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .args([
+            "--from-line",
+            "2",
+            "--filter-client",
+            "1",
+            "--filter-client",
+            "2",
+        ])
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client, available, held, total, locked\n1, 3.0000, 0.0000, 3.0000, false\n2, 2.0000, 0.0000, 2.0000, false\n"
+    );
+}
+
+#[test]
+fn with_timestamp() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+    let input = "type, client, tx, amount, timestamp\ndeposit, 1, 1, 1.0, 2023-01-01T00:00:00Z";
+
+    fs::write(&path, input).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--with-timestamp")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().lines().last(),
+        Some("1, 1.0000, 0.0000, 1.0000, false")
+    );
+
+    // Without the flag, the timestamp column is unknown
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn process_subcommand() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+
+    fs::write(&path, "type, client, tx, amount\ndeposit, 1, 1, 1.0")
+        .expect("Failed to create temporary file");
+
+    let implicit = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+    let explicit = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("process")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(explicit.status.success());
+    assert_eq!(implicit.stdout, explicit.stdout);
+}
+
+#[test]
+fn validate_subcommand() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+
+    // Rows after the header are never read
+    fs::write(&path, "type, client, tx, amount\nfoo, bar")
+        .expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("validate")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn lint_subcommand() {
+    let dir = tempdir().expect("Failed to create temporary directory");
+    let path = dir.path().join("test.csv");
+
+    fs::write(
+        &path,
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\nchargeback, 1, 1,",
+    )
+    .expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .args(["lint", "--quiet"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Chargeback on a transaction that is not disputed"));
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn gzip_input() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let dir = tempdir().expect("Failed to create temporary directory");
+    // Compressed input is detected by its contents, not its extension
+    let path = dir.path().join("test.csv");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 1.0")
+        .unwrap();
+    fs::write(&path, encoder.finish().unwrap()).expect("Failed to create temporary file");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_csv_ledger"))
+        .arg("--omit-header")
+        .arg(&path)
+        .output()
+        .expect("Failed to run csv_ledger");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        "1, 1.0000, 0.0000, 1.0000, false"
+    );
+}